@@ -0,0 +1,87 @@
+// Encodes frames from the software renderer as an animated GIF or PNG (APNG)
+//
+// GIF images can only have 256 colours per frame, so each frame gets its own palette, picked to best match the
+// colours in it.  APNG keeps the full colour of each frame, but makes for larger files.
+
+// How long each frame is shown for, in milliseconds
+pub(crate) const FRAME_DELAY_MS: u16 = 40;
+
+// How closely GIF palettes match the colours in each frame, from 1 (best, but slowest) to 30
+const GIF_QUANTIZE_SPEED: i32 = 10;
+
+// The supported animated image formats
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Format {
+    Gif,
+    Apng,
+}
+
+impl Format {
+    pub(crate) fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "gif" => Some(Format::Gif),
+            "apng" | "png" => Some(Format::Apng),
+            _ => None,
+        }
+    }
+}
+
+// Encodes RGBA frames (all the same size) as an animation which loops forever.  Each frame is asked for in turn and
+// encoded straight away, so only one is kept in memory at a time
+pub(crate) fn encode(
+    format: Format,
+    width: usize,
+    height: usize,
+    frames: usize,
+    frame: impl FnMut(usize) -> Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    match format {
+        Format::Gif => encode_gif(width, height, frames, frame),
+        Format::Apng => encode_apng(width, height, frames, frame),
+    }
+}
+
+fn encode_gif(
+    width: usize,
+    height: usize,
+    frames: usize,
+    mut frame: impl FnMut(usize) -> Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(format!("GIF images can't be larger than {} pixels in each direction", u16::MAX));
+    }
+    let mut out = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut out, width as u16, height as u16, &[]).map_err(|e| e.to_string())?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| e.to_string())?;
+        for n in 0..frames {
+            let mut pixels = frame(n);
+            let mut f = gif::Frame::from_rgba_speed(width as u16, height as u16, &mut pixels, GIF_QUANTIZE_SPEED);
+            f.delay = FRAME_DELAY_MS / 10; // GIF delays are in hundredths of a second
+            encoder.write_frame(&f).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(out)
+}
+
+fn encode_apng(
+    width: usize,
+    height: usize,
+    frames: usize,
+    mut frame: impl FnMut(usize) -> Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frames as u32, 0).map_err(|e| e.to_string())?;
+        encoder.set_frame_delay(FRAME_DELAY_MS, 1000).map_err(|e| e.to_string())?;
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        for n in 0..frames {
+            writer.write_image_data(&frame(n)).map_err(|e| e.to_string())?;
+        }
+        writer.finish().map_err(|e| e.to_string())?;
+    }
+    Ok(out)
+}
@@ -0,0 +1,128 @@
+// World space X, Y, and Z axes, with tick marks and labels
+//
+// The axes are drawn through the origin of the scene, and move with it as it's transformed.  A small orientation gizmo
+// in the corner of the graph area shows the current rotation of the scene.
+
+use lazy_static::lazy_static;
+use std::sync::{Arc, Mutex};
+use crate::painter::Painter;
+use crate::{lock, transform, Matrix, Point, View};
+
+// The colours and titles of the X, Y, and Z axes
+const AXIS_COLOURS: [&str; 3] = ["#d62728", "#2ca02c", "#1f77b4"];
+const AXIS_TITLES: [&str; 3] = ["X", "Y", "Z"];
+
+// The most tick marks drawn along each half of an axis.  The tick spacing is widened to stay under this
+const MAX_TICKS: f64 = 50.0;
+
+// The length of the gizmo axis lines, in pixels
+const GIZMO_SIZE: f64 = 30.0;
+
+pub(crate) struct AxesSettings {
+    pub(crate) visible: bool,
+    pub(crate) gizmo: bool,       // Whether the orientation gizmo is shown
+    pub(crate) length: f64,       // How far each axis reaches either side of the origin, in world space units
+    pub(crate) tick_spacing: f64, // The distance between tick marks, in world space units
+}
+
+lazy_static! {
+    pub(crate) static ref AXES: Arc<Mutex<AxesSettings>> = Arc::new(Mutex::new(AxesSettings {
+        visible: false,
+        gizmo: false,
+        length: 5.0,
+        tick_spacing: 1.0,
+    }));
+}
+
+// Returns the point at the given distance along an axis
+fn along(axis: usize, dist: f64) -> Point {
+    let mut c = [0.0; 3];
+    c[axis] = dist;
+    Point {
+        num: 0,
+        x: c[0],
+        y: c[1],
+        z: c[2],
+    }
+}
+
+// Formats a tick value, without the floating point noise (eg 0.30000000000000004)
+fn tick_label(v: f64) -> String {
+    let rounded = (v * 1_000_000.0).round() / 1_000_000.0;
+    format!("{}", rounded)
+}
+
+// Draws the axes, if they're turned on
+pub(crate) fn draw(p: &mut dyn Painter, scene: &Matrix, view: &View) {
+    let settings = lock(&AXES);
+    if !settings.visible || settings.length <= 0.0 {
+        return;
+    }
+    let to_screen = |pt: Point| -> (f64, f64) { view.project(&transform(scene, pt)) };
+    let length = settings.length;
+    let spacing = settings.tick_spacing.max(length / MAX_TICKS);
+    let tick_size = spacing * 0.15;
+    let k = view.line_width; // Text and offsets are scaled along with the lines
+    let label_font = format!("{}px sans-serif", 10.0 * k);
+    let title_font = format!("bold {}px sans-serif", 12.0 * k);
+
+    for axis in 0..3 {
+        let colour = AXIS_COLOURS[axis];
+
+        // The axis line itself
+        p.line(to_screen(along(axis, -length)), to_screen(along(axis, length)), colour, k);
+
+        // Tick marks point along the Y axis, except for those on the Y axis which point along X
+        let tick_axis = if axis == 1 { 0 } else { 1 };
+        let mut n = 1.0;
+        while n * spacing <= length + (spacing * 1e-9) {
+            for dist in [-n * spacing, n * spacing].iter() {
+                let base = along(axis, *dist);
+                let mut tip = base;
+                let offset = along(tick_axis, tick_size);
+                tip.x += offset.x;
+                tip.y += offset.y;
+                tip.z += offset.z;
+                let (tx, ty) = to_screen(tip);
+                p.line(to_screen(base), (tx, ty), colour, k);
+                p.text(&tick_label(*dist), (tx + (2.0 * k), ty - (2.0 * k)), &label_font, colour);
+            }
+            n += 1.0;
+        }
+
+        // The axis title, just past its positive end
+        p.text(AXIS_TITLES[axis], to_screen(along(axis, length + spacing * 0.5)), &title_font, colour);
+    }
+}
+
+// Draws the orientation gizmo if it's turned on, in the bottom left corner of the graph area.  Only the rotation of
+// the scene is shown, so the gizmo stays the same size however much the scene is scaled
+pub(crate) fn draw_gizmo(p: &mut dyn Painter, scene: &Matrix, view: &View) {
+    if !lock(&AXES).gizmo {
+        return;
+    }
+    let k = view.line_width;
+    let size = GIZMO_SIZE * k;
+    let gx = view.left + (size * 1.5);
+    let gy = view.graph_height - (size * 1.5);
+
+    // The columns of the scene matrix are where the X, Y, and Z axes have ended up
+    let mut ends: Vec<(usize, f64, f64, f64)> = Vec::new();
+    for axis in 0..3 {
+        let (x, y, z) = (scene[axis], scene[4 + axis], scene[8 + axis]);
+        let len = (x * x + y * y + z * z).sqrt();
+        if len > 0.0 {
+            ends.push((axis, x / len, y / len, z / len));
+        }
+    }
+
+    // Draw the axes pointing away from the viewer first, so the nearer ones are drawn over them
+    ends.sort_by(|a, b| b.3.total_cmp(&a.3));
+    let font = format!("bold {}px sans-serif", 10.0 * k);
+    for (axis, x, y, _) in ends {
+        let ex = gx + (x * size);
+        let ey = gy - (y * size);
+        p.line((gx, gy), (ex, ey), AXIS_COLOURS[axis], 2.0 * k);
+        p.text(AXIS_TITLES[axis], (ex + (x * 6.0 * k) - (3.0 * k), ey - (y * 6.0 * k) + (4.0 * k)), &font, AXIS_COLOURS[axis]);
+    }
+}
@@ -0,0 +1,217 @@
+// Renders a scene file to a PNG or SVG image, without needing a browser
+//
+// Build and run with: cargo run --features cli -- [options] <scene file> <output file>
+
+use std::error::Error;
+use std::path::Path;
+use std::process::exit;
+
+const USAGE: &str = "Usage: render_scene [options] <scene file> <output file>
+
+The scene file can be JSON, OBJ, or STL.  The output file is written as PNG or SVG, depending on its extension.  A
+turntable animation is written instead for GIF or APNG output files.
+
+Options:
+  --format FORMAT   The scene file format (json, obj, or stl).  Defaults to the file's extension
+  --size WxH        The image size in pixels.  Defaults to 1024x768
+  --rotate X,Y,Z    Degrees to rotate the scene around the X, Y, and Z axes before rendering
+  --zoom AMOUNT     How much to zoom the scene in (above 1) or out (between 0 and 1)
+  --axes            Show the X, Y, and Z axes
+  --grid            Show the ground grid
+  --zbuffer         Depth test every pixel, so objects which overlap or pass through each other are drawn correctly.
+                    Only used for PNG, GIF, and APNG output
+  --shading MODE    How to light the surfaces: none (the default), flat, gouraud, or phong.  SVG output uses a
+                    single colour for each surface
+  --turntable N     Render N frames, turning the scene around the Y axis.  For PNG and SVG output, the frame number
+                    is added to the end of each file name (eg out_000.png, out_001.png, ...).  Defaults to 36 frames
+                    for GIF and APNG output
+  --degrees D       Degrees to turn between turntable frames.  Defaults to a full turn over all the frames
+  --help            Show this message";
+
+struct Options {
+    scene: String,
+    output: String,
+    format: Option<String>,
+    width: u32,
+    height: u32,
+    rotate: (f64, f64, f64),
+    zoom: f64,
+    axes: bool,
+    grid: bool,
+    zbuffer: bool,
+    shading: Option<String>,
+    frames: Option<u32>,
+    degrees: Option<f64>,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+    if let Err(e) = run(&opts) {
+        eprintln!("render_scene: {}", e);
+        exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut files = Vec::new();
+    let mut opts = Options {
+        scene: String::new(),
+        output: String::new(),
+        format: None,
+        width: 1024,
+        height: 768,
+        rotate: (0.0, 0.0, 0.0),
+        zoom: 1.0,
+        axes: false,
+        grid: false,
+        zbuffer: false,
+        shading: None,
+        frames: None,
+        degrees: None,
+    };
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| -> Result<String, String> {
+            it.next().cloned().ok_or_else(|| format!("{} needs a value", name))
+        };
+        match arg.as_str() {
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            "--format" => opts.format = Some(value(arg)?),
+            "--size" => {
+                let v = value(arg)?;
+                let (w, h) = v.split_once('x').ok_or("--size should look like 800x600")?;
+                opts.width = w.parse().map_err(|_| "--size should look like 800x600")?;
+                opts.height = h.parse().map_err(|_| "--size should look like 800x600")?;
+            }
+            "--rotate" => {
+                let v: Result<Vec<f64>, _> = value(arg)?.split(',').map(|p| p.trim().parse::<f64>()).collect();
+                match v {
+                    Ok(ref d) if d.len() == 3 && d.iter().all(|v| v.is_finite()) => opts.rotate = (d[0], d[1], d[2]),
+                    _ => return Err("--rotate should be three numbers, like 30,45,0".to_string()),
+                }
+            }
+            "--zoom" => {
+                let zoom: f64 = value(arg)?.parse().map_err(|_| "--zoom should be a number above 0")?;
+                if !(zoom.is_finite() && zoom > 0.0) {
+                    return Err("--zoom should be a number above 0".to_string());
+                }
+                opts.zoom = zoom;
+            }
+            "--axes" => opts.axes = true,
+            "--grid" => opts.grid = true,
+            "--zbuffer" => opts.zbuffer = true,
+            "--shading" => opts.shading = Some(value(arg)?),
+            "--turntable" => {
+                let n: u32 = value(arg)?.parse().map_err(|_| "--turntable should be a number of frames")?;
+                if n == 0 {
+                    return Err("--turntable needs at least one frame".to_string());
+                }
+                opts.frames = Some(n);
+            }
+            "--degrees" => {
+                let degrees: f64 = value(arg)?.parse().map_err(|_| "--degrees should be a number")?;
+                if !degrees.is_finite() {
+                    return Err("--degrees should be a number".to_string());
+                }
+                opts.degrees = Some(degrees);
+            }
+            a if a.starts_with("--") => return Err(format!("Unknown option {}", a)),
+            _ => files.push(arg.clone()),
+        }
+    }
+    if files.len() != 2 {
+        return Err("Both a scene file and an output file are needed".to_string());
+    }
+    opts.output = files.pop().unwrap();
+    opts.scene = files.pop().unwrap();
+    Ok(opts)
+}
+
+fn run(opts: &Options) -> Result<(), Box<dyn Error>> {
+    let format = match &opts.format {
+        Some(f) => f.clone(),
+        None => extension(&opts.scene).ok_or("Can't tell the scene format from its file name, so use --format")?,
+    };
+    let output_format = extension(&opts.output);
+    let svg = match output_format.as_deref() {
+        Some("png") | Some("gif") | Some("apng") => false,
+        Some("svg") => true,
+        _ => return Err("The output file name should end in .png, .svg, .gif, or .apng".into()),
+    };
+    let data = std::fs::read(&opts.scene).map_err(|e| format!("Can't read {}: {}", opts.scene, e))?;
+    let name = Path::new(&opts.scene).file_stem().and_then(|s| s.to_str()).unwrap_or("scene");
+    canvas_2d_test1::read_scene(name, &format, &data)?;
+
+    canvas_2d_test1::set_axes_visible(opts.axes);
+    canvas_2d_test1::set_ground_grid_visible(opts.grid);
+    if opts.zbuffer {
+        canvas_2d_test1::select_render_mode("zbuffer")?;
+    }
+    if let Some(mode) = &opts.shading {
+        canvas_2d_test1::select_shading(mode, None)?;
+    }
+    let (x, y, z) = opts.rotate;
+    canvas_2d_test1::turn_scene(x, y, z, opts.zoom)?;
+
+    // Animations are written as a single file
+    if let Some(anim) = output_format.as_deref().filter(|f| *f == "gif" || *f == "apng") {
+        let frames = opts.frames.unwrap_or(36);
+        let degrees = opts.degrees.unwrap_or(360.0 / frames as f64);
+        let bytes = canvas_2d_test1::render_turntable(frames, degrees, anim, opts.width, opts.height)?;
+        return write_file(&opts.output, bytes);
+    }
+
+    let frames = match opts.frames {
+        Some(n) => n,
+        None => return write_image(&opts.output, svg, opts.width, opts.height),
+    };
+    let degrees = opts.degrees.unwrap_or(360.0 / frames as f64);
+    for frame in 0..frames {
+        if frame > 0 {
+            canvas_2d_test1::turn_scene(0.0, degrees, 0.0, 1.0)?;
+        }
+        write_image(&frame_file_name(&opts.output, frame, frames), svg, opts.width, opts.height)?;
+    }
+    Ok(())
+}
+
+fn write_image(path: &str, svg: bool, width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+    let bytes = if svg {
+        canvas_2d_test1::render_svg(width, height).into_bytes()
+    } else {
+        canvas_2d_test1::render_png(width, height)?
+    };
+    write_file(path, bytes)
+}
+
+fn write_file(path: &str, bytes: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    std::fs::write(path, bytes).map_err(|e| format!("Can't write {}: {}", path, e).into())
+}
+
+// Returns the lower case extension of a file name
+fn extension(path: &str) -> Option<String> {
+    Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase())
+}
+
+// Adds the frame number to a file name, just before its extension.  The number is padded to the same width for all
+// the frames, so the files sort in order
+fn frame_file_name(path: &str, frame: u32, frames: u32) -> String {
+    let digits = (frames - 1).to_string().len().max(3);
+    let p = Path::new(path);
+    let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+    let ext = p.extension().and_then(|e| e.to_str()).unwrap_or("");
+    p.with_file_name(format!("{}_{:0width$}.{}", stem, frame, ext, width = digits))
+        .to_string_lossy()
+        .into_owned()
+}
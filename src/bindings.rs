@@ -0,0 +1,369 @@
+// Key bindings: which keys start which actions
+//
+// Keys are written as their name, after any of the Ctrl, Alt, and Shift modifiers, eg "Ctrl+Shift+Z".  The names are
+// the ones browsers use (https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/key/Key_Values), plus "Space"
+// and "Plus".  Ctrl covers the Cmd key on Macs too.  As Shift is often needed just to type a key (eg +), a binding
+// without Shift also works with Shift held, unless the key has a Shift binding of its own.
+//
+// The bindings are saved as a JSON object mapping each key to the name of its action, eg {"w": "move_up"}.
+
+use lazy_static::lazy_static;
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex};
+
+use crate::{lock, Error, KeyVal};
+
+// The actions keys can be bound to, with their names in JSON and in the help text.  The help lists them in this order
+const ACTIONS: [(KeyVal, &str, &str); 19] = [
+    (KeyVal::KeyMoveUp, "move_up", "Move up"),
+    (KeyVal::KeyMoveDown, "move_down", "Move down"),
+    (KeyVal::KeyMoveLeft, "move_left", "Move left"),
+    (KeyVal::KeyMoveRight, "move_right", "Move right"),
+    (KeyVal::KeyRotateUp, "rotate_up", "Rotate up"),
+    (KeyVal::KeyRotateDown, "rotate_down", "Rotate down"),
+    (KeyVal::KeyRotateLeft, "rotate_left", "Rotate left"),
+    (KeyVal::KeyRotateRight, "rotate_right", "Rotate right"),
+    (KeyVal::KeyHome, "rotate_up_left", "Rotate up & left"),
+    (KeyVal::KeyPageUp, "rotate_up_right", "Rotate up & right"),
+    (KeyVal::KeyEnd, "rotate_down_left", "Rotate down & left"),
+    (KeyVal::KeyPageDown, "rotate_down_right", "Rotate down & right"),
+    (KeyVal::KeyPlus, "speed_up", "Speed up"),
+    (KeyVal::KeyMinus, "slow_down", "Slow down"),
+    (KeyVal::KeyStop, "stop", "Stop"),
+    (KeyVal::KeyFitView, "fit_view", "Fit to view"),
+    (KeyVal::KeyResetView, "reset_view", "Reset view"),
+    (KeyVal::KeyUndo, "undo", "Undo"),
+    (KeyVal::KeyRedo, "redo", "Redo"),
+];
+
+// The names of the keys which aren't a single character, with how they're shown in the help text.  Function keys
+// (F1 to F24) are understood too
+const NAMED_KEYS: [(&str, &str); 15] = [
+    ("ArrowUp", "Up"),
+    ("ArrowDown", "Down"),
+    ("ArrowLeft", "Left"),
+    ("ArrowRight", "Right"),
+    ("PageUp", "PgUp"),
+    ("PageDown", "PgDn"),
+    ("Home", "Home"),
+    ("End", "End"),
+    ("Insert", "Ins"),
+    ("Delete", "Del"),
+    ("Backspace", "Backspace"),
+    ("Enter", "Enter"),
+    ("Tab", "Tab"),
+    ("Escape", "Esc"),
+    (" ", "Space"),
+];
+
+// The bindings used until others are set.  WASD moves, the arrows and numpad rotate, and + and - change the speed
+const DEFAULT_BINDINGS: [(&str, &str); 28] = [
+    ("w", "move_up"),
+    ("s", "move_down"),
+    ("a", "move_left"),
+    ("d", "move_right"),
+    ("ArrowUp", "rotate_up"),
+    ("8", "rotate_up"),
+    ("ArrowDown", "rotate_down"),
+    ("2", "rotate_down"),
+    ("ArrowLeft", "rotate_left"),
+    ("4", "rotate_left"),
+    ("ArrowRight", "rotate_right"),
+    ("6", "rotate_right"),
+    ("Home", "rotate_up_left"),
+    ("7", "rotate_up_left"),
+    ("PageUp", "rotate_up_right"),
+    ("9", "rotate_up_right"),
+    ("End", "rotate_down_left"),
+    ("1", "rotate_down_left"),
+    ("PageDown", "rotate_down_right"),
+    ("3", "rotate_down_right"),
+    ("+", "speed_up"),
+    ("-", "slow_down"),
+    ("Escape", "stop"),
+    ("f", "fit_view"),
+    ("r", "reset_view"),
+    ("Ctrl+Z", "undo"),
+    ("Ctrl+Shift+Z", "redo"),
+    ("Ctrl+Y", "redo"),
+];
+
+// A key, along with the modifiers held down with it
+#[derive(Clone, PartialEq)]
+struct KeyPress {
+    key: String, // The browser's name for the key.  Single characters are lower case
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+}
+
+#[derive(Clone)]
+struct Binding {
+    press: KeyPress,
+    action: KeyVal,
+}
+
+lazy_static! {
+    // The active key bindings
+    static ref BINDINGS: Arc<Mutex<Vec<Binding>>> = Arc::new(Mutex::new(default_bindings()));
+}
+
+fn default_bindings() -> Vec<Binding> {
+    DEFAULT_BINDINGS
+        .iter()
+        .filter_map(|(key, action)| Some(Binding { press: parse_key(key).ok()?, action: action_by_name(action)? }))
+        .collect()
+}
+
+// Returns the action a key press is bound to, if any
+pub(crate) fn lookup(key: &str, ctrl: bool, alt: bool, shift: bool) -> Option<KeyVal> {
+    let key = if key.chars().count() == 1 { key.to_lowercase() } else { key.to_string() };
+    let bindings = lock(&BINDINGS);
+    let find = |shift: bool| {
+        let press = KeyPress { key: key.clone(), ctrl, alt, shift };
+        bindings.iter().find(|b| b.press == press).map(|b| b.action)
+    };
+    find(shift).or_else(|| if shift { find(false) } else { None })
+}
+
+// Replaces all the bindings with the ones in a JSON object.  Nothing changes if any of them aren't valid
+pub(crate) fn load(json: &str) -> Result<(), Error> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| Error::Invalid(format!("The key bindings aren't valid JSON: {}", e)))?;
+    let map = value
+        .as_object()
+        .ok_or_else(|| Error::Invalid("The key bindings should be a JSON object mapping keys to actions".to_string()))?;
+    let mut bindings: Vec<Binding> = Vec::new();
+    for (key, action) in map {
+        let name = action
+            .as_str()
+            .ok_or_else(|| Error::Invalid(format!("The action for '{}' should be a string", key)))?;
+        let press = parse_key(key)?;
+        if bindings.iter().any(|b| b.press == press) {
+            return Err(Error::Invalid(format!("The key '{}' is bound more than once", key)));
+        }
+        bindings.push(Binding { press, action: parse_action(name)? });
+    }
+    *lock(&BINDINGS) = bindings;
+    Ok(())
+}
+
+// Returns the bindings as a JSON object, in the form load() takes
+pub(crate) fn save() -> String {
+    let mut map = Map::new();
+    for b in lock(&BINDINGS).iter() {
+        map.insert(key_name(&b.press, false), Value::String(action_name(b.action).to_string()));
+    }
+    serde_json::to_string_pretty(&Value::Object(map)).unwrap_or_default()
+}
+
+// Binds a key to an action, replacing anything the key was bound to before.  An empty action unbinds the key
+pub(crate) fn bind(key: &str, action: &str) -> Result<(), Error> {
+    let press = parse_key(key)?;
+    let action = if action.is_empty() { None } else { Some(parse_action(action)?) };
+    let mut bindings = lock(&BINDINGS);
+    bindings.retain(|b| b.press != press);
+    if let Some(action) = action {
+        bindings.push(Binding { press, action });
+    }
+    Ok(())
+}
+
+// Puts the default bindings back
+pub(crate) fn reset() {
+    *lock(&BINDINGS) = default_bindings();
+}
+
+// Returns a line of help text for each action with keys bound to it, eg "Rotate left: Left, 4"
+pub(crate) fn help_lines() -> Vec<String> {
+    let bindings = lock(&BINDINGS);
+    ACTIONS
+        .iter()
+        .filter_map(|(action, _, label)| {
+            let keys: Vec<String> =
+                bindings.iter().filter(|b| b.action == *action).map(|b| key_name(&b.press, true)).collect();
+            if keys.is_empty() {
+                None
+            } else {
+                Some(format!("{}: {}", label, keys.join(", ")))
+            }
+        })
+        .collect()
+}
+
+fn action_by_name(name: &str) -> Option<KeyVal> {
+    ACTIONS.iter().find(|(_, n, _)| n.eq_ignore_ascii_case(name)).map(|(a, _, _)| *a)
+}
+
+fn action_name(action: KeyVal) -> &'static str {
+    ACTIONS.iter().find(|(a, _, _)| *a == action).map(|(_, n, _)| *n).unwrap_or("")
+}
+
+fn parse_action(name: &str) -> Result<KeyVal, Error> {
+    action_by_name(name).ok_or_else(|| {
+        let names: Vec<&str> = ACTIONS.iter().map(|(_, n, _)| *n).collect();
+        Error::Invalid(format!("Unknown action '{}'.  It should be one of: {}", name, names.join(", ")))
+    })
+}
+
+// Reads a key name such as "w", "Ctrl+Shift+Z", or "Alt+ArrowLeft"
+fn parse_key(text: &str) -> Result<KeyPress, Error> {
+    // The key itself can be "+", so it's split off from the modifiers first
+    let (modifiers, key) = match text.strip_suffix("++") {
+        Some(m) => (m, "+"),
+        None if text == "+" => ("", "+"),
+        None => text.rsplit_once('+').unwrap_or(("", text)),
+    };
+    let mut press = KeyPress { key: String::new(), ctrl: false, alt: false, shift: false };
+    for m in modifiers.split('+').filter(|m| !m.is_empty()) {
+        match m.to_lowercase().as_str() {
+            "ctrl" | "control" | "cmd" | "meta" => press.ctrl = true,
+            "alt" | "option" => press.alt = true,
+            "shift" => press.shift = true,
+            _ => return Err(Error::Invalid(format!("Unknown modifier '{}' in '{}'", m, text))),
+        }
+    }
+    press.key = if key.eq_ignore_ascii_case("space") {
+        " ".to_string()
+    } else if key.eq_ignore_ascii_case("plus") {
+        "+".to_string()
+    } else if key.chars().count() == 1 {
+        key.to_lowercase()
+    } else if let Some((name, _)) = NAMED_KEYS.iter().find(|(n, _)| n.eq_ignore_ascii_case(key)) {
+        name.to_string()
+    } else if is_function_key(key) {
+        key.to_uppercase()
+    } else {
+        return Err(Error::Invalid(format!("Unknown key '{}' in '{}'", key, text)));
+    };
+    Ok(press)
+}
+
+fn is_function_key(key: &str) -> bool {
+    let n = key.strip_prefix('F').or_else(|| key.strip_prefix('f')).and_then(|n| n.parse::<u32>().ok());
+    matches!(n, Some(1..=24))
+}
+
+// Returns the name of a key press.  The short form is for the help text, and the long form is what parse_key() reads
+fn key_name(press: &KeyPress, short: bool) -> String {
+    let mut name = String::new();
+    if press.ctrl {
+        name.push_str("Ctrl+");
+    }
+    if press.alt {
+        name.push_str("Alt+");
+    }
+    if press.shift {
+        name.push_str("Shift+");
+    }
+    let named = NAMED_KEYS.iter().find(|(n, _)| *n == press.key);
+    match named {
+        Some((_, label)) if short || press.key == " " => name.push_str(label),
+        _ if (short || press.ctrl || press.alt) && press.key.chars().count() == 1 => {
+            name.push_str(&press.key.to_uppercase())
+        }
+        _ => name.push_str(&press.key),
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    lazy_static! {
+        // The bindings are shared, so the tests which change them take turns
+        static ref TURN: Mutex<()> = Mutex::new(());
+    }
+
+    // The long name of a key, as it reads back
+    fn parsed(text: &str) -> String {
+        parse_key(text).map(|p| key_name(&p, false)).unwrap_or_else(|e| e.to_string())
+    }
+
+    // The name of the action a key press is bound to
+    fn action(key: &str, ctrl: bool, shift: bool) -> &'static str {
+        lookup(key, ctrl, false, shift).map(action_name).unwrap_or("")
+    }
+
+    #[test]
+    fn keys_with_modifiers() {
+        assert_eq!(parsed("w"), "w");
+        assert_eq!(parsed("W"), "w");
+        assert_eq!(parsed("ctrl+shift+z"), "Ctrl+Shift+Z");
+        assert_eq!(parsed("Cmd+Alt+arrowleft"), "Ctrl+Alt+ArrowLeft");
+        assert_eq!(parsed("f12"), "F12");
+        assert_eq!(parsed("F25"), Error::Invalid("Unknown key 'F25' in 'F25'".to_string()).to_string());
+        assert_eq!(parsed("Hyper+x"), Error::Invalid("Unknown modifier 'Hyper' in 'Hyper+x'".to_string()).to_string());
+    }
+
+    #[test]
+    fn plus_and_space_keys() {
+        assert_eq!(parse_key("+").unwrap().key, "+");
+        assert_eq!(parse_key("Plus").unwrap().key, "+");
+        assert_eq!(parsed("Ctrl++"), "Ctrl++");
+        assert_eq!(parsed("Ctrl+Plus"), "Ctrl++");
+        assert_eq!(parse_key("space").unwrap().key, " ");
+        assert_eq!(parsed("Shift+Space"), "Shift+Space");
+        assert_eq!(key_name(&parse_key("Space").unwrap(), true), "Space");
+    }
+
+    #[test]
+    fn shift_falls_back_to_the_key_without_it() {
+        let _turn = TURN.lock();
+        reset();
+        assert_eq!(action("+", false, true), "speed_up");
+        assert_eq!(action("W", false, true), "move_up");
+
+        // Unless the key has a Shift binding of its own
+        assert_eq!(action("z", true, false), "undo");
+        assert_eq!(action("Z", true, true), "redo");
+
+        // Other modifiers have to match
+        assert_eq!(action("w", true, false), "");
+        assert_eq!(action("y", false, false), "");
+    }
+
+    #[test]
+    fn loading_replaces_the_bindings() {
+        let _turn = TURN.lock();
+        reset();
+        load(r#"{"Ctrl+Plus": "speed_up", "Space": "stop", "Shift+W": "move_down"}"#).unwrap();
+        assert_eq!(action("+", true, false), "speed_up");
+        assert_eq!(action(" ", false, false), "stop");
+        assert_eq!(action("w", false, true), "move_down");
+        assert_eq!(action("w", false, false), "");
+        assert_eq!(action("+", false, false), "");
+        reset();
+    }
+
+    #[test]
+    fn loading_bad_bindings_changes_nothing() {
+        let _turn = TURN.lock();
+        reset();
+        let before = save();
+        let duplicate = load(r#"{"Ctrl++": "speed_up", "ctrl+plus": "slow_down"}"#).unwrap_err();
+        let message = Error::Invalid("The key 'ctrl+plus' is bound more than once".to_string()).to_string();
+        assert_eq!(duplicate.to_string(), message);
+        assert!(load(r#"{"w": "fly"}"#).is_err());
+        assert!(load(r#"{"w": 1}"#).is_err());
+        assert!(load(r#"["w"]"#).is_err());
+        assert_eq!(save(), before);
+        assert_eq!(action("w", false, false), "move_up");
+    }
+
+    #[test]
+    fn saved_bindings_load_back_the_same() {
+        let _turn = TURN.lock();
+        reset();
+        bind("Ctrl+Space", "fit_view").unwrap();
+        bind("w", "").unwrap();
+        let saved = save();
+        reset();
+        load(&saved).unwrap();
+        assert_eq!(save(), saved);
+        assert_eq!(action(" ", true, false), "fit_view");
+        assert_eq!(action("w", false, false), "");
+        reset();
+    }
+}
@@ -0,0 +1,425 @@
+// Bounding volumes around objects: axis aligned boxes, oriented boxes, and spheres
+//
+// Each object keeps its bounds up to date as it's transformed.  The axis aligned box is worked out again from the
+// points every time.  The oriented box and sphere are moved along with the object instead, so they stay fitted to the
+// same parts of it, and are only worked out again when a transformation stretches the object unevenly.
+//
+// The oriented box comes from principal component analysis (PCA) of the points, so its axes follow the directions the
+// points are most spread out in.  For shapes spread out evenly in every direction (eg a cube) those directions aren't
+// well defined, and the box is lined up with the world space axes instead.
+
+use lazy_static::lazy_static;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::painter::Painter;
+use crate::vector::{add, dot, length, scaled, sub, Vec3};
+use crate::{lock, transform, Matrix, Object, Point, View};
+
+// The most Jacobi rotation sweeps used to find the principal axes.  It normally settles well before this
+const JACOBI_SWEEPS: usize = 50;
+
+// How far from perpendicular (as a dot product) transformed box axes can be before the box is worked out again
+const PERPENDICULAR_TOLERANCE: f64 = 1e-6;
+
+// The overlay colours
+const AABB_COLOUR: &str = "darkorange";
+const OBB_COLOUR: &str = "purple";
+const SPHERE_COLOUR: &str = "teal";
+
+// The lengths of the overlay dashes and the gaps between them, in pixels
+const DASH_LENGTH: f64 = 6.0;
+const DASH_GAP: f64 = 4.0;
+
+// The most dashes drawn around the outline of a sphere.  Larger outlines have longer dashes instead
+const MAX_SPHERE_DASHES: usize = 720;
+
+// An axis aligned bounding box.  Empty boxes have their minimum above their maximum
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Aabb {
+    pub(crate) min: Vec3,
+    pub(crate) max: Vec3,
+}
+
+impl Aabb {
+    pub(crate) fn empty() -> Aabb {
+        Aabb { min: [f64::INFINITY; 3], max: [f64::NEG_INFINITY; 3] }
+    }
+
+    pub(crate) fn from_points(points: &[Point]) -> Aabb {
+        let mut b = Aabb::empty();
+        for p in points.iter() {
+            b.add(to_vec(p));
+        }
+        b
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.min[0] > self.max[0]
+    }
+
+    // Grows the box to take in a point
+    pub(crate) fn add(&mut self, v: Vec3) {
+        for (i, c) in v.iter().enumerate() {
+            self.min[i] = self.min[i].min(*c);
+            self.max[i] = self.max[i].max(*c);
+        }
+    }
+
+    // Returns the box around both this box and another
+    pub(crate) fn union(&self, other: &Aabb) -> Aabb {
+        let mut b = *self;
+        if !other.is_empty() {
+            b.add(other.min);
+            b.add(other.max);
+        }
+        b
+    }
+
+    pub(crate) fn centre(&self) -> Vec3 {
+        [0, 1, 2].map(|i| (self.min[i] + self.max[i]) / 2.0)
+    }
+
+    pub(crate) fn size(&self) -> Vec3 {
+        [0, 1, 2].map(|i| self.max[i] - self.min[i])
+    }
+
+    // Returns the corners.  Bit 0 of the corner number picks the X side, bit 1 the Y side, and bit 2 the Z side
+    fn corners(&self) -> [Vec3; 8] {
+        let side = |c: usize, i: usize| if c & (1 << i) == 0 { self.min[i] } else { self.max[i] };
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|c| [side(c, 0), side(c, 1), side(c, 2)])
+    }
+}
+
+// An oriented bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Obb {
+    pub(crate) centre: Vec3,
+    pub(crate) axes: [Vec3; 3],   // The directions of the box sides, as unit vectors
+    pub(crate) half_sizes: Vec3, // How far the box reaches either side of the centre, along each axis
+}
+
+impl Obb {
+    pub(crate) fn from_points(points: &[Point]) -> Obb {
+        if points.is_empty() {
+            return Obb { centre: [0.0; 3], axes: IDENTITY_AXES, half_sizes: [0.0; 3] };
+        }
+
+        // Find the directions the points are spread out in, using the covariance of the points
+        let n = points.len() as f64;
+        let mean = points.iter().fold([0.0; 3], |m, p| add(m, scaled(to_vec(p), 1.0 / n)));
+        let mut cov = [[0.0; 3]; 3];
+        for p in points.iter() {
+            let d = sub(to_vec(p), mean);
+            for (i, row) in cov.iter_mut().enumerate() {
+                for (j, c) in row.iter_mut().enumerate() {
+                    *c += d[i] * d[j] / n;
+                }
+            }
+        }
+        let axes = principal_axes(cov);
+
+        // Fit the box around the points along those directions
+        let mut lo = [f64::INFINITY; 3];
+        let mut hi = [f64::NEG_INFINITY; 3];
+        for p in points.iter() {
+            let d = sub(to_vec(p), mean);
+            for i in 0..3 {
+                let along = dot(d, axes[i]);
+                lo[i] = lo[i].min(along);
+                hi[i] = hi[i].max(along);
+            }
+        }
+        let mut centre = mean;
+        for i in 0..3 {
+            centre = add(centre, scaled(axes[i], (lo[i] + hi[i]) / 2.0));
+        }
+        Obb { centre, axes, half_sizes: [0, 1, 2].map(|i| (hi[i] - lo[i]) / 2.0) }
+    }
+
+    // Returns the corners, in the same order as for Aabb::corners()
+    fn corners(&self) -> [Vec3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|c| {
+            (0..3).fold(self.centre, |v, i| {
+                let sign = if c & (1 << i) == 0 { -1.0 } else { 1.0 };
+                add(v, scaled(self.axes[i], sign * self.half_sizes[i]))
+            })
+        })
+    }
+}
+
+// A bounding sphere
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Sphere {
+    pub(crate) centre: Vec3,
+    pub(crate) radius: f64,
+}
+
+impl Sphere {
+    // Uses Ritter's algorithm, which gives a sphere close to (within a few percent of) the smallest one
+    pub(crate) fn from_points(points: &[Point]) -> Sphere {
+        let first = match points.first() {
+            Some(p) => to_vec(p),
+            None => return Sphere { centre: [0.0; 3], radius: 0.0 },
+        };
+
+        // Start with the sphere between two points which are far apart
+        let farthest = |from: Vec3| -> Vec3 {
+            points.iter().map(to_vec).fold(from, |best, v| if dist(v, from) > dist(best, from) { v } else { best })
+        };
+        let a = farthest(first);
+        let b = farthest(a);
+        let mut centre = scaled(add(a, b), 0.5);
+        let mut radius = dist(a, b) / 2.0;
+
+        // Then grow it to take in any points left outside
+        for p in points.iter() {
+            let v = to_vec(p);
+            let d = dist(v, centre);
+            if d > radius {
+                let new_radius = (radius + d) / 2.0;
+                centre = add(centre, scaled(sub(v, centre), (new_radius - radius) / d));
+                radius = new_radius;
+            }
+        }
+        Sphere { centre, radius }
+    }
+}
+
+// All the bounding volumes of an object
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Bounds {
+    pub(crate) aabb: Aabb,
+    pub(crate) obb: Obb,
+    pub(crate) sphere: Sphere,
+}
+
+impl Bounds {
+    pub(crate) fn from_points(points: &[Point]) -> Bounds {
+        Bounds { aabb: Aabb::from_points(points), obb: Obb::from_points(points), sphere: Sphere::from_points(points) }
+    }
+
+    // Returns the bounds after the object has been transformed by the given matrix, into the given points
+    pub(crate) fn transformed(&self, m: &Matrix, points: &[Point]) -> Bounds {
+        let k = match similarity_scale(m) {
+            Some(k) => k,
+            None => return Bounds::from_points(points),
+        };
+        let linear = |v: Vec3| sub(transform_vec(m, v), transform_vec(m, [0.0; 3]));
+        let obb = Obb {
+            centre: transform_vec(m, self.obb.centre),
+            axes: self.obb.axes.map(|a| scaled(linear(a), 1.0 / k)),
+            half_sizes: self.obb.half_sizes.map(|h| h * k),
+        };
+        let sphere = Sphere { centre: transform_vec(m, self.sphere.centre), radius: self.sphere.radius * k };
+        Bounds { aabb: Aabb::from_points(points), obb, sphere }
+    }
+
+    pub(crate) fn json(&self) -> Value {
+        let aabb = if self.aabb.is_empty() {
+            Value::Null
+        } else {
+            json!({"min": self.aabb.min, "max": self.aabb.max})
+        };
+        json!({
+            "aabb": aabb,
+            "obb": {"centre": self.obb.centre, "axes": self.obb.axes, "halfSizes": self.obb.half_sizes},
+            "sphere": {"centre": self.sphere.centre, "radius": self.sphere.radius},
+        })
+    }
+}
+
+const IDENTITY_AXES: [Vec3; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+// Returns how much a matrix scales things by, if it only rotates, moves, and scales evenly in every direction.  Returns
+// None for anything else (eg stretching in one direction), as that doesn't keep the shape of boxes and spheres
+fn similarity_scale(m: &Matrix) -> Option<f64> {
+    let cols = [0, 1, 2].map(|j| [m[j], m[4 + j], m[8 + j]]);
+    let k = length(cols[0]);
+    if k == 0.0 || !k.is_finite() {
+        return None;
+    }
+    for i in 0..3 {
+        if ((length(cols[i]) - k) / k).abs() > PERPENDICULAR_TOLERANCE {
+            return None;
+        }
+        for j in (i + 1)..3 {
+            if (dot(cols[i], cols[j]) / (k * k)).abs() > PERPENDICULAR_TOLERANCE {
+                return None;
+            }
+        }
+    }
+    Some(k)
+}
+
+// Returns the eigenvectors of a symmetric 3x3 matrix, found with the Jacobi eigenvalue method.  They're returned as
+// unit vectors at right angles to each other
+fn principal_axes(mut a: [[f64; 3]; 3]) -> [Vec3; 3] {
+    let mut v = IDENTITY_AXES; // The rotations so far.  The eigenvectors end up as its columns
+    for _ in 0..JACOBI_SWEEPS {
+        let off_diagonal = a[0][1].powi(2) + a[0][2].powi(2) + a[1][2].powi(2);
+        let diagonal = a[0][0].powi(2) + a[1][1].powi(2) + a[2][2].powi(2);
+        if off_diagonal <= diagonal * 1e-24 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+
+            // Rotate in the p-q plane by the angle which zeroes a[p][q]
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for row in a.iter_mut() {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = [0, 1, 2].map(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = [0, 1, 2].map(|k| s * row_p[k] + c * row_q[k]);
+            for row in v.iter_mut() {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+        }
+    }
+    [0, 1, 2].map(|j| [v[0][j], v[1][j], v[2][j]])
+}
+
+// * Small vector helpers *
+fn to_vec(p: &Point) -> Vec3 {
+    [p.x, p.y, p.z]
+}
+
+fn to_point(v: Vec3) -> Point {
+    Point { num: 0, x: v[0], y: v[1], z: v[2] }
+}
+
+fn transform_vec(m: &Matrix, v: Vec3) -> Vec3 {
+    to_vec(&transform(m, to_point(v)))
+}
+
+fn dist(a: Vec3, b: Vec3) -> f64 {
+    length(sub(a, b))
+}
+
+// Returns the bounds around all the points of the given objects.  The boxes and sphere are worked out from scratch,
+// as they don't follow from the bounds of each object
+pub(crate) fn around<'a>(objects: impl Iterator<Item = &'a Object>) -> Bounds {
+    let points: Vec<Point> = objects.flat_map(|o| o.points.iter().copied()).collect();
+    Bounds::from_points(&points)
+}
+
+// Which bounding volumes are drawn over the scene
+pub(crate) struct Overlay {
+    pub(crate) aabb: bool,
+    pub(crate) obb: bool,
+    pub(crate) sphere: bool,
+}
+
+lazy_static! {
+    pub(crate) static ref OVERLAY: Arc<Mutex<Overlay>> = Arc::new(Mutex::new(Overlay {
+        aabb: false,
+        obb: false,
+        sphere: false,
+    }));
+}
+
+// Draws the bounding volumes of each object as dashed wireframes, for those turned on
+pub(crate) fn draw(p: &mut dyn Painter, world_space: &HashMap<String, Object>, view: &View) {
+    let overlay = lock(&OVERLAY);
+    if !overlay.aabb && !overlay.obb && !overlay.sphere {
+        return;
+    }
+    for ob in world_space.values().filter(|o| !o.points.is_empty()) {
+        let b = &ob.bounds;
+        if overlay.aabb {
+            draw_box(p, &b.aabb.corners(), AABB_COLOUR, view);
+        }
+        if overlay.obb {
+            draw_box(p, &b.obb.corners(), OBB_COLOUR, view);
+        }
+        if overlay.sphere {
+            draw_sphere(p, &b.sphere, view);
+        }
+    }
+}
+
+// Draws the 12 edges of a box, given its corners in the order Aabb::corners() uses
+fn draw_box(p: &mut dyn Painter, corners: &[Vec3; 8], colour: &str, view: &View) {
+    let screen = corners.map(|c| view.project(&to_point(c)));
+    for c in 0..8 {
+        for bit in [1, 2, 4] {
+            if c & bit == 0 {
+                dashed_line(p, screen[c], screen[c | bit], colour, view);
+            }
+        }
+    }
+}
+
+// Draws the outline of a sphere.  As the view is orthographic, this is a circle the same size as the sphere
+fn draw_sphere(p: &mut dyn Painter, sphere: &Sphere, view: &View) {
+    let (cx, cy) = view.project(&to_point(sphere.centre));
+    let r = sphere.radius * view.step;
+    let period = (DASH_LENGTH + DASH_GAP) * view.line_width;
+    let dashes = ((2.0 * std::f64::consts::PI * r / period).floor() as usize).clamp(12, MAX_SPHERE_DASHES);
+    let step = 2.0 * std::f64::consts::PI / dashes as f64;
+    let sweep = step * DASH_LENGTH / (DASH_LENGTH + DASH_GAP);
+    for i in 0..dashes {
+        let (a, b) = (i as f64 * step, i as f64 * step + sweep);
+        p.line((cx + r * a.cos(), cy + r * a.sin()), (cx + r * b.cos(), cy + r * b.sin()), SPHERE_COLOUR, view.line_width);
+    }
+}
+
+// Draws a dashed line.  Only the dashes inside the graph area are drawn, so a line reaching far outside it (eg when
+// zoomed right in) doesn't turn into a huge number of dashes.  The dashes are still spaced from the start of the line,
+// so they don't creep along it as it moves in and out of the graph area
+fn dashed_line(p: &mut dyn Painter, from: (f64, f64), to: (f64, f64), colour: &str, view: &View) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == 0.0 || !len.is_finite() {
+        return;
+    }
+    let (start, end) = match inside_graph(from, to, view) {
+        Some((start, end)) => (start * len, end * len),
+        None => return,
+    };
+    let at = |d: f64| (from.0 + dx * d / len, from.1 + dy * d / len);
+    let (dash, gap) = (DASH_LENGTH * view.line_width, DASH_GAP * view.line_width);
+    let mut d = (start / (dash + gap)).floor() * (dash + gap);
+    while d < end {
+        p.line(at(d), at((d + dash).min(len)), colour, view.line_width);
+        d += dash + gap;
+    }
+}
+
+// Returns how far along a line (from 0 at its start to 1 at its end) it goes into and out of the graph area, or None
+// if it misses the graph area
+fn inside_graph(from: (f64, f64), to: (f64, f64), view: &View) -> Option<(f64, f64)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let (mut start, mut end) = (0.0_f64, 1.0_f64);
+
+    // Each side of the graph area is given by which way the line crosses it, and how far inside it the start is
+    let sides = [(-dx, from.0), (dx, view.graph_width - from.0), (-dy, from.1), (dy, view.graph_height - from.1)];
+    for (towards, room) in sides {
+        if towards == 0.0 {
+            if room < 0.0 {
+                return None;
+            }
+        } else if towards < 0.0 {
+            start = start.max(room / towards);
+        } else {
+            end = end.min(room / towards);
+        }
+    }
+    if start < end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,314 @@
+// Colour maps, for turning data values into colours
+
+// Evenly spaced stops along the viridis colour map, from low to high
+const VIRIDIS: [(u8, u8, u8); 9] = [
+    (68, 1, 84),
+    (71, 44, 122),
+    (59, 81, 139),
+    (44, 113, 142),
+    (33, 144, 141),
+    (39, 173, 129),
+    (92, 200, 99),
+    (170, 220, 50),
+    (253, 231, 37),
+];
+
+// Distinct colours for categories, from the Tableau 10 palette
+const CATEGORIES: [&str; 10] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7", "#9c755f", "#bab0ac",
+];
+
+// Returns the colour for a category number.  The colours repeat after the palette has been used up
+pub(crate) fn category_colour(i: usize) -> String {
+    CATEGORIES[i % CATEGORIES.len()].to_string()
+}
+
+// Returns the colour for a value between 0.0 and 1.0 on a continuous colour map.  Values outside that range are clamped
+pub(crate) fn colour_map(t: f64) -> String {
+    let t = if t.is_finite() { t.clamp(0.0, 1.0) } else { 0.0 };
+    let pos = t * (VIRIDIS.len() - 1) as f64;
+    let i = (pos.floor() as usize).min(VIRIDIS.len() - 2);
+    let frac = pos - i as f64;
+    let (r1, g1, b1) = VIRIDIS[i];
+    let (r2, g2, b2) = VIRIDIS[i + 1];
+    let mix = |a: u8, b: u8| -> u8 { (a as f64 + (b as f64 - a as f64) * frac).round() as u8 };
+    format!("rgb({}, {}, {})", mix(r1, r2), mix(g1, g2), mix(b1, b2))
+}
+
+// The CSS named colours, sorted by name
+const NAMED_COLOURS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+// A colour as red, green, and blue values from 0 to 255, plus an alpha (opacity) from 0.0 to 1.0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Rgba {
+    pub(crate) r: u8,
+    pub(crate) g: u8,
+    pub(crate) b: u8,
+    pub(crate) a: f64,
+}
+
+impl Rgba {
+    // Returns the colour as a CSS colour string
+    pub(crate) fn css(&self) -> String {
+        format!("rgba({}, {}, {}, {})", self.r, self.g, self.b, self.a)
+    }
+}
+
+// Works out the value of a CSS colour string.  Handles the named colours, "#rgb", "#rrggbb", "rgb(...)", "rgba(...)",
+// "hsl(...)", and "hsla(...)".  Returns None for anything else
+pub(crate) fn parse_colour(s: &str) -> Option<Rgba> {
+    let s = s.trim().to_lowercase();
+    if let Some(hex) = s.strip_prefix('#') {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let v = u32::from_str_radix(hex, 16).ok()?;
+        return match hex.len() {
+            3 => Some(Rgba {
+                r: ((v >> 8) & 0xf) as u8 * 17,
+                g: ((v >> 4) & 0xf) as u8 * 17,
+                b: (v & 0xf) as u8 * 17,
+                a: 1.0,
+            }),
+            6 => Some(Rgba {
+                r: (v >> 16) as u8,
+                g: (v >> 8) as u8,
+                b: v as u8,
+                a: 1.0,
+            }),
+            _ => None,
+        };
+    }
+    if let Some(args) = s.strip_prefix("rgba(").or_else(|| s.strip_prefix("rgb(")) {
+        let args = args.strip_suffix(')')?;
+        let parts: Vec<&str> = args.split([',', '/', ' ']).filter(|p| !p.is_empty()).collect();
+        if parts.len() != 3 && parts.len() != 4 {
+            return None;
+        }
+        let channel = |p: &str| -> Option<u8> {
+            let v = match p.strip_suffix('%') {
+                Some(pc) => number(pc)? * 2.55,
+                None => number(p)?,
+            };
+            Some(v.round().clamp(0.0, 255.0) as u8)
+        };
+        let a = match parts.get(3) {
+            Some(p) => match p.strip_suffix('%') {
+                Some(pc) => number(pc)? / 100.0,
+                None => number(p)?,
+            },
+            None => 1.0,
+        };
+        return Some(Rgba {
+            r: channel(parts[0])?,
+            g: channel(parts[1])?,
+            b: channel(parts[2])?,
+            a: a.clamp(0.0, 1.0),
+        });
+    }
+    if let Some(args) = s.strip_prefix("hsla(").or_else(|| s.strip_prefix("hsl(")) {
+        let args = args.strip_suffix(')')?;
+        let parts: Vec<&str> = args.split([',', '/', ' ']).filter(|p| !p.is_empty()).collect();
+        if parts.len() != 3 && parts.len() != 4 {
+            return None;
+        }
+        let h = number(parts[0].trim_end_matches("deg"))?.rem_euclid(360.0) / 360.0;
+        let sat = number(parts[1].strip_suffix('%')?)?.clamp(0.0, 100.0) / 100.0;
+        let l = number(parts[2].strip_suffix('%')?)?.clamp(0.0, 100.0) / 100.0;
+        let a = match parts.get(3) {
+            Some(p) => match p.strip_suffix('%') {
+                Some(pc) => number(pc)? / 100.0,
+                None => number(p)?,
+            },
+            None => 1.0,
+        };
+
+        // The standard HSL to RGB conversion, from the CSS colour spec
+        let q = if l < 0.5 { l * (1.0 + sat) } else { l + sat - (l * sat) };
+        let p = (2.0 * l) - q;
+        let hue = |t: f64| -> u8 {
+            let t = t.rem_euclid(1.0);
+            let v = if t < 1.0 / 6.0 {
+                p + ((q - p) * 6.0 * t)
+            } else if t < 0.5 {
+                q
+            } else if t < 2.0 / 3.0 {
+                p + ((q - p) * (2.0 / 3.0 - t) * 6.0)
+            } else {
+                p
+            };
+            (v * 255.0).round() as u8
+        };
+        return Some(Rgba {
+            r: hue(h + 1.0 / 3.0),
+            g: hue(h),
+            b: hue(h - 1.0 / 3.0),
+            a: a.clamp(0.0, 1.0),
+        });
+    }
+    if s == "transparent" {
+        return Some(Rgba { r: 0, g: 0, b: 0, a: 0.0 });
+    }
+    let i = NAMED_COLOURS.binary_search_by(|(name, _)| name.cmp(&s.as_str())).ok()?;
+    let v = NAMED_COLOURS[i].1;
+    Some(Rgba {
+        r: (v >> 16) as u8,
+        g: (v >> 8) as u8,
+        b: v as u8,
+        a: 1.0,
+    })
+}
+
+// Reads a number in a colour.  Rust reads "nan" and "inf" as numbers, but CSS doesn't
+fn number(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|v| v.is_finite())
+}
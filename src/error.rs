@@ -0,0 +1,61 @@
+// The errors which can be returned by this crate
+//
+// When returned from an exported function, an error becomes a JavaScript Error whose message is the text below.
+
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug)]
+pub enum Error {
+    NoWindow,               // There's no browser window (eg when running in a worker)
+    NoDocument,             // The window has no document
+    CanvasNotFound(String), // No element has the given id
+    NotACanvas(String),     // The element with the given id isn't a canvas
+    NoContext,              // The canvas didn't provide a 2D drawing context
+    NotStarted,             // Drawing was attempted before wasm_main() set things up
+    Js(String),             // A call to a browser API failed
+    NameInUse(String),      // An object with the given name already exists
+    NotFound(String),       // No object has the given name
+    Invalid(String),        // The input given wasn't valid (eg a malformed expression or scene file)
+    Encode(String),         // An image couldn't be encoded
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NoWindow => write!(f, "There's no browser window to draw in"),
+            Error::NoDocument => write!(f, "The browser window has no document"),
+            Error::CanvasNotFound(id) => write!(f, "Couldn't find an element with the id '{}' to draw on", id),
+            Error::NotACanvas(id) => write!(f, "The element with the id '{}' isn't a canvas", id),
+            Error::NoContext => write!(f, "The canvas doesn't support 2D drawing"),
+            Error::NotStarted => write!(f, "wasm_main() needs to be called first"),
+            Error::Js(msg) => write!(f, "A browser call failed: {}", msg),
+            Error::NameInUse(name) => write!(f, "An object named '{}' already exists", name),
+            Error::NotFound(name) => write!(f, "There's no object named '{}'", name),
+            Error::Invalid(msg) => write!(f, "{}", msg),
+            Error::Encode(msg) => write!(f, "Couldn't encode the image: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Most of the parsers describe problems with a plain message
+impl From<String> for Error {
+    fn from(msg: String) -> Error {
+        Error::Invalid(msg)
+    }
+}
+
+// Errors thrown by browser APIs
+impl From<JsValue> for Error {
+    fn from(v: JsValue) -> Error {
+        Error::Js(v.as_string().unwrap_or_else(|| format!("{:?}", v)))
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(e: Error) -> JsValue {
+        JsError::new(&e.to_string()).into()
+    }
+}
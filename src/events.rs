@@ -0,0 +1,156 @@
+// The browser event listeners, so the embedding page only needs to call wasm_main()
+//
+// The listeners live for as long as the page does, so their closures are handed over to JavaScript and never freed.
+// They're only added once, however many times wasm_main() is called.
+
+use std::cell::RefCell;
+use wasm_bindgen::convert::FromWasmAbi;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    AddEventListenerOptions, DragEvent, EventTarget, HtmlCanvasElement, HtmlElement, KeyboardEvent, PointerEvent,
+    TouchEvent, WheelEvent,
+};
+
+use crate::{apply_transformation, click_handler, document, key_action, load_csv, move_handler, wheel_handler, window};
+use crate::{bindings, canvas_position, gestures, layout, lock, Error};
+
+// How often the running operation moves on a step, in milliseconds
+const TRANSFORM_INTERVAL_MS: i32 = 25;
+
+thread_local! {
+    // The canvas the listeners were last added to, if they've been added
+    static INSTALLED: RefCell<Option<HtmlCanvasElement>> = const { RefCell::new(None) };
+}
+
+// Returns true once the listeners have been added
+pub(crate) fn installed() -> bool {
+    INSTALLED.with(|i| i.borrow().is_some())
+}
+
+// Adds the keyboard listener to the document, and the pointer, wheel, touch, and drag and drop listeners to the
+// canvas.  Also starts the timer which animates the operations.  Calling this again only adds the canvas listeners,
+// and only if it's a different canvas
+pub(crate) fn install(canvas: &HtmlCanvasElement) -> Result<(), Error> {
+    if INSTALLED.with(|i| i.borrow().as_ref() == Some(canvas)) {
+        return Ok(());
+    }
+    if !installed() {
+        install_page()?;
+    }
+
+    // Pointer events cover the mouse, pens, and touches, so taps on the source code link work the same as clicks.
+    // Pointers pressed on the graph area start gestures, and are captured so the gesture carries on if they stray
+    // off the canvas
+    let c = canvas.clone();
+    listen(canvas, "pointerdown", move |evt: PointerEvent| {
+        if let Err(e) = click_handler(evt.client_x(), evt.client_y()) {
+            web_sys::console::error_1(&e);
+        }
+        let (x, y) = canvas_position(evt.client_x() as f64, evt.client_y() as f64);
+        if evt.button() == 0 && lock(&layout::LAYOUT).graph.contains(x, y) {
+            let _ = c.set_pointer_capture(evt.pointer_id());
+            gestures::pointer_down(evt.pointer_id(), x, y, evt.time_stamp());
+        }
+    })?;
+    listen(canvas, "pointermove", |evt: PointerEvent| {
+        move_handler(evt.client_x(), evt.client_y());
+        let (x, y) = canvas_position(evt.client_x() as f64, evt.client_y() as f64);
+        gestures::pointer_move(evt.pointer_id(), x, y);
+    })?;
+    listen(canvas, "pointerup", |evt: PointerEvent| gestures::pointer_up(evt.pointer_id(), evt.time_stamp()))?;
+    listen(canvas, "pointercancel", |evt: PointerEvent| gestures::pointer_cancel(evt.pointer_id()))?;
+    listen(canvas, "wheel", |evt: WheelEvent| wheel_handler(evt.delta_y() as i32))?;
+
+    // Stop touches on the canvas from scrolling or zooming the page instead.  This needs a listener which isn't
+    // passive, as browsers ignore preventDefault() from passive touch listeners
+    canvas.style().set_property("touch-action", "none")?;
+    let opts = AddEventListenerOptions::new();
+    opts.set_passive(false);
+    for name in ["touchstart", "touchmove"] {
+        let f = Closure::wrap(Box::new(|evt: TouchEvent| evt.prevent_default()) as Box<dyn FnMut(TouchEvent)>);
+        canvas.add_event_listener_with_callback_and_add_event_listener_options(name, f.as_ref().unchecked_ref(), &opts)?;
+        f.forget();
+    }
+
+    // CSV files dropped onto the canvas are loaded as 3D scatter plots
+    listen(canvas, "dragover", |evt: DragEvent| evt.prevent_default())?;
+    listen(canvas, "drop", drop_files)?;
+
+    INSTALLED.with(|i| *i.borrow_mut() = Some(canvas.clone()));
+    Ok(())
+}
+
+// Adds the keyboard listener and the animation timer, which aren't tied to a canvas
+fn install_page() -> Result<(), Error> {
+    let doc = document()?;
+    listen(&doc, "keydown", key_down)?;
+
+    // Apply the matrix transformations
+    let tick = Closure::wrap(Box::new(apply_transformation) as Box<dyn FnMut()>);
+    window()?.set_interval_with_callback_and_timeout_and_arguments_0(
+        tick.as_ref().unchecked_ref(),
+        TRANSFORM_INTERVAL_MS,
+    )?;
+    tick.forget();
+    Ok(())
+}
+
+// Adds an event listener which lasts for the life of the page
+fn listen<E, F>(target: &EventTarget, name: &str, f: F) -> Result<(), Error>
+where
+    E: FromWasmAbi + 'static,
+    F: FnMut(E) + 'static,
+{
+    let closure = Closure::wrap(Box::new(f) as Box<dyn FnMut(E)>);
+    target.add_event_listener_with_callback(name, closure.as_ref().unchecked_ref())?;
+    closure.forget();
+    Ok(())
+}
+
+fn key_down(evt: KeyboardEvent) {
+    // Keys typed into the page's own text boxes are left alone, including shortcuts like Ctrl+Z
+    if typing(&evt) {
+        return;
+    }
+    let ctrl = evt.ctrl_key() || evt.meta_key();
+    if let Some(key) = bindings::lookup(&evt.key(), ctrl, evt.alt_key(), evt.shift_key()) {
+        // Stop the browser acting on shortcuts the viewer uses too
+        if ctrl || evt.alt_key() {
+            evt.prevent_default();
+        }
+        key_action(key);
+    }
+}
+
+// Returns true if a key was pressed in something which takes text, such as an input box or an editable element
+fn typing(evt: &KeyboardEvent) -> bool {
+    match evt.target().and_then(|t| t.dyn_into::<HtmlElement>().ok()) {
+        Some(el) => el.is_content_editable() || matches!(el.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT"),
+        None => false,
+    }
+}
+
+// Loads each CSV file dropped onto the canvas
+fn drop_files(evt: DragEvent) {
+    evt.prevent_default();
+    let files = match evt.data_transfer().and_then(|d| d.files()) {
+        Some(f) => f,
+        None => return,
+    };
+    for file in (0..files.length()).filter_map(|i| files.get(i)) {
+        let name = file.name();
+        let loaded = Closure::once(move |text: JsValue| {
+            match load_csv(&name, &text.as_string().unwrap_or_default()) {
+                Ok(problems) => {
+                    for p in problems {
+                        web_sys::console::warn_1(&format!("{}, {}", name, p).into());
+                    }
+                }
+                Err(e) => web_sys::console::error_2(&format!("{}:", name).into(), &e),
+            }
+        });
+        let _ = file.text().then(&loaded);
+        loaded.forget();
+    }
+}
@@ -0,0 +1,342 @@
+// Touch (and mouse) gestures on the graph area
+//
+// Dragging one pointer rotates the scene, pinching two pointers zooms it, and dragging two pointers together moves it.
+// A double tap resets the view.  The recogniser only works with positions and times, so it doesn't need a browser.
+//
+// The changes from a gesture are applied straight away as the pointers move, then recorded as a single undo step once
+// all the pointers are lifted.
+
+use lazy_static::lazy_static;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    current_view, history, lock, reset_view, rotate_around_x, rotate_around_y, scale, stop_operation, transform_world,
+    translate, IDENTITY_MATRIX,
+};
+
+// How far (in pixels) a pointer can move and still count as a tap
+const TAP_SLOP: f64 = 10.0;
+
+// The longest a tap can be held down for, in milliseconds
+const TAP_TIME_MS: f64 = 250.0;
+
+// The most time between lifting the first tap of a double tap and starting the second, in milliseconds
+const DOUBLE_TAP_TIME_MS: f64 = 300.0;
+
+// How far apart (in pixels) the two taps of a double tap can be
+const DOUBLE_TAP_SLOP: f64 = 30.0;
+
+// How far the scene turns for each pixel dragged
+const ROTATE_DEGREES_PER_PIXEL: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Gesture {
+    Rotate { dx: f64, dy: f64 }, // A single pointer was dragged by the given pixels
+    Pinch { scale: f64 },         // The distance between two pointers changed by the given ratio
+    Pan { dx: f64, dy: f64 },     // The point between two pointers was dragged by the given pixels
+    DoubleTap { x: f64, y: f64 }, // A double tap at the given position
+}
+
+#[derive(Clone, Copy)]
+struct Pointer {
+    id: i32,
+    x: f64,
+    y: f64,
+}
+
+// Where and when a pointer was pressed or lifted
+#[derive(Clone, Copy)]
+struct Touch {
+    x: f64,
+    y: f64,
+    time: f64,
+}
+
+#[derive(Default)]
+pub(crate) struct GestureRecogniser {
+    pointers: Vec<Pointer>, // The pointers currently down.  Only the first two are followed
+    press: Option<Touch>,   // Where the current gesture started, while it could still be a tap
+    last_tap: Option<Touch>, // Where the last tap was, and when it was lifted
+}
+
+impl GestureRecogniser {
+    pub(crate) fn new() -> GestureRecogniser {
+        GestureRecogniser::default()
+    }
+
+    // Returns true while any pointers are down
+    pub(crate) fn active(&self) -> bool {
+        !self.pointers.is_empty()
+    }
+
+    pub(crate) fn pointer_down(&mut self, id: i32, x: f64, y: f64, time: f64) {
+        if self.pointers.len() >= 2 || self.pointers.iter().any(|p| p.id == id) {
+            return;
+        }
+
+        // A gesture with more than one pointer isn't a tap
+        self.press = if self.pointers.is_empty() { Some(Touch { x, y, time }) } else { None };
+        self.pointers.push(Pointer { id, x, y });
+    }
+
+    // Returns the gestures made by a pointer moving.  Moving two pointers can both pinch and pan at once
+    pub(crate) fn pointer_move(&mut self, id: i32, x: f64, y: f64) -> Vec<Gesture> {
+        let i = match self.pointers.iter().position(|p| p.id == id) {
+            Some(i) => i,
+            None => return Vec::new(),
+        };
+
+        // Small movements are ignored until it's clear this isn't a tap, so taps don't nudge the scene.  The pointer
+        // isn't moved either, so the first drag includes the distance moved so far
+        if let Some(press) = self.press {
+            if distance(press.x, press.y, x, y) <= TAP_SLOP {
+                return Vec::new();
+            }
+            self.press = None;
+        }
+
+        let before = self.pointers.clone();
+        self.pointers[i].x = x;
+        self.pointers[i].y = y;
+        match self.pointers.len() {
+            1 => vec![Gesture::Rotate { dx: x - before[0].x, dy: y - before[0].y }],
+            _ => {
+                let (old_x, old_y, old_spread) = centre_and_spread(&before);
+                let (new_x, new_y, new_spread) = centre_and_spread(&self.pointers);
+                let mut gestures = Vec::new();
+                if old_spread > 0.0 && new_spread > 0.0 && new_spread != old_spread {
+                    gestures.push(Gesture::Pinch { scale: new_spread / old_spread });
+                }
+                if new_x != old_x || new_y != old_y {
+                    gestures.push(Gesture::Pan { dx: new_x - old_x, dy: new_y - old_y });
+                }
+                gestures
+            }
+        }
+    }
+
+    // Returns a double tap if lifting the pointer finished one
+    pub(crate) fn pointer_up(&mut self, id: i32, time: f64) -> Option<Gesture> {
+        let i = self.pointers.iter().position(|p| p.id == id)?;
+        self.pointers.remove(i);
+        if !self.pointers.is_empty() {
+            return None;
+        }
+        let press = self.press.take().filter(|p| time - p.time <= TAP_TIME_MS)?;
+        match self.last_tap.take() {
+            Some(last)
+                if press.time - last.time <= DOUBLE_TAP_TIME_MS
+                    && distance(last.x, last.y, press.x, press.y) <= DOUBLE_TAP_SLOP =>
+            {
+                Some(Gesture::DoubleTap { x: press.x, y: press.y })
+            }
+            _ => {
+                self.last_tap = Some(Touch { time, ..press });
+                None
+            }
+        }
+    }
+
+    // Forgets a pointer the browser has taken over (eg for scrolling), without counting it as a tap
+    pub(crate) fn pointer_cancel(&mut self, id: i32) {
+        self.pointers.retain(|p| p.id != id);
+        self.press = None;
+        self.last_tap = None;
+    }
+}
+
+fn distance(x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
+    ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt()
+}
+
+// Returns the point between the first two pointers, and the distance between them
+fn centre_and_spread(pointers: &[Pointer]) -> (f64, f64, f64) {
+    let (a, b) = (pointers[0], pointers[1]);
+    ((a.x + b.x) / 2.0, (a.y + b.y) / 2.0, distance(a.x, a.y, b.x, b.y))
+}
+
+lazy_static! {
+    static ref GESTURES: Arc<Mutex<GestureRecogniser>> = Arc::new(Mutex::new(GestureRecogniser::new()));
+}
+
+// Returns true while a gesture is being made, so its changes aren't recorded as finished yet
+pub(crate) fn in_progress() -> bool {
+    lock(&GESTURES).active()
+}
+
+// The pointer handlers, for positions on the canvas in CSS pixels and times in milliseconds
+pub(crate) fn pointer_down(id: i32, x: f64, y: f64, time: f64) {
+    let starting = {
+        let mut g = lock(&GESTURES);
+        let starting = !g.active();
+        g.pointer_down(id, x, y, time);
+        starting
+    };
+
+    // Grabbing the scene stops anything already moving it
+    if starting {
+        stop_operation();
+    }
+}
+
+pub(crate) fn pointer_move(id: i32, x: f64, y: f64) {
+    let gestures = lock(&GESTURES).pointer_move(id, x, y);
+    for g in gestures {
+        apply(g);
+    }
+}
+
+pub(crate) fn pointer_up(id: i32, time: f64) {
+    let (gesture, finished) = {
+        let mut g = lock(&GESTURES);
+        (g.pointer_up(id, time), !g.active())
+    };
+    if finished {
+        history::commit_pending();
+    }
+    if let Some(g) = gesture {
+        apply(g);
+    }
+}
+
+pub(crate) fn pointer_cancel(id: i32) {
+    let finished = {
+        let mut g = lock(&GESTURES);
+        g.pointer_cancel(id);
+        !g.active()
+    };
+    if finished {
+        history::commit_pending();
+    }
+}
+
+// Changes the scene for a gesture
+fn apply(gesture: Gesture) {
+    let m = match gesture {
+        // Dragging turns the scene so its front follows the pointer
+        Gesture::Rotate { dx, dy } => {
+            let m = rotate_around_x(&IDENTITY_MATRIX, -dy * ROTATE_DEGREES_PER_PIXEL);
+            rotate_around_y(&m, -dx * ROTATE_DEGREES_PER_PIXEL)
+        }
+
+        // Zooms straight away so the scene keeps up with the fingers, rather than animating like the mouse wheel
+        Gesture::Pinch { scale: s } => scale(&IDENTITY_MATRIX, s, s, s),
+        Gesture::Pan { dx, dy } => {
+            let step = current_view().step;
+            translate(&IDENTITY_MATRIX, dx / step, -dy / step, 0.0)
+        }
+        Gesture::DoubleTap { .. } => {
+            reset_view();
+            return;
+        }
+    };
+    transform_world(&m);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_pointer_drag_rotates() {
+        let mut g = GestureRecogniser::new();
+        g.pointer_down(1, 100.0, 100.0, 0.0);
+        assert_eq!(g.pointer_move(1, 120.0, 90.0), vec![Gesture::Rotate { dx: 20.0, dy: -10.0 }]);
+        assert_eq!(g.pointer_move(1, 125.0, 90.0), vec![Gesture::Rotate { dx: 5.0, dy: 0.0 }]);
+        assert_eq!(g.pointer_up(1, 1000.0), None);
+        assert!(!g.active());
+    }
+
+    #[test]
+    fn small_movements_within_tap_slop_are_ignored() {
+        let mut g = GestureRecogniser::new();
+        g.pointer_down(1, 100.0, 100.0, 0.0);
+        assert!(g.pointer_move(1, 100.0 + TAP_SLOP, 100.0).is_empty());
+        assert_eq!(g.pointer_move(1, 100.0 + TAP_SLOP + 1.0, 100.0), vec![Gesture::Rotate { dx: 11.0, dy: 0.0 }]);
+    }
+
+    #[test]
+    fn taps_held_too_long_aren_t_taps() {
+        let mut g = GestureRecogniser::new();
+        g.pointer_down(1, 100.0, 100.0, 0.0);
+        assert_eq!(g.pointer_up(1, TAP_TIME_MS + 1.0), None);
+        g.pointer_down(1, 100.0, 100.0, TAP_TIME_MS + 50.0);
+        assert_eq!(g.pointer_up(1, TAP_TIME_MS + 100.0), None);
+    }
+
+    #[test]
+    fn two_pointers_pinch_and_pan() {
+        let mut g = GestureRecogniser::new();
+        g.pointer_down(1, 100.0, 100.0, 0.0);
+        g.pointer_down(2, 200.0, 100.0, 10.0);
+
+        // Moving one pointer away from the other both spreads them and moves the point between them
+        assert_eq!(
+            g.pointer_move(2, 300.0, 100.0),
+            vec![Gesture::Pinch { scale: 2.0 }, Gesture::Pan { dx: 50.0, dy: 0.0 }]
+        );
+
+        // Swinging one pointer around the other keeps the spread the same, so it's only a pan
+        assert_eq!(g.pointer_move(2, 100.0, 300.0), vec![Gesture::Pan { dx: -100.0, dy: 100.0 }]);
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut g = GestureRecogniser::new();
+        g.pointer_down(1, 100.0, 100.0, 0.0);
+        assert_eq!(g.pointer_up(1, 100.0), None);
+        g.pointer_down(1, 110.0, 105.0, 100.0 + DOUBLE_TAP_TIME_MS);
+        assert_eq!(g.pointer_up(1, 450.0), Some(Gesture::DoubleTap { x: 110.0, y: 105.0 }));
+
+        // A third tap starts over, rather than making another double tap
+        g.pointer_down(1, 110.0, 105.0, 500.0);
+        assert_eq!(g.pointer_up(1, 550.0), None);
+    }
+
+    #[test]
+    fn taps_too_far_apart_in_time_aren_t_a_double_tap() {
+        let mut g = GestureRecogniser::new();
+        g.pointer_down(1, 100.0, 100.0, 0.0);
+        g.pointer_up(1, 100.0);
+        g.pointer_down(1, 100.0, 100.0, 101.0 + DOUBLE_TAP_TIME_MS);
+        assert_eq!(g.pointer_up(1, 150.0 + DOUBLE_TAP_TIME_MS), None);
+    }
+
+    #[test]
+    fn taps_too_far_apart_in_space_aren_t_a_double_tap() {
+        let mut g = GestureRecogniser::new();
+        g.pointer_down(1, 100.0, 100.0, 0.0);
+        g.pointer_up(1, 100.0);
+        g.pointer_down(1, 101.0 + DOUBLE_TAP_SLOP, 100.0, 200.0);
+        assert_eq!(g.pointer_up(1, 250.0), None);
+    }
+
+    #[test]
+    fn cancelled_pointers_are_forgotten() {
+        let mut g = GestureRecogniser::new();
+        g.pointer_down(1, 100.0, 100.0, 0.0);
+        g.pointer_up(1, 100.0);
+        g.pointer_down(1, 100.0, 100.0, 200.0);
+        g.pointer_cancel(1);
+        assert!(!g.active());
+        assert!(g.pointer_move(1, 200.0, 200.0).is_empty());
+        assert_eq!(g.pointer_up(1, 250.0), None);
+
+        // The tap before the cancel doesn't count towards a double tap either
+        g.pointer_down(1, 100.0, 100.0, 300.0);
+        assert_eq!(g.pointer_up(1, 350.0), None);
+    }
+
+    #[test]
+    fn third_pointer_is_ignored() {
+        let mut g = GestureRecogniser::new();
+        g.pointer_down(1, 100.0, 100.0, 0.0);
+        g.pointer_down(2, 200.0, 100.0, 0.0);
+        g.pointer_down(3, 300.0, 300.0, 0.0);
+        assert!(g.pointer_move(3, 400.0, 400.0).is_empty());
+        assert_eq!(g.pointer_up(3, 100.0), None);
+        g.pointer_up(1, 100.0);
+        g.pointer_up(2, 100.0);
+        assert!(!g.active());
+    }
+}
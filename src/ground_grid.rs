@@ -0,0 +1,141 @@
+// A ground plane grid, drawn in world space
+//
+// Unlike the background graph paper grid, the ground grid is transformed along with the rest of the scene.  Each grid
+// line is split into short segments, one per grid cell, so the segments can be depth sorted along with the objects.
+// The lines fade out with depth, from the nearest part of the grid to the furthest.
+
+use lazy_static::lazy_static;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use crate::painter::Painter;
+use crate::zbuffer::{Vertex, ZBufferPainter};
+use crate::{lock, transform, Matrix, Point, View};
+
+// The most grid lines drawn in each direction.  The spacing is widened to stay under this
+const MAX_LINES: f64 = 100.0;
+
+// The number of steps the lines fade out in.  The colour for each step is only written out once, and shared by the
+// segments drawn in it
+const FADE_STEPS: usize = 32;
+
+// The plane the grid is laid out in
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum GridPlane {
+    XY,
+    XZ,
+    YZ,
+}
+
+impl GridPlane {
+    pub(crate) fn from_name(name: &str) -> Option<GridPlane> {
+        match name.to_lowercase().as_str() {
+            "xy" => Some(GridPlane::XY),
+            "xz" => Some(GridPlane::XZ),
+            "yz" => Some(GridPlane::YZ),
+            _ => None,
+        }
+    }
+
+    // Returns the world space point for a position on the grid
+    fn point(self, a: f64, b: f64) -> Point {
+        let (x, y, z) = match self {
+            GridPlane::XY => (a, b, 0.0),
+            GridPlane::XZ => (a, 0.0, b),
+            GridPlane::YZ => (0.0, a, b),
+        };
+        Point { num: 0, x, y, z }
+    }
+}
+
+pub(crate) struct GroundGrid {
+    pub(crate) visible: bool,
+    pub(crate) plane: GridPlane,
+    pub(crate) extent: f64,       // How far the grid reaches either side of the origin, in world space units
+    pub(crate) spacing: f64,      // The distance between the major grid lines, in world space units
+    pub(crate) subdivisions: u32, // The number of parts each major grid square is split into by the minor lines
+}
+
+lazy_static! {
+    pub(crate) static ref GROUND_GRID: Arc<Mutex<GroundGrid>> = Arc::new(Mutex::new(GroundGrid {
+        visible: false,
+        plane: GridPlane::XZ,
+        extent: 10.0,
+        spacing: 1.0,
+        subdivisions: 1,
+    }));
+}
+
+// A single segment of a grid line, transformed into the scene
+pub(crate) struct GridSegment {
+    from: Point,
+    to: Point,
+    pub(crate) mid_z: f64, // Z depth of the segment's mid point, for sorting along with the objects
+    colour: Rc<str>,
+}
+
+// Returns the segments of the ground grid, sorted furthest away first.  Returns nothing if the grid is turned off
+pub(crate) fn segments(scene: &Matrix) -> Vec<GridSegment> {
+    let grid = lock(&GROUND_GRID);
+    if !grid.visible || grid.extent <= 0.0 || grid.spacing <= 0.0 {
+        return Vec::new();
+    }
+    let divs = grid.subdivisions.max(1) as f64;
+    let minor = (grid.spacing / divs).max(grid.extent * 2.0 / MAX_LINES);
+    let major_every = ((grid.spacing / minor).round() as i64).max(1);
+    let steps = (grid.extent / minor).floor() as i64;
+
+    // Each place the grid lines cross is transformed once, and shared by the segments which meet there
+    let size = (2 * steps + 1) as usize;
+    let mut crossings = Vec::with_capacity(size * size);
+    for i in -steps..=steps {
+        for j in -steps..=steps {
+            crossings.push(transform(scene, grid.plane.point(i as f64 * minor, j as f64 * minor)));
+        }
+    }
+    let at = |i: i64, j: i64| crossings[(i + steps) as usize * size + (j + steps) as usize];
+
+    // The colours for each fade step, from invisible to full strength
+    let near = crossings.iter().map(|p| p.z).fold(f64::INFINITY, f64::min);
+    let far = crossings.iter().map(|p| p.z).fold(f64::NEG_INFINITY, f64::max);
+    let fade_colours = |alpha: f64| -> Vec<Rc<str>> {
+        (0..=FADE_STEPS)
+            .map(|k| format!("rgba(120, 120, 120, {:.3})", alpha * k as f64 / FADE_STEPS as f64).into())
+            .collect()
+    };
+    let major_colours = fade_colours(0.6);
+    let minor_colours = fade_colours(0.25);
+
+    let mut segs = Vec::new();
+    for i in -steps..=steps {
+        let colours = if i % major_every == 0 { &major_colours } else { &minor_colours };
+        for j in -steps..steps {
+            // One segment for each direction of line
+            for (from, to) in [(at(i, j), at(i, j + 1)), (at(j, i), at(j + 1, i))] {
+                let mid_z = (from.z + to.z) / 2.0;
+                let fade = if far > near { (far - mid_z) / (far - near) } else { 1.0 };
+                let step = ((fade * FADE_STEPS as f64).round() as usize).min(FADE_STEPS);
+                if step == 0 {
+                    continue;
+                }
+                segs.push(GridSegment {
+                    from,
+                    to,
+                    mid_z,
+                    colour: colours[step].clone(),
+                });
+            }
+        }
+    }
+    segs.sort_by(|a, b| b.mid_z.total_cmp(&a.mid_z));
+    segs
+}
+
+// Draws a grid segment
+pub(crate) fn draw_segment(p: &mut dyn Painter, seg: &GridSegment, view: &View) {
+    p.line(view.project(&seg.from), view.project(&seg.to), &seg.colour, view.line_width);
+}
+
+// Draws a segment of the grid, depth tested against the objects
+pub(crate) fn draw_segment_zbuffer(z: &mut ZBufferPainter, seg: &GridSegment, view: &View) {
+    z.line(Vertex::project(view, &seg.from), Vertex::project(view, &seg.to), &seg.colour, view.line_width);
+}
@@ -0,0 +1,227 @@
+// Undo and redo support for changes made to the world space
+//
+// Each change is recorded as a reversible command.  Transformations are recorded as where every object's points were
+// before the operation started and after it finished, so undoing and redoing them puts the points back exactly (even
+// for transformations which can't be reversed, like a scale by zero).
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::bounds::Bounds;
+use crate::shading::CornerNormals;
+use crate::{lock, matrix_mult, scene_changed, transform_object, Matrix, Object, Point, SCENE_MATRIX, WORLD_SPACE};
+
+// The maximum number of commands kept on the undo stack
+const HISTORY_LIMIT: usize = 100;
+
+// A reversible change to the world space
+#[derive(Clone)]
+pub(crate) enum Command {
+    Transform { before: Placement, after: Placement }, // A transformation of the whole scene
+    Import { name: String, object: Object },           // An object added to the world space
+    Remove { name: String, object: Object },           // An object removed from the world space
+    Colour { name: String, old: String, new: String }, // An object's surface colour being changed
+    Group(Vec<Command>),                               // Several commands which are undone and redone together
+}
+
+// Where everything in the world space is, which is all a transformation changes
+#[derive(Clone)]
+pub(crate) struct Placement {
+    objects: HashMap<String, (Vec<Point>, Point, Bounds, CornerNormals)>, // The points, mid point, bounds, and normals
+    scene_matrix: Matrix,
+}
+
+impl Placement {
+    fn of(world_space: &HashMap<String, Object>) -> Placement {
+        Placement {
+            objects: world_space
+                .iter()
+                .map(|(name, o)| (name.clone(), (o.points.clone(), o.mid_point, o.bounds, o.corner_normals.clone())))
+                .collect(),
+            scene_matrix: *lock(&SCENE_MATRIX),
+        }
+    }
+
+    // Moves the objects back to where they were.  Objects whose points have changed in number since (eg by being
+    // repaired) are left alone, and the normals of the others are worked out again if the crease angle has changed
+    fn restore(&self, world_space: &mut HashMap<String, Object>) {
+        for (name, (points, mid_point, bounds, normals)) in self.objects.iter() {
+            if let Some(o) = world_space.get_mut(name) {
+                if o.points.len() == points.len() {
+                    o.points = points.clone();
+                    o.mid_point = *mid_point;
+                    o.bounds = *bounds;
+                    if normals.crease_angle == o.corner_normals.crease_angle {
+                        o.corner_normals = normals.clone();
+                    } else {
+                        o.corner_normals = CornerNormals::new(points, &o.surfaces, o.corner_normals.crease_angle);
+                    }
+                }
+            }
+        }
+        *lock(&SCENE_MATRIX) = self.scene_matrix;
+    }
+}
+
+struct History {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+    pending: Option<Placement>, // Where everything was before the running transformation started, if any
+}
+
+lazy_static! {
+    static ref HISTORY: Arc<Mutex<History>> = Arc::new(Mutex::new(History {
+        undo: Vec::new(),
+        redo: Vec::new(),
+        pending: None,
+    }));
+}
+
+// Notes where everything is before a step of the running transformation moves it, if this is the first step
+pub(crate) fn record_step() {
+    let mut history = lock(&HISTORY);
+    if history.pending.is_none() {
+        history.pending = Some(Placement::of(&lock(&WORLD_SPACE)));
+    }
+}
+
+// Moves the running transformation (if any) onto the undo stack.  Called whenever a transformation finishes
+pub(crate) fn commit_pending() {
+    let mut history = lock(&HISTORY);
+    if let Some(before) = history.pending.take() {
+        let after = Placement::of(&lock(&WORLD_SPACE));
+        push_undo(&mut history, Command::Transform { before, after });
+    }
+}
+
+// Transforms the whole scene straight away, and records it
+pub(crate) fn transform(m: &Matrix) {
+    commit_pending();
+    let mut world_space = lock(&WORLD_SPACE);
+    let before = Placement::of(&world_space);
+    for o in world_space.values_mut() {
+        *o = transform_object(o, m);
+    }
+    {
+        let mut scene_matrix = lock(&SCENE_MATRIX);
+        *scene_matrix = matrix_mult(m, &scene_matrix);
+    }
+    let after = Placement::of(&world_space);
+    drop(world_space);
+    push(Command::Transform { before, after });
+}
+
+// Records a command which has already been applied to the world space
+pub(crate) fn push(cmd: Command) {
+    commit_pending();
+    push_undo(&mut lock(&HISTORY), cmd);
+    scene_changed();
+}
+
+// Applies a command to the world space, and records it.  Any running transformation is recorded first, so it's undone
+// separately
+pub(crate) fn execute(cmd: Command) {
+    commit_pending();
+    apply(&cmd);
+    push(cmd);
+}
+
+// Forgets all recorded commands
+pub(crate) fn clear() {
+    let mut history = lock(&HISTORY);
+    history.undo.clear();
+    history.redo.clear();
+    history.pending = None;
+}
+
+// Reverts the most recent command.  Returns false if there was nothing to undo
+pub(crate) fn undo() -> bool {
+    commit_pending();
+    let cmd = match lock(&HISTORY).undo.pop() {
+        Some(c) => c,
+        None => return false,
+    };
+    revert(&cmd);
+    lock(&HISTORY).redo.push(cmd);
+    true
+}
+
+// Re-applies the most recently undone command.  Returns false if there was nothing to redo
+pub(crate) fn redo() -> bool {
+    commit_pending();
+    let cmd = match lock(&HISTORY).redo.pop() {
+        Some(c) => c,
+        None => return false,
+    };
+    apply(&cmd);
+    lock(&HISTORY).undo.push(cmd);
+    true
+}
+
+// Adds a new command to the undo stack, which invalidates anything previously undone
+fn push_undo(history: &mut History, cmd: Command) {
+    history.redo.clear();
+    history.undo.push(cmd);
+    if history.undo.len() > HISTORY_LIMIT {
+        history.undo.remove(0);
+    }
+}
+
+// Applies a command to the world space
+fn apply(cmd: &Command) {
+    let mut world_space = lock(&WORLD_SPACE);
+    apply_to(&mut world_space, cmd);
+    scene_changed();
+}
+
+fn apply_to(world_space: &mut HashMap<String, Object>, cmd: &Command) {
+    match cmd {
+        Command::Transform { after, .. } => after.restore(world_space),
+        Command::Import { name, object } => {
+            world_space.insert(name.clone(), object.clone());
+        }
+        Command::Remove { name, .. } => {
+            world_space.remove(name);
+        }
+        Command::Colour { name, new, .. } => {
+            if let Some(o) = world_space.get_mut(name) {
+                o.material.diffuse = new.clone();
+            }
+        }
+        Command::Group(cmds) => {
+            for c in cmds.iter() {
+                apply_to(world_space, c);
+            }
+        }
+    }
+}
+
+// Reverses the effect of a command on the world space
+fn revert(cmd: &Command) {
+    let mut world_space = lock(&WORLD_SPACE);
+    revert_to(&mut world_space, cmd);
+    scene_changed();
+}
+
+fn revert_to(world_space: &mut HashMap<String, Object>, cmd: &Command) {
+    match cmd {
+        Command::Transform { before, .. } => before.restore(world_space),
+        Command::Import { name, .. } => {
+            world_space.remove(name);
+        }
+        Command::Remove { name, object } => {
+            world_space.insert(name.clone(), object.clone());
+        }
+        Command::Colour { name, old, .. } => {
+            if let Some(o) = world_space.get_mut(name) {
+                o.material.diffuse = old.clone();
+            }
+        }
+        Command::Group(cmds) => {
+            for c in cmds.iter().rev() {
+                revert_to(world_space, c);
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// How the canvas is split between the graph area and the info panel
+//
+// The layout is set up by the options passed to wasm_main(), and worked out again for each frame so it follows the
+// canvas size.
+
+use js_sys::Reflect;
+use lazy_static::lazy_static;
+use std::sync::{Arc, Mutex};
+use wasm_bindgen::prelude::*;
+
+use crate::Error;
+
+// The default share of the canvas taken up by the info panel
+const DEFAULT_PANEL_SIZE: f64 = 0.25;
+
+// Which side of the canvas the info panel is on, if it's shown at all
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum PanelPosition {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    Hidden,
+}
+
+impl PanelPosition {
+    fn from_name(name: &str) -> Option<PanelPosition> {
+        match name.to_lowercase().as_str() {
+            "left" => Some(PanelPosition::Left),
+            "right" => Some(PanelPosition::Right),
+            "top" => Some(PanelPosition::Top),
+            "bottom" => Some(PanelPosition::Bottom),
+            "hidden" | "none" => Some(PanelPosition::Hidden),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Options {
+    pub(crate) panel: PanelPosition,
+    pub(crate) panel_size: f64, // A fraction of the canvas when up to 1, otherwise a size in pixels
+    pub(crate) auto_resize: bool, // Whether the canvas is resized to fill the window, or left at its CSS size
+}
+
+lazy_static! {
+    pub(crate) static ref OPTIONS: Arc<Mutex<Options>> = Arc::new(Mutex::new(Options {
+        panel: PanelPosition::Right,
+        panel_size: DEFAULT_PANEL_SIZE,
+        auto_resize: true,
+    }));
+}
+
+// A rectangular area of the canvas, in pixels
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Rect {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) width: f64,
+    pub(crate) height: f64,
+}
+
+impl Rect {
+    pub(crate) fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+// Where the graph area and info panel are on the canvas
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Layout {
+    pub(crate) graph: Rect,
+    pub(crate) panel: Option<Rect>,
+}
+
+impl Layout {
+    // Splits a canvas of the given size between the graph area and the info panel
+    pub(crate) fn new(width: f64, height: f64, opts: &Options) -> Layout {
+        let size = |total: f64| -> f64 {
+            let s = if opts.panel_size <= 1.0 { total * opts.panel_size } else { opts.panel_size };
+            s.clamp(0.0, total)
+        };
+        let full = Rect { x: 0.0, y: 0.0, width, height };
+        let (graph, panel) = match opts.panel {
+            PanelPosition::Hidden => (full, None),
+            PanelPosition::Right => {
+                let p = size(width);
+                (Rect { width: width - p, ..full }, Some(Rect { x: width - p, width: p, ..full }))
+            }
+            PanelPosition::Left => {
+                let p = size(width);
+                (Rect { x: p, width: width - p, ..full }, Some(Rect { width: p, ..full }))
+            }
+            PanelPosition::Bottom => {
+                let p = size(height);
+                (Rect { height: height - p, ..full }, Some(Rect { y: height - p, height: p, ..full }))
+            }
+            PanelPosition::Top => {
+                let p = size(height);
+                (Rect { y: p, height: height - p, ..full }, Some(Rect { height: p, ..full }))
+            }
+        };
+        Layout { graph, panel }
+    }
+}
+
+lazy_static! {
+    // The layout of the canvas as last drawn.  Used to work out what the mouse is over
+    pub(crate) static ref LAYOUT: Arc<Mutex<Layout>> = Arc::new(Mutex::new(Layout::default()));
+}
+
+// Reads the layout options from the object passed to wasm_main().  Anything left out keeps its default
+pub(crate) fn read_options(js: &JsValue, opts: &mut Options) -> Result<(), Error> {
+    if js.is_undefined() || js.is_null() {
+        return Ok(());
+    }
+    if !js.is_object() {
+        return Err(Error::Invalid("The options should be an object".to_string()));
+    }
+    let get = |key: &str| -> Result<Option<JsValue>, Error> {
+        let v = Reflect::get(js, &JsValue::from_str(key))?;
+        Ok(if v.is_undefined() || v.is_null() { None } else { Some(v) })
+    };
+    if let Some(v) = get("panel")? {
+        let name = v.as_string().ok_or_else(|| Error::Invalid("The panel option should be a string".to_string()))?;
+        opts.panel = PanelPosition::from_name(&name).ok_or_else(|| {
+            let msg = format!("Unknown panel position '{}'.  It should be left, right, top, bottom, or hidden", name);
+            Error::Invalid(msg)
+        })?;
+    }
+    if let Some(v) = get("panelSize")? {
+        match v.as_f64() {
+            Some(s) if s >= 0.0 => opts.panel_size = s,
+            _ => return Err(Error::Invalid("The panelSize option should be a positive number".to_string())),
+        }
+    }
+    if let Some(v) = get("autoResize")? {
+        opts.auto_resize = v
+            .as_bool()
+            .ok_or_else(|| Error::Invalid("The autoResize option should be true or false".to_string()))?;
+    }
+    Ok(())
+}
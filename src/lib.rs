@@ -1,19 +1,53 @@
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use lazy_static::lazy_static;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-
+use wasm_bindgen::{Clamped, JsCast};
+use web_sys::ImageData;
+
+mod animation;
+mod axes;
+mod bindings;
+mod bounds;
+mod colour;
+mod error;
+mod events;
+mod gestures;
+mod ground_grid;
+mod history;
+mod layout;
+mod material;
+mod mesh;
+mod painter;
+mod plot;
+mod primitives;
+mod raster;
+mod scatter;
+mod scene_file;
+mod shading;
+mod svg;
+mod triangulate;
+mod vector;
+mod zbuffer;
+
+pub use error::Error;
+use painter::{CanvasPainter, Painter};
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq)]
 enum OperationType {
     NOTHING,
     ROTATE,
     SCALE,
     TRANSLATE,
+    FIT, // Zooming and moving to fit objects into view.  Set up by fit_to_view()
 }
 
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Copy, PartialEq)]
 enum KeyVal {
     KeyNone = 0,
     KeyMoveRight = 1,
@@ -30,6 +64,41 @@ enum KeyVal {
     KeyEnd = 12,
     KeyMinus = 13,
     KeyPlus = 14,
+    KeyUndo = 15,
+    KeyRedo = 16,
+    KeyStop = 17,
+    KeyFitView = 18,
+    KeyResetView = 19,
+}
+
+impl KeyVal {
+    // Converts the key numbers used by key_press_handler() back into keys
+    fn from_i32(val: i32) -> Option<KeyVal> {
+        let key = match val {
+            0 => KeyVal::KeyNone,
+            1 => KeyVal::KeyMoveRight,
+            2 => KeyVal::KeyMoveLeft,
+            3 => KeyVal::KeyMoveUp,
+            4 => KeyVal::KeyMoveDown,
+            5 => KeyVal::KeyRotateLeft,
+            6 => KeyVal::KeyRotateRight,
+            7 => KeyVal::KeyRotateUp,
+            8 => KeyVal::KeyRotateDown,
+            9 => KeyVal::KeyPageUp,
+            10 => KeyVal::KeyPageDown,
+            11 => KeyVal::KeyHome,
+            12 => KeyVal::KeyEnd,
+            13 => KeyVal::KeyMinus,
+            14 => KeyVal::KeyPlus,
+            15 => KeyVal::KeyUndo,
+            16 => KeyVal::KeyRedo,
+            17 => KeyVal::KeyStop,
+            18 => KeyVal::KeyFitView,
+            19 => KeyVal::KeyResetView,
+            _ => return None,
+        };
+        Some(key)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -45,41 +114,146 @@ type Surface = Vec<i32>;
 
 #[derive(Clone)]
 struct Object {
-    colour: String, // Colour of the object
+    material: material::Material, // How the object looks
     points: Vec<Point>, // List of point (vertices) in the object
     edges: Vec<Edge>, // List of points to connect by edges
     surfaces: Vec<Surface>, // List of points to connect in order, to create a surface
-    mid_point: Point, // The mid point of the object.  Used for calculating object draw order in a very simple way
+    surface_materials: Vec<material::Material>, // Optional material for each surface.  When empty, the object's is used
+    point_colours: Vec<String>, // Optional colour for each point.  When empty, the material's point colour is used
+    mid_point: Point, // The mid point of the object.  Used to draw objects without surfaces in depth order
+    bounds: bounds::Bounds, // Bounding volumes around the points.  Updated by import_object() and transform_object()
+    triangles: Vec<Vec<[usize; 3]>>, // The triangles each surface splits into.  Updated by update_surfaces()
+    corner_normals: shading::CornerNormals, // The normals at the corners of the surfaces.  Updated by update_surfaces()
 }
 
 impl Object {
     fn new() -> Object {
         Object {
-            colour: String::new(),
+            material: material::Material::new(""),
             points: Vec::new(),
             edges: Vec::new(),
             surfaces: Vec::new(),
+            surface_materials: Vec::new(),
+            point_colours: Vec::new(),
             mid_point: Point {
                 num: 0,
                 x: 0.0,
                 y: 0.0,
                 z: 0.0,
             },
+            bounds: bounds::Bounds::from_points(&[]),
+            triangles: Vec::new(),
+            corner_normals: shading::CornerNormals::default(),
         }
     }
+
+    // Works out the triangles each surface splits into and the normals at their corners again, after the surfaces
+    // have changed.  The triangles stay the same when the object is transformed, and the normals are transformed
+    // along with the points, so this is only needed when it's imported or its surfaces are changed
+    fn update_surfaces(&mut self) {
+        let crease_angle = lock(&shading::SHADING).crease_angle;
+        self.triangles = self.surfaces.iter().map(|surf| triangulate::triangulate(&self.points, surf)).collect();
+        self.corner_normals = shading::CornerNormals::new(&self.points, &self.surfaces, crease_angle);
+    }
+
+    // The triangles a surface splits into, as positions in the surface.  If they haven't been worked out since the
+    // surfaces last changed, they're worked out now instead
+    fn surface_triangles(&self, k: usize) -> Cow<'_, [[usize; 3]]> {
+        match self.triangles.get(k) {
+            Some(t) if self.triangles.len() == self.surfaces.len() => Cow::Borrowed(t),
+            _ => Cow::Owned(triangulate::triangulate(&self.points, &self.surfaces[k])),
+        }
+    }
+
+    // The normals at the corners of each surface, for the given crease angle.  If they weren't worked out with that
+    // angle, or since the surfaces last changed, they're worked out now instead
+    fn corner_normals(&self, crease_angle: f64) -> Cow<'_, [Vec<vector::Vec3>]> {
+        let cached = &self.corner_normals;
+        if cached.crease_angle == crease_angle && cached.normals.len() == self.surfaces.len() {
+            Cow::Borrowed(&cached.normals)
+        } else {
+            Cow::Owned(mesh::corner_normals(&self.points, &self.surfaces, crease_angle))
+        }
+    }
+
+    // The material a surface is drawn with
+    fn surface_material(&self, k: usize) -> &material::Material {
+        self.surface_materials.get(k).unwrap_or(&self.material)
+    }
+
+    // The colour a point is drawn in
+    fn point_colour(&self, n: usize) -> &str {
+        self.point_colours.get(n).unwrap_or(&self.material.point_colour)
+    }
 }
 
-#[derive(PartialEq)]
-struct PaintObject {
-    name: String,
-    mid_z: f64, // Z depth of an object's mid point
+// A part of the scene which is drawn in Z depth order.  Objects are given as positions in the list being drawn
+#[derive(Clone, Copy)]
+enum PaintPart {
+    Surface(usize, usize), // A surface of an object
+    Edge(usize, usize),    // An edge of an object
+    Point(usize, usize),   // A point of an object
+    Segment(usize),        // A ground grid segment
+}
+
+struct PaintItem {
+    part: PaintPart,
+    depth: f64, // Z depth of the part's middle, or of the surface it's drawn over
+}
+
+// The layout of the graph area, and how world space co-ordinates map onto it
+#[derive(Clone, Copy, PartialEq)]
+struct View {
+    graph_width: f64,
+    graph_height: f64,
+    border: f64,
+    left: f64, // Left edge of the background grid, inside the border
+    top: f64,  // Top edge of the background grid, inside the border
+    center_x: f64,
+    center_y: f64,
+    step: f64,       // The number of pixels per world space unit
+    line_width: f64, // The width of lines, and the radius of points
 }
 
-impl PaintObject {
-    pub fn new(name: String, mid_z: f64) -> Self {
-        PaintObject {
-            name,
-            mid_z
+impl View {
+    // Lays out a graph area of the given size
+    fn new(width: f64, height: f64) -> View {
+        let border = 2.0;
+        let gap = 3.0;
+        let graph_width = width;
+        let graph_height = height - 1.0;
+        View {
+            graph_width,
+            graph_height,
+            border,
+            left: border + gap,
+            top: border + gap,
+            center_x: graph_width / 2.0,
+            center_y: graph_height / 2.0,
+            step: graph_width.min(graph_height) / 30.0,
+            line_width: 1.0,
+        }
+    }
+
+    // Returns the position in the graph area of a world space point
+    fn project(&self, p: &Point) -> (f64, f64) {
+        (self.center_x + (p.x * self.step), self.center_y - (p.y * self.step))
+    }
+
+    // Returns the same view, scaled to fit a graph area of a different size.  The scene stays centred, and if the
+    // shape of the area changes, it's scaled to fit the narrower direction
+    fn resized(&self, graph_width: f64, graph_height: f64) -> View {
+        let k = (graph_width / self.graph_width).min(graph_height / self.graph_height);
+        View {
+            graph_width,
+            graph_height,
+            border: self.border * k,
+            left: self.left * k,
+            top: self.top * k,
+            center_x: graph_width / 2.0,
+            center_y: graph_height / 2.0,
+            step: self.step * k,
+            line_width: self.line_width * k,
         }
     }
 }
@@ -90,24 +264,36 @@ const IDENTITY_MATRIX: Matrix = [
     1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
 ];
 const SOURCE_URL: &str = "https://github.com/justinclift/rust_canvas_2d";
+const GRID_COLOUR: &str = "rgb(220, 220, 220)";
+
+// The canvas size assumed when exporting before anything has been drawn
+const DEFAULT_WIDTH: f64 = 1024.0;
+const DEFAULT_HEIGHT: f64 = 768.0;
 const DEBUG: bool = false;
 
+// How much of the graph area fitting to view fills, leaving a margin around the objects
+const FIT_FILL: f64 = 0.9;
+
+// The number of parts the fit to view animation is split into
+const FIT_PARTS: i32 = 12;
+
+// The most frames which can be recorded in a turntable animation
+const MAX_TURNTABLE_FRAMES: u32 = 720;
+
+// The id of the canvas element drawn on
+const CANVAS_ID: &str = "mycanvas";
+
 thread_local! {
-    pub static CANVAS: web_sys::HtmlCanvasElement = document()
-        .get_element_by_id("mycanvas")
-        .unwrap()
-        .dyn_into::<web_sys::HtmlCanvasElement>()
-        .unwrap();
+    // The canvas being drawn on.  This is looked up by wasm_main()
+    static CANVAS: RefCell<Option<web_sys::HtmlCanvasElement>> = const { RefCell::new(None) };
 }
 
 lazy_static! {
     // Initialise some shared state variables (aka globals)
-    static ref GRAPH_WIDTH: Arc<Mutex<f64>> = Arc::new(Mutex::new(0.0));
-    static ref HEIGHT: Arc<Mutex<f64>> = Arc::new(Mutex::new(0.0));
     static ref HIGHLIGHT_SOURCE: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
     static ref OP_TEXT: Arc<Mutex<String>> = Arc::new(Mutex::new(String::new()));
     static ref POINT_COUNTER: Arc<Mutex<i32>> = Arc::new(Mutex::new(0));
-    static ref PREV_KEY: Arc<Mutex<i32>> = Arc::new(Mutex::new(KeyVal::KeyNone as i32));
+    static ref PREV_KEY: Arc<Mutex<KeyVal>> = Arc::new(Mutex::new(KeyVal::KeyNone));
     static ref QUEUE_PARTS: Arc<Mutex<i32>> = Arc::new(Mutex::new(1));
     static ref QUEUE_OP: Arc<Mutex<OperationType>> = Arc::new(Mutex::new(OperationType::NOTHING));
     static ref STEP_SIZE: Arc<Mutex<f64>> = Arc::new(Mutex::new(15.0));
@@ -116,11 +302,23 @@ lazy_static! {
     static ref WORLD_SPACE: Arc<Mutex<HashMap<String, Object>>> = Arc::new(Mutex::new(HashMap::new()));
 
     // Initialise the transformation matrix from the identity matrix
-    static ref TRANSFORM_MATRIX: Arc<Mutex<Matrix>> = Arc::new(Mutex::new(IDENTITY_MATRIX.clone()));
+    static ref TRANSFORM_MATRIX: Arc<Mutex<Matrix>> = Arc::new(Mutex::new(IDENTITY_MATRIX));
+
+    // The overall transformation applied to the world space so far.  Used to draw things which move with the scene
+    // but aren't objects (eg the axes), and to place new objects into the scene the right way around
+    static ref SCENE_MATRIX: Arc<Mutex<Matrix>> = Arc::new(Mutex::new(IDENTITY_MATRIX));
+
+    // Counts the changes to anything drawn in the graph area (see scene_changed()), and the z-buffer image last drawn
+    // on the canvas along with the count and view it was drawn for, so it's only drawn again when something changes
+    static ref SCENE_REVISION: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+    static ref ZBUFFER_IMAGE: Arc<Mutex<Option<(u64, View, raster::RasterPainter)>>> = Arc::new(Mutex::new(None));
+
+    // The scene matrix once wasm_main() has set up the scene, which resetting the view goes back to
+    static ref HOME_MATRIX: Arc<Mutex<Matrix>> = Arc::new(Mutex::new(IDENTITY_MATRIX));
 
     // The point objects
     static ref OBJECT1: Object = Object {
-        colour: "lightblue".into(),
+        material: material::Material::new("lightblue"),
         points: vec![
             Point {num: 0, x: 0.0, y: 1.75, z: 1.0}, // Point 0 for this object
             Point {num: 1, x: 1.5, y: -1.75, z: 1.0}, // Point 1 for this object
@@ -141,11 +339,16 @@ lazy_static! {
             vec![0, 1, 2],
             vec![1, 2, 3],
         ],
+        surface_materials: vec![],
+        point_colours: vec![],
         mid_point: Point {num: 0, x: 0.0, y: 0.0, z: 0.0},
+        bounds: bounds::Bounds::from_points(&[]),
+        triangles: vec![],
+        corner_normals: shading::CornerNormals::default(),
     };
 
     static ref OBJECT2: Object = Object {
-        colour: "lightgreen".into(),
+        material: material::Material::new("lightgreen"),
         points: vec![
             Point {num: 0, x: 1.5, y: 1.5, z: -1.0},
 			Point {num: 1, x: 1.5, y: -1.5, z: -1.0},
@@ -159,11 +362,16 @@ lazy_static! {
         surfaces: vec![
             vec![0, 1, 2],
         ],
+        surface_materials: vec![],
+        point_colours: vec![],
         mid_point: Point {num: 0, x: 0.0, y: 0.0, z: 0.0},
+        bounds: bounds::Bounds::from_points(&[]),
+        triangles: vec![],
+        corner_normals: shading::CornerNormals::default(),
     };
 
     static ref OBJECT3: Object = Object {
-        colour: "indianred".into(),
+        material: material::Material::new("indianred"),
         points: vec![
 			Point {num: 0, x: 2.0, y: -2.0, z: 1.0},
 			Point {num: 1, x: 2.0, y: -4.0, z: 1.0},
@@ -188,27 +396,94 @@ lazy_static! {
 			vec![3, 0, 4],
 			vec![0, 1, 2, 3],
         ],
+        surface_materials: vec![],
+        point_colours: vec![],
         mid_point: Point {num: 0, x: 0.0, y: 0.0, z: 0.0},
+        bounds: bounds::Bounds::from_points(&[]),
+        triangles: vec![],
+        corner_normals: shading::CornerNormals::default(),
     };
 }
 
 // * Helper functions, as the web_sys pieces don't seem capable of being stored in globals *
-fn window() -> web_sys::Window {
-    web_sys::window().expect("no global `window` exists")
+fn window() -> Result<web_sys::Window, Error> {
+    web_sys::window().ok_or(Error::NoWindow)
+}
+
+fn document() -> Result<web_sys::Document, Error> {
+    window()?.document().ok_or(Error::NoDocument)
+}
+
+// Converts a position in the browser window to a position on the canvas.  Both are in CSS pixels, which is what the
+// canvas layout uses no matter what the devicePixelRatio is
+fn canvas_position(client_x: f64, client_y: f64) -> (f64, f64) {
+    CANVAS.with(|c| match c.borrow().as_ref() {
+        Some(canvas) => {
+            let r = canvas.get_bounding_client_rect();
+            (client_x - r.left(), client_y - r.top())
+        }
+        None => (client_x, client_y),
+    })
+}
+
+// Returns true if a position in the browser window is over the source code link, at the bottom of the info panel
+fn over_source_link(client_x: f64, client_y: f64) -> bool {
+    let (x, y) = canvas_position(client_x, client_y);
+    match lock(&layout::LAYOUT).panel {
+        Some(panel) => panel.contains(x, y) && y > panel.y + panel.height - 40.0,
+        None => false,
+    }
 }
 
-fn document() -> web_sys::Document {
-    window()
-        .document()
-        .expect("should have a document on window")
+// Finds the canvas element with the given id
+fn find_canvas(id: &str) -> Result<web_sys::HtmlCanvasElement, Error> {
+    document()?
+        .get_element_by_id(id)
+        .ok_or_else(|| Error::CanvasNotFound(id.to_string()))?
+        .dyn_into::<web_sys::HtmlCanvasElement>()
+        .map_err(|_| Error::NotACanvas(id.to_string()))
 }
 
-// Main setup
+// Finds the canvas given in the wasm_main() options, which can be either the canvas element itself or its id
+fn canvas_option(options: &JsValue) -> Result<web_sys::HtmlCanvasElement, Error> {
+    let v = if options.is_object() { js_sys::Reflect::get(options, &"canvas".into())? } else { JsValue::UNDEFINED };
+    if v.is_undefined() || v.is_null() {
+        find_canvas(CANVAS_ID)
+    } else if let Some(id) = v.as_string() {
+        find_canvas(&id)
+    } else {
+        v.dyn_into::<web_sys::HtmlCanvasElement>()
+            .map_err(|_| Error::Invalid("The canvas option should be a canvas element or its id".to_string()))
+    }
+}
+
+// Main setup.  The options object is optional, and can have any of:
+//
+//   canvas      The canvas element to draw on, or its id.  Defaults to "mycanvas"
+//   panel       Where the info panel goes: "right" (the default), "left", "top", "bottom", or "hidden"
+//   panelSize   The size of the info panel.  Up to 1 is a share of the canvas (defaults to 0.25), larger is in pixels
+//   autoResize  Whether the canvas is resized to fill the window (the default), or kept at its CSS size.  When turned
+//               off, the canvas needs a width and height set by CSS
+//
+// The keyboard, mouse, and touch listeners are added here too, so nothing else needs setting up by the page.  Keys
+// typed into the page's own text boxes are left to them.  Fails if the canvas can't be found, or the options aren't
+// valid
 #[wasm_bindgen]
-pub fn wasm_main() {
+pub fn wasm_main(options: JsValue) -> Result<(), JsValue> {
+    // Show the details of any panics on the browser console, instead of an opaque "unreachable executed" error
+    console_error_panic_hook::set_once();
+
+    let canvas = canvas_option(&options)?;
+    let mut layout_options = lock(&layout::OPTIONS).clone();
+    layout::read_options(&options, &mut layout_options)?;
+    *lock(&layout::OPTIONS) = layout_options;
+    let first_run = !events::installed();
+    events::install(&canvas)?;
+    CANVAS.with(|c| *c.borrow_mut() = Some(canvas));
+
     // Add some objects to the world space
     {
-        let mut world_space = WORLD_SPACE.lock().unwrap();
+        let mut world_space = lock(&WORLD_SPACE);
         let z = import_object(&OBJECT1, 5.0, 3.0, 0.0);
         (*world_space).insert("ob1".to_string(), z);
 
@@ -224,205 +499,304 @@ pub fn wasm_main() {
 
     // Scale the objects up a bit
     {
-        let mut queue_op = QUEUE_OP.lock().unwrap();
+        let mut queue_op = lock(&QUEUE_OP);
         *queue_op = OperationType::SCALE;
     }
     {
-        let mut transform_matrix = TRANSFORM_MATRIX.lock().unwrap();
-        *transform_matrix = scale(&*transform_matrix, 2.0, 2.0, 2.0);
+        let mut transform_matrix = lock(&TRANSFORM_MATRIX);
+        *transform_matrix = scale(&transform_matrix, 2.0, 2.0, 2.0);
     }
     apply_transformation();
 
+    // The initial set up of the scene isn't something to undo
+    history::clear();
+    *lock(&HOME_MATRIX) = *lock(&SCENE_MATRIX);
+
     // Start a rotation going
     {
         set_up_operation(OperationType::ROTATE, 12, -25.0, 25.0, 0.0);
-        let mut prev_key = PREV_KEY.lock().unwrap();
-        *prev_key = KeyVal::KeyPageUp as i32;
+        let mut prev_key = lock(&PREV_KEY);
+        *prev_key = KeyVal::KeyPageUp;
     }
 
-    // Set up the render loop
+    // Set up the render loop.  When wasm_main() is called again, the loop which is already running carries on drawing
+    // onto the new canvas
+    if !first_run {
+        return Ok(());
+    }
     let f = Rc::new(RefCell::new(None));
     let g = f.clone();
     *g.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        render_frame();
-        req_anim_frame(f.borrow().as_ref().unwrap());
+        // Stop the render loop if something goes wrong, rather than repeating the same error every frame
+        let result = render_frame().and_then(|_| match f.borrow().as_ref() {
+            Some(next) => req_anim_frame(next),
+            None => Ok(()),
+        });
+        if let Err(e) = result {
+            web_sys::console::error_1(&e.to_string().into());
+        }
     }) as Box<dyn FnMut()>));
-    req_anim_frame(g.borrow().as_ref().unwrap());
+    if let Some(first) = g.borrow().as_ref() {
+        req_anim_frame(first)?;
+    }
+    Ok(())
 }
 
 // Apply each transformation, one small part at a time (this gives the animation effect)
 #[wasm_bindgen]
 pub fn apply_transformation() {
     {
-        let queue_op = QUEUE_OP.lock().unwrap();
-        let queue_parts = QUEUE_PARTS.lock().unwrap();
-        if (*queue_parts < 1 && *queue_op == OperationType::SCALE) || *queue_op == OperationType::NOTHING {
-            let mut op_text = OP_TEXT.lock().unwrap();
+        let queue_op = lock(&QUEUE_OP);
+        let queue_parts = lock(&QUEUE_PARTS);
+        let finished = *queue_op == OperationType::SCALE || *queue_op == OperationType::FIT;
+        if (*queue_parts < 1 && finished) || *queue_op == OperationType::NOTHING {
+            let mut op_text = lock(&OP_TEXT);
             *op_text = "Complete.".to_string();
+
+            // The operation has finished, so it can now be undone as a whole.  Gestures are recorded once the pointers
+            // are lifted instead
+            if !gestures::in_progress() {
+                history::commit_pending();
+            }
             return;
         }
     }
 
-    // Transform each object in the world space, using the defined global transform matrix
+    let step_matrix = *lock(&TRANSFORM_MATRIX);
+    transform_world(&step_matrix);
+
+    let mut queue_parts = lock(&QUEUE_PARTS);
+    *queue_parts -= 1;
+}
+
+// Transforms each object in the world space by the given matrix, recording it as part of the running operation
+fn transform_world(step_matrix: &Matrix) {
     let mut new_world_space: HashMap<String, Object> = HashMap::new();
     {
-        let world_space = WORLD_SPACE.lock().unwrap();
-        let transform_matrix = TRANSFORM_MATRIX.lock().unwrap();
+        let world_space = lock(&WORLD_SPACE);
         for (j, o) in &(*world_space) {
-
-            // Create a blank new object
-            let mut new_object = Object::new();
-
-            // Add the points to the new object, transformed from the original object
-            let mut new_points: Vec<Point> = Vec::new();
-            for orig_point in o.points.iter() {
-                new_points.push(transform(&*transform_matrix, *orig_point));
-            }
-            new_object.points = new_points;
-
-            // Copy the edges across from the original object
-            let mut new_edges: Vec<Edge> = Vec::new();
-            for orig_edge in o.edges.iter() {
-                let mut new_edge = Edge::new();
-                for p in orig_edge.iter() {
-                    new_edge.push(*p);
-                }
-                new_edges.push(new_edge);
-            }
-            new_object.edges = new_edges;
-
-            // Copy the surfaces across from the original object
-            let mut new_surfaces: Vec<Surface> = Vec::new();
-            for orig_surf in o.surfaces.iter() {
-                let mut new_surf = Surface::new();
-                for p in orig_surf.iter() {
-                    new_surf.push(*p);
-                }
-                new_surfaces.push(new_surf);
-            }
-            new_object.surfaces = new_surfaces;
-
-            // Copy the colour across from the original object
-            new_object.colour = o.colour.clone();
-
-            // Transform the mid point of the object.  In theory, this should mean the mid point can
-            // always be used for a simple (not-cpu-intensive) way to sort the objects in Z depth order
-            new_object.mid_point = transform(&*transform_matrix, o.mid_point);
+            let new_object = transform_object(o, step_matrix);
 
             // Add the transformed object to the new world space
             new_world_space.insert(j.to_string(), new_object);
         }
     }
 
+    // Note where everything was before the operation started, so the whole operation can be undone later
+    history::record_step();
+    {
+        let mut scene_matrix = lock(&SCENE_MATRIX);
+        *scene_matrix = matrix_mult(step_matrix, &scene_matrix);
+    }
+
     // Replace the original world space with the updated world space
-    let mut world_space = WORLD_SPACE.lock().unwrap();
+    let mut world_space = lock(&WORLD_SPACE);
     *world_space = new_world_space;
+    scene_changed();
+}
 
-    let mut queue_parts = QUEUE_PARTS.lock().unwrap();
-    *queue_parts -= 1;
+// Zooms and moves the scene so the named objects fill the graph area, or all the objects when no names are given.  The
+// change is animated, the same as the key operations.  Fails if an object isn't found
+#[wasm_bindgen]
+pub fn fit_view(names: Vec<String>) -> Result<(), JsValue> {
+    Ok(fit_to_view(&names)?)
+}
+
+fn fit_to_view(names: &[String]) -> Result<(), Error> {
+    let aabb = objects_aabb(names)?;
+    if aabb.is_empty() {
+        return Ok(());
+    }
+    let (size, centre) = (aabb.size(), aabb.centre());
+
+    // Work out the zoom which makes the box fill the graph area in the narrower direction.  The Z size of the box
+    // doesn't matter, as it points into the screen
+    let view = current_view();
+    let room_x = view.graph_width / 2.0 / view.step * FIT_FILL;
+    let room_y = view.graph_height / 2.0 / view.step * FIT_FILL;
+    let zoom = [(size[0] / 2.0, room_x), (size[1] / 2.0, room_y)]
+        .iter()
+        .filter(|(half, _)| *half > 0.0)
+        .map(|(half, room)| room / half)
+        .fold(f64::INFINITY, f64::min);
+    let zoom = if zoom.is_finite() { zoom } else { 1.0 };
+
+    // The whole change scales by the zoom and moves the centre of the box to the origin.  Each part scales by the
+    // same amount, then moves by whatever makes the parts add up to the whole change
+    stop_operation();
+    let parts = FIT_PARTS as f64;
+    let k = zoom.powf(1.0 / parts);
+    let part_move = |c: f64| {
+        if (zoom - 1.0).abs() < 1e-9 {
+            -c / parts
+        } else {
+            -zoom * c * (k - 1.0) / (zoom - 1.0)
+        }
+    };
+    let step = scale(&IDENTITY_MATRIX, k, k, k);
+    *lock(&TRANSFORM_MATRIX) = translate(&step, part_move(centre[0]), part_move(centre[1]), part_move(centre[2]));
+    *lock(&QUEUE_PARTS) = FIT_PARTS;
+    *lock(&OP_TEXT) = format!("Fit to view. Zoom: {:.2}", zoom);
+    *lock(&QUEUE_OP) = OperationType::FIT;
+    Ok(())
+}
+
+// Returns the axis aligned box around the named objects, or all of them when no names are given.  Fails if an object
+// isn't found
+fn objects_aabb(names: &[String]) -> Result<bounds::Aabb, Error> {
+    let world_space = lock(&WORLD_SPACE);
+    if names.is_empty() {
+        return Ok(world_space.values().fold(bounds::Aabb::empty(), |b, o| b.union(&o.bounds.aabb)));
+    }
+    let mut aabb = bounds::Aabb::empty();
+    for n in names {
+        aabb = aabb.union(&world_space.get(n).ok_or_else(|| Error::NotFound(n.clone()))?.bounds.aabb);
+    }
+    Ok(aabb)
+}
+
+// Puts the scene back the way wasm_main() set it up, undoing any rotation, zooming, and moving since.  This happens
+// straight away, rather than being animated
+#[wasm_bindgen]
+pub fn reset_view() {
+    stop_operation();
+    let inverse = match invert_matrix(&lock(&SCENE_MATRIX)) {
+        Some(m) => m,
+        None => return,
+    };
+    let matrix = matrix_mult(&lock(&HOME_MATRIX), &inverse);
+    history::transform(&matrix);
 }
 
 // Simple mouse handler watching for people clicking on the source code link
 #[wasm_bindgen]
-pub fn click_handler(cx: i32, cy: i32) {
+pub fn click_handler(cx: i32, cy: i32) -> Result<(), JsValue> {
     let client_x = cx as f64;
     let client_y = cy as f64;
-    let height;
-    let graph_width;
-    {
-        let h = HEIGHT.lock().unwrap();
-        height = *h;
-        let g = GRAPH_WIDTH.lock().unwrap();
-        graph_width = *g;
-    }
+    let hit = over_source_link(client_x, client_y);
     if DEBUG {
         web_sys::console::log_2(&"client_x: ".into(), &client_x.into());
         web_sys::console::log_2(&"client_y: ".into(), &client_y.into());
-        if client_x > graph_width && client_y > (height - 40.0) {
+        if hit {
             web_sys::console::log_1(&"URL hit!".into());
         }
     }
 
     // If the user clicks the source code URL area, open the URL
-    if client_x > graph_width && client_y > (height - 40.0) {
-        let w = web_sys::window().unwrap();
-        w.open_with_url_and_target(SOURCE_URL, "_blank");
+    if hit {
+        window()?.open_with_url_and_target(SOURCE_URL, "_blank")?;
     }
+    Ok(())
 }
 
-// Simple keyboard handler for catching the arrow, WASD, and numpad keys
-// Key value info can be found here: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/key/Key_Values
+// Runs the action for a key.  The key numbers are the KeyVal values.  Key presses are picked up by wasm_main() already,
+// so this is only needed to drive the viewer from other controls on the page
 #[wasm_bindgen]
-pub fn key_press_handler(mut key_val: i32) {
+pub fn key_press_handler(key_val: i32) {
+    if let Some(key) = KeyVal::from_i32(key_val) {
+        key_action(key);
+    }
+}
+
+// Starts (or stops) the operation for a key press
+fn key_action(mut key: KeyVal) {
     if DEBUG {
-        web_sys::console::log_2(&"Key is: ".into(), &key_val.into());
+        web_sys::console::log_2(&"Key is: ".into(), &(key as i32).into());
+    }
+
+    // These can be pressed repeatedly, so they're handled before the 2nd key press check below
+    match key {
+        KeyVal::KeyUndo => {
+            undo();
+            return;
+        }
+        KeyVal::KeyRedo => {
+            redo();
+            return;
+        }
+        KeyVal::KeyStop => {
+            stop_operation();
+            return;
+        }
+        KeyVal::KeyFitView => {
+            let _ = fit_to_view(&[]);
+            return;
+        }
+        KeyVal::KeyResetView => {
+            reset_view();
+            return;
+        }
+        _ => {}
     }
 
     // If a key is pressed for a 2nd time in a row, then stop the animated movement
     {
-        let prev_key = PREV_KEY.lock().unwrap();
-        let mut queue_op = QUEUE_OP.lock().unwrap();
-        if key_val == *prev_key && *queue_op != OperationType::NOTHING {
+        let prev_key = lock(&PREV_KEY);
+        let mut queue_op = lock(&QUEUE_OP);
+        if key == *prev_key && *queue_op != OperationType::NOTHING {
             *queue_op = OperationType::NOTHING;
+            history::commit_pending();
             return
         }
     }
 
     // If the plus or minus keys were pressed, increase the step size then cause the current operation to be recalculated
-    if key_val == KeyVal::KeyMinus as i32 {
-        let mut stp = STEP_SIZE.lock().unwrap();
-        *stp -= 5.0;
-        let prev_key = PREV_KEY.lock().unwrap();
-        key_val = *prev_key;
-    } else if key_val == KeyVal::KeyPlus as i32 {
-        let mut stp = STEP_SIZE.lock().unwrap();
-        *stp += 5.0;
-        let prev_key = PREV_KEY.lock().unwrap();
-        key_val = *prev_key;
+    match key {
+        KeyVal::KeyMinus => {
+            *lock(&STEP_SIZE) -= 5.0;
+            key = *lock(&PREV_KEY);
+        }
+        KeyVal::KeyPlus => {
+            *lock(&STEP_SIZE) += 5.0;
+            key = *lock(&PREV_KEY);
+        }
+        _ => {}
     }
 
     // Set up translate and rotate operations
-    // FIXME: This should use a match instead, but we'd need to implement stuff for KeyVal to make
-    //        that work.  Maybe later.
-    if key_val == KeyVal::KeyMoveLeft as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::TRANSLATE, 12, -*stp / 2.0, 0.0, 0.0);
-    } else if key_val == KeyVal::KeyMoveRight as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::TRANSLATE, 12, *stp/2.0, 0.0, 0.0);
-    } else if key_val == KeyVal::KeyMoveUp as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::TRANSLATE, 12, 0.0, *stp/2.0, 0.0);
-    } else if key_val == KeyVal::KeyMoveDown as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::TRANSLATE, 12, 0.0, -*stp/2.0, 0.0);
-    } else if key_val == KeyVal::KeyRotateLeft as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::ROTATE, 12, 0.0, -*stp, 0.0);
-    } else if key_val == KeyVal::KeyRotateRight as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::ROTATE, 12, 0.0, *stp, 0.0);
-    } else if key_val == KeyVal::KeyRotateUp as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::ROTATE, 12, -*stp, 0.0, 0.0);
-    } else if key_val == KeyVal::KeyRotateDown as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::ROTATE, 12, *stp, 0.0, 0.0);
-    } else if key_val == KeyVal::KeyPageUp as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::ROTATE, 12, -*stp, *stp, 0.0);
-    } else if key_val == KeyVal::KeyPageDown as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::ROTATE, 12, *stp, *stp, 0.0);
-    } else if key_val == KeyVal::KeyHome as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::ROTATE, 12, -*stp, -*stp, 0.0);
-    } else if key_val == KeyVal::KeyEnd as i32 {
-        let stp = STEP_SIZE.lock().unwrap();
-        set_up_operation(OperationType::ROTATE, 12, *stp, -*stp, 0.0);
-    }
-    let mut prev_key = PREV_KEY.lock().unwrap();
-    *prev_key = key_val;
+    let stp = *lock(&STEP_SIZE);
+    match key {
+        KeyVal::KeyMoveLeft => set_up_operation(OperationType::TRANSLATE, 12, -stp / 2.0, 0.0, 0.0),
+        KeyVal::KeyMoveRight => set_up_operation(OperationType::TRANSLATE, 12, stp / 2.0, 0.0, 0.0),
+        KeyVal::KeyMoveUp => set_up_operation(OperationType::TRANSLATE, 12, 0.0, stp / 2.0, 0.0),
+        KeyVal::KeyMoveDown => set_up_operation(OperationType::TRANSLATE, 12, 0.0, -stp / 2.0, 0.0),
+        KeyVal::KeyRotateLeft => set_up_operation(OperationType::ROTATE, 12, 0.0, -stp, 0.0),
+        KeyVal::KeyRotateRight => set_up_operation(OperationType::ROTATE, 12, 0.0, stp, 0.0),
+        KeyVal::KeyRotateUp => set_up_operation(OperationType::ROTATE, 12, -stp, 0.0, 0.0),
+        KeyVal::KeyRotateDown => set_up_operation(OperationType::ROTATE, 12, stp, 0.0, 0.0),
+        KeyVal::KeyPageUp => set_up_operation(OperationType::ROTATE, 12, -stp, stp, 0.0),
+        KeyVal::KeyPageDown => set_up_operation(OperationType::ROTATE, 12, stp, stp, 0.0),
+        KeyVal::KeyHome => set_up_operation(OperationType::ROTATE, 12, -stp, -stp, 0.0),
+        KeyVal::KeyEnd => set_up_operation(OperationType::ROTATE, 12, stp, -stp, 0.0),
+        _ => {}
+    }
+    *lock(&PREV_KEY) = key;
+}
+
+// Replaces all the key bindings with the ones in a JSON object, which maps each key to the name of its action, eg
+// {"w": "move_up", "Ctrl+Z": "undo"}.  Fails without changing anything if a key or action isn't known
+#[wasm_bindgen]
+pub fn set_key_bindings(json: &str) -> Result<(), JsValue> {
+    Ok(bindings::load(json)?)
+}
+
+// Returns the key bindings as JSON, in the form set_key_bindings() takes
+#[wasm_bindgen]
+pub fn key_bindings() -> String {
+    bindings::save()
+}
+
+// Binds a key (eg "Shift+ArrowLeft") to an action, replacing what it was bound to before.  An empty action unbinds
+// the key instead
+#[wasm_bindgen]
+pub fn bind_key(key: &str, action: &str) -> Result<(), JsValue> {
+    Ok(bindings::bind(key, action)?)
+}
+
+// Puts back the default key bindings
+#[wasm_bindgen]
+pub fn reset_key_bindings() {
+    bindings::reset();
 }
 
 // Simple mouse handler watching for people moving the mouse over the source code link
@@ -436,15 +810,9 @@ pub fn move_handler(cx: i32, cy: i32) {
     }
 
     // If the mouse is over the source code link, let the frame renderer know to draw the url in bold
-    let height = HEIGHT.lock().unwrap();
-    let graph_width = GRAPH_WIDTH.lock().unwrap();
-    if (client_x > *graph_width) && (client_y > *height - 40.0) {
-        let mut high_light_source = HIGHLIGHT_SOURCE.lock().unwrap();
-        *high_light_source = true;
-    } else {
-        let mut high_light_source = HIGHLIGHT_SOURCE.lock().unwrap();
-        *high_light_source = false;
-    }
+    let hit = over_source_link(client_x, client_y);
+    let mut high_light_source = lock(&HIGHLIGHT_SOURCE);
+    *high_light_source = hit;
 }
 
 // Simple mouse handler watching for mouse wheel events
@@ -459,238 +827,971 @@ pub fn wheel_handler(val: i32) {
     }
     set_up_operation(OperationType::SCALE, 12, scale_size, scale_size, scale_size);
     {
-        let mut prev_key = PREV_KEY.lock().unwrap();
-        *prev_key = KeyVal::KeyNone as i32;
+        let mut prev_key = lock(&PREV_KEY);
+        *prev_key = KeyVal::KeyNone;
     }
 }
 
-// Do the rendering here
-fn render_frame() {
-    CANVAS.with(|f| {
-        let canvas = &*f;
-        let mut width = canvas.width() as f64;
-        let mut height = canvas.height() as f64;
-        {
-            // Update the height in the global
-            let mut h = HEIGHT.lock().unwrap();
-            *h = height;
+// Returns an SVG image of the graph area, drawn the same way as on screen.  This doesn't need a canvas, so works
+// headless too
+#[wasm_bindgen]
+pub fn export_svg() -> String {
+    let view = current_view();
+    let mut svg = svg::SvgPainter::new(view.graph_width, view.graph_height);
+    draw_scene(&mut svg, &view);
+    svg.finish()
+}
+
+// Returns a PNG image of the graph area at the given size, drawn with the software renderer.  The scene is scaled up
+// or down from how it looks on screen, so it can be rendered larger than the window.  This works headless too
+#[wasm_bindgen]
+pub fn export_png(width: u32, height: u32) -> Result<Vec<u8>, JsValue> {
+    Ok(render_png(width, height)?)
+}
+
+// Returns the surfaces of every object as a binary STL model, in their current positions.  Surfaces are split into
+// triangles, as STL files can only hold triangles
+#[wasm_bindgen]
+pub fn export_stl() -> Vec<u8> {
+    let world_space = lock(&WORLD_SPACE);
+    let mut names: Vec<&String> = world_space.keys().collect();
+    names.sort();
+    scene_file::write_stl(names.into_iter().map(|n| &world_space[n]))
+}
+
+// Returns every object as a JSON scene, in the form load_scene() reads, including their edges and materials.  The
+// objects are turned back from the current view to the scene's own axes, so loading the scene again puts them where
+// they are now
+#[wasm_bindgen]
+pub fn export_scene() -> String {
+    let world_space = lock(&WORLD_SPACE);
+    let unplace = invert_matrix(&lock(&SCENE_MATRIX)).unwrap_or(IDENTITY_MATRIX);
+    let mut names: Vec<&String> = world_space.keys().collect();
+    names.sort();
+    let objects: Vec<(&String, Object)> =
+        names.into_iter().map(|n| (n, transform_object(&world_space[n], &unplace))).collect();
+    scene_file::write_json(objects.iter().map(|(n, ob)| (*n, ob)))
+}
+
+// Records the scene turning around the Y axis, as an animated image the size of the graph area.  The format is "gif"
+// or "apng", and each frame is turned by the given degrees from the one before.  The scene is left as it was
+#[wasm_bindgen]
+pub fn record_turntable(frames: u32, degrees_per_frame: f64, format: &str) -> Result<Vec<u8>, JsValue> {
+    let view = current_view();
+    let width = view.graph_width.round() as u32;
+    let height = view.graph_height.round() as u32;
+    Ok(render_turntable(frames, degrees_per_frame, format, width, height)?)
+}
+
+// Reverts the most recent change to the world space.  Returns false if there was nothing to undo
+#[wasm_bindgen]
+pub fn undo() -> bool {
+    stop_operation();
+    history::undo()
+}
+
+// Re-applies the most recently undone change to the world space.  Returns false if there was nothing to redo
+#[wasm_bindgen]
+pub fn redo() -> bool {
+    stop_operation();
+    history::redo()
+}
+
+// Adds a copy of an object to the world space, offset by the given X, Y, and Z values.  Fails if there's no object
+// with the given name, or the new name is already in use
+#[wasm_bindgen]
+pub fn copy_object(name: &str, new_name: &str, x: f64, y: f64, z: f64) -> Result<(), JsValue> {
+    let copy;
+    {
+        let world_space = lock(&WORLD_SPACE);
+        if world_space.contains_key(new_name) {
+            return Err(Error::NameInUse(new_name.to_string()).into());
         }
+        copy = match world_space.get(name) {
+            Some(o) => transform_object(o, &translate(&IDENTITY_MATRIX, x, y, z)),
+            None => return Err(Error::NotFound(name.to_string()).into()),
+        };
+    }
+    insert_object(new_name, copy);
+    Ok(())
+}
 
-        // Handle window resizing
-        let current_body_width = window().inner_width().unwrap().as_f64().unwrap();
-        let current_body_height = window().inner_height().unwrap().as_f64().unwrap();
-        if current_body_width != width || current_body_height != height {
-            width = current_body_width;
-            height = current_body_height;
-            canvas.set_attribute("width", &width.to_string());
-            canvas.set_attribute("height", &height.to_string());
+// Turns the world space axes on or off
+#[wasm_bindgen]
+pub fn set_axes_visible(visible: bool) {
+    lock(&axes::AXES).visible = visible;
+    scene_changed();
+}
+
+// Sets how far the axes reach either side of the origin, and the distance between their tick marks.  Fails if either
+// isn't a number above 0
+#[wasm_bindgen]
+pub fn set_axes_range(length: f64, tick_spacing: f64) -> Result<(), JsValue> {
+    for (what, v) in [("length", length), ("tick spacing", tick_spacing)].iter() {
+        if !(v.is_finite() && *v > 0.0) {
+            return Err(Error::Invalid(format!("The axes {} should be a number above 0, not {}", what, v)).into());
         }
+    }
+    let mut settings = lock(&axes::AXES);
+    settings.length = length;
+    settings.tick_spacing = tick_spacing;
+    scene_changed();
+    Ok(())
+}
 
-        // Get the 2D context for the canvas
-        let ctx = canvas.get_context("2d").unwrap().unwrap().dyn_into::<web_sys::CanvasRenderingContext2d>().unwrap();
+// Turns the orientation gizmo on or off
+#[wasm_bindgen]
+pub fn set_gizmo_visible(visible: bool) {
+    lock(&axes::AXES).gizmo = visible;
+    scene_changed();
+}
 
-        // Setup useful variables
-        let border = 2.0;
-        let gap = 3.0;
-        let left = border + gap;
-        let top = border + gap;
-        let mut graph_width = GRAPH_WIDTH.lock().unwrap();
-        *graph_width = width * 0.75;
-        let graph_height = height - 1.0;
-        let center_x= *graph_width / 2.0;
-        let center_y = graph_height / 2.0;
+// Turns the world space ground grid on or off
+#[wasm_bindgen]
+pub fn set_ground_grid_visible(visible: bool) {
+    lock(&ground_grid::GROUND_GRID).visible = visible;
+    scene_changed();
+}
 
-        // Clear the background
-        ctx.set_fill_style(&"white".into());
-        ctx.fill_rect(0.0, 0.0, width, height);
+// Returns the bounding volumes of an object as JSON: its axis aligned box ("aabb", with "min" and "max" corners), its
+// oriented box ("obb", with "centre", "axes", and "halfSizes"), and its bounding sphere ("sphere", with "centre" and
+// "radius").  Fails if no object has the given name
+#[wasm_bindgen]
+pub fn object_bounds(name: &str) -> Result<String, JsValue> {
+    let world_space = lock(&WORLD_SPACE);
+    let ob = world_space.get(name).ok_or_else(|| Error::NotFound(name.to_string()))?;
+    Ok(ob.bounds.json().to_string())
+}
 
-        // Save the current graphics state - no clip region currently defined - as the default
-        ctx.save();
+// Returns the bounding volumes around all the objects, as JSON in the same form as object_bounds()
+#[wasm_bindgen]
+pub fn world_bounds() -> String {
+    bounds::around(lock(&WORLD_SPACE).values()).json().to_string()
+}
 
-        // Set the clip region so drawing only occurs in the display area
-        ctx.begin_path();
-        ctx.move_to(0.0, 0.0);
-        ctx.line_to(*graph_width, 0.0);
-        ctx.line_to(*graph_width, height);
-        ctx.line_to(0.0, height);
-        ctx.clip();
+// Turns the dashed outlines of each object's axis aligned box, oriented box, and bounding sphere on or off
+#[wasm_bindgen]
+pub fn set_bounds_visible(aabb: bool, obb: bool, sphere: bool) {
+    let mut overlay = lock(&bounds::OVERLAY);
+    overlay.aabb = aabb;
+    overlay.obb = obb;
+    overlay.sphere = sphere;
+    scene_changed();
+}
+
+// Sets up the ground grid.  The plane is one of "xz" (the default), "xy", or "yz".  The extent is how far the grid
+// reaches either side of the origin, the spacing is the distance between major grid lines, and the subdivisions are
+// the number of parts each major grid square is split into.  Fails if the plane isn't known, the extent or spacing
+// isn't a number above 0, or there are no subdivisions
+#[wasm_bindgen]
+pub fn set_ground_grid(plane: &str, extent: f64, spacing: f64, subdivisions: u32) -> Result<(), JsValue> {
+    let plane = ground_grid::GridPlane::from_name(plane)
+        .ok_or_else(|| Error::Invalid(format!("Unknown grid plane '{}'.  It should be xz, xy, or yz", plane)))?;
+    for (what, v) in [("extent", extent), ("spacing", spacing)].iter() {
+        if !(v.is_finite() && *v > 0.0) {
+            return Err(Error::Invalid(format!("The grid {} should be a number above 0, not {}", what, v)).into());
+        }
+    }
+    if subdivisions < 1 {
+        return Err(Error::Invalid("The grid should have at least 1 subdivision".to_string()).into());
+    }
+    let mut grid = lock(&ground_grid::GROUND_GRID);
+    grid.plane = plane;
+    grid.extent = extent;
+    grid.spacing = spacing;
+    grid.subdivisions = subdivisions;
+    scene_changed();
+    Ok(())
+}
+
+// Chooses how the scene is drawn.  The mode is "painter" (the default), which draws each surface, edge, and point
+// furthest away first, or "zbuffer", which uses the software renderer to depth test every pixel so objects which
+// overlap or pass through each other are drawn correctly.  The mode is used by export_png() and record_turntable()
+// too.  Fails if the mode isn't known
+#[wasm_bindgen]
+pub fn set_render_mode(mode: &str) -> Result<(), JsValue> {
+    Ok(select_render_mode(mode)?)
+}
 
-        // * Draw grid lines *
+// The same as set_render_mode(), for use outside the browser
+pub fn select_render_mode(mode: &str) -> Result<(), Error> {
+    let mode = zbuffer::RenderMode::from_name(mode)
+        .ok_or_else(|| Error::Invalid(format!("Unknown render mode '{}'.  It should be painter or zbuffer", mode)))?;
+    *lock(&zbuffer::RENDER_MODE) = mode;
+    Ok(())
+}
 
-        let step = width.min(height) / 30.0;
-        ctx.set_stroke_style(&"rgb(220, 220, 220)".into());
+// Returns the name of the current render mode
+#[wasm_bindgen]
+pub fn render_mode() -> String {
+    lock(&zbuffer::RENDER_MODE).name().to_string()
+}
 
-        // We use while loops here, because Rust doesn't seem able to iterate using an f64 step. eg .step_by(step)
-        // At least not yet: "the trait `std::iter::Step` is not implemented for `f64`"
+// Chooses how the surfaces are lit.  The mode is "none" (the default), which fills each surface with its plain colour,
+// "flat", which lights each surface evenly, "gouraud", which blends the light at the corners of each surface across
+// it, or "phong", which works out the light for each pixel.  Surfaces meeting at more than the crease angle (in
+// degrees, 30 if not given) aren't smoothed into each other.  The canvas approximates gouraud and phong shading with
+// gradients (and fills see-through surfaces with their overall colour), while export_png() and the zbuffer render
+// mode blend them exactly.  Fails if the mode isn't known, or the crease angle isn't a number from 0 to 180
+#[wasm_bindgen]
+pub fn set_shading(mode: &str, crease_degrees: Option<f64>) -> Result<(), JsValue> {
+    Ok(select_shading(mode, crease_degrees)?)
+}
 
-        // Vertical dashed lines
-        let mut i = left;
-        while i < *graph_width-step {
-            ctx.begin_path();
-            ctx.move_to(i+step, top);
-            ctx.line_to(i+step, graph_height);
-            ctx.stroke();
-            i += step;
+// The same as set_shading(), for use outside the browser
+pub fn select_shading(mode: &str, crease_degrees: Option<f64>) -> Result<(), Error> {
+    let mode = shading::ShadingMode::from_name(mode).ok_or_else(|| {
+        Error::Invalid(format!("Unknown shading '{}'.  It should be none, flat, gouraud, or phong", mode))
+    })?;
+    let crease_angle = crease_degrees.unwrap_or(mesh::DEFAULT_CREASE_ANGLE);
+    if !(0.0..=180.0).contains(&crease_angle) {
+        return Err(Error::Invalid(format!("The crease angle should be a number from 0 to 180, not {}", crease_angle)));
+    }
+    let changed = {
+        let mut shading = lock(&shading::SHADING);
+        shading.mode = mode;
+        let changed = shading.crease_angle != crease_angle;
+        shading.crease_angle = crease_angle;
+        changed
+    };
+
+    // The normals kept with each object depend on the crease angle, so they're worked out again when it changes
+    if changed {
+        for ob in lock(&WORLD_SPACE).values_mut() {
+            ob.corner_normals = shading::CornerNormals::new(&ob.points, &ob.surfaces, crease_angle);
         }
+    }
+    scene_changed();
+    Ok(())
+}
 
-        // Horizontal dashed lines
-        let mut i = top;
-        while i < graph_height-step {
-            ctx.begin_path();
-            ctx.move_to(left, i+step);
-            ctx.line_to(*graph_width-border, i+step);
-            ctx.stroke();
-            i += step;
+// Returns the name of the current shading mode
+#[wasm_bindgen]
+pub fn shading() -> String {
+    lock(&shading::SHADING).mode.name().to_string()
+}
+
+// Adds a generated shape to the world space.  The params are specific to each kind of shape, and any left out use
+// defaults.  Up to three extra values after the shape params give the X, Y, and Z position to place the shape at:
+//
+//   cube      size, divisions
+//   sphere    radius, segments, rings
+//   icosphere radius, subdivisions
+//   cylinder  radius, height, segments
+//   cone      radius, height, segments
+//   torus     major radius, minor radius, major segments, minor segments
+//   plane     width, depth, x divisions, z divisions
+//
+// Shapes have at most 256 segments or rings, and 200 divisions.  Fails if the kind of shape isn't known, or the name
+// is already in use
+#[wasm_bindgen]
+pub fn add_primitive(kind: &str, name: &str, params: Vec<f64>) -> Result<(), JsValue> {
+    let p = |i: usize, default: f64| -> f64 { params.get(i).copied().unwrap_or(default) };
+    let (ob, num_params) = primitives::by_name(kind, &params)
+        .ok_or_else(|| Error::Invalid(format!("Unknown kind of shape '{}'", kind)))?;
+    if lock(&WORLD_SPACE).contains_key(name) {
+        return Err(Error::NameInUse(name.to_string()).into());
+    }
+    let x = p(num_params, 0.0);
+    let y = p(num_params + 1, 0.0);
+    let z = p(num_params + 2, 0.0);
+    insert_object(name, place_in_scene(&import_object(&ob, x, y, z)));
+    Ok(())
+}
+
+// Plots the function z = f(x, y) as a surface over the given range, and adds it to the world space.  The resolution is
+// the number of divisions along each side of the grid.  Places where the function has no valid value are left as holes.
+// Fails if the expression can't be parsed, the name is already in use, or either range isn't a pair of numbers with
+// the minimum below the maximum
+#[wasm_bindgen]
+pub fn plot_function(
+    name: &str,
+    expression: &str,
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+    resolution: u32,
+) -> Result<(), JsValue> {
+    let f = plot::parse(expression).map_err(|e| Error::Invalid(format!("Invalid expression: {}", e)))?;
+    for (axis, min, max) in [("x", x_min, x_max), ("y", y_min, y_max)].iter() {
+        if !((max - min).is_finite() && min < max) {
+            let msg = format!("The {} range should be from a number to a larger one, not {} to {}", axis, min, max);
+            return Err(Error::Invalid(msg).into());
         }
+    }
+    if lock(&WORLD_SPACE).contains_key(name) {
+        return Err(Error::NameInUse(name.to_string()).into());
+    }
+    let ob = plot::surface(&f, x_min, x_max, y_min, y_max, resolution);
+    insert_object(name, place_in_scene(&import_object(&ob, 0.0, 0.0, 0.0)));
+    Ok(())
+}
 
-        // Sort the world space objects by mid point Z depth order
-        let mut paint_order: Vec<PaintObject> = vec![];
-        {
-            let world_space = WORLD_SPACE.lock().unwrap();
-            for (idx, obj) in &(*world_space) {
-                paint_order.push(PaintObject::new(idx.clone(), obj.mid_point.z.clone()));
-            }
+// Adds a 3D scatter plot of CSV data to the world space, as a single object with the given name whose points are drawn
+// as round markers.  Each row needs x, y, and z values, with an optional fourth value for its colour.  The plot is
+// fitted to the part of the scene in view.  Returns a message for each row which couldn't be used.  Fails if there
+// are no rows which can be used, or the name is already in use
+#[wasm_bindgen]
+pub fn load_csv(name: &str, text: &str) -> Result<Vec<String>, JsValue> {
+    let data = scatter::parse_csv(text);
+    if data.is_empty() {
+        let mut msg = "No data points found in the CSV".to_string();
+        if !data.errors.is_empty() {
+            msg = format!("{}: {}", msg, data.errors.join(", "));
         }
-        paint_order.sort_by(|a, b| b.mid_z.partial_cmp(&a.mid_z).unwrap());
-
-        // Draw the objects
-        let mut point_x;
-        let mut point_y;
-        let world_space = WORLD_SPACE.lock().unwrap();
-        for z in paint_order {
-            let obj = &(*world_space)[&z.name];
-            ctx.set_fill_style(&format!("{}", obj.colour).into());
-            for surf in obj.surfaces.iter() {
-                for (m, n) in surf.iter().enumerate() {
-                    point_x = obj.points[*n as usize].x;
-                    point_y = obj.points[*n as usize].y;
-                    if m == 0 {
-                        ctx.begin_path();
-                        ctx.move_to(center_x + (point_x * step), center_y + ((point_y * step) * -1.0));
-                    } else {
-                        ctx.line_to(center_x + (point_x * step), center_y + ((point_y * step) * -1.0));
-                    }
-                }
-                ctx.close_path();
-                ctx.fill();
-            }
+        return Err(Error::Invalid(msg).into());
+    }
+
+    // The plot is centred in the graph area, and sized so it stays inside it whichever way it's turned.  The scene has
+    // been turned and zoomed since it was set up, so the centre and size are turned back into the scene's own axes
+    let view = current_view();
+    let room = view.graph_width.min(view.graph_height) / 2.0 / view.step * FIT_FILL / 3.0_f64.sqrt();
+    let unplace = invert_matrix(&lock(&SCENE_MATRIX)).unwrap_or(IDENTITY_MATRIX);
+    let origin = Point { num: 0, x: 0.0, y: 0.0, z: 0.0 };
+    let centre = transform(&unplace, origin);
+    let side = transform(&unplace, Point { x: room, ..origin });
+    let half_size = ((side.x - centre.x).powi(2) + (side.y - centre.y).powi(2) + (side.z - centre.z).powi(2)).sqrt();
+    let ob = import_object(&data.plot(half_size), centre.x, centre.y, centre.z);
+    insert_objects(vec![(name.to_string(), ob)])?;
+    Ok(data.errors)
+}
 
-            // Draw the edges
-            ctx.set_stroke_style(&"black".into());
-            ctx.set_fill_style(&"black".into());
-            ctx.set_line_width(1.0);
-            let mut point1_x;
-            let mut point1_y;
-            let mut point2_x;
-            let mut point2_y;
-            for edge in obj.edges.iter() {
-                point1_x = obj.points[edge[0 as usize] as usize].x;
-                point1_y = obj.points[edge[0 as usize] as usize].y;
-                point2_x = obj.points[edge[1 as usize] as usize].x;
-                point2_y = obj.points[edge[1 as usize] as usize].y;
-                ctx.begin_path();
-                ctx.move_to(center_x+(point1_x*step), center_y+((point1_y*step)*-1.0));
-                ctx.line_to(center_x+(point2_x*step), center_y+((point2_y*step)*-1.0));
-                ctx.stroke();
+// Adds the objects from a scene file to the world space.  The format is "json", "obj", or "stl".  OBJ and STL models
+// are added as a single object with the given name.  Returns the names of the objects added
+#[wasm_bindgen]
+pub fn load_scene(name: &str, format: &str, data: &[u8]) -> Result<Vec<String>, JsValue> {
+    Ok(read_scene(name, format, data)?)
+}
+
+// The same as load_scene(), for use outside the browser
+pub fn read_scene(name: &str, format: &str, data: &[u8]) -> Result<Vec<String>, Error> {
+    let objects = scene_file::parse(name, format, data)?;
+    let names = objects.iter().map(|(n, _)| n.clone()).collect();
+    insert_objects(objects)?;
+    Ok(names)
+}
+
+// Rotates the whole scene around the X, Y, and Z axes (in that order) by the given degrees, then zooms it by the given
+// amount.  This happens straight away, rather than being animated.  Fails if the degrees aren't numbers, or the zoom
+// isn't a number above 0
+#[wasm_bindgen]
+pub fn transform_scene(x_degrees: f64, y_degrees: f64, z_degrees: f64, zoom: f64) -> Result<(), JsValue> {
+    Ok(turn_scene(x_degrees, y_degrees, z_degrees, zoom)?)
+}
+
+// The same as transform_scene(), for use outside the browser
+pub fn turn_scene(x_degrees: f64, y_degrees: f64, z_degrees: f64, zoom: f64) -> Result<(), Error> {
+    for (axis, degrees) in [("X", x_degrees), ("Y", y_degrees), ("Z", z_degrees)].iter() {
+        if !degrees.is_finite() {
+            return Err(Error::Invalid(format!("The {} rotation should be a number of degrees, not {}", axis, degrees)));
+        }
+    }
+    if !(zoom.is_finite() && zoom > 0.0) {
+        return Err(Error::Invalid(format!("The zoom should be a number above 0, not {}", zoom)));
+    }
+    stop_operation();
+    let mut m = rotate_around_x(&IDENTITY_MATRIX, x_degrees);
+    m = rotate_around_y(&m, y_degrees);
+    m = rotate_around_z(&m, z_degrees);
+    m = scale(&m, zoom, zoom, zoom);
+    history::transform(&m);
+    Ok(())
+}
+
+// Removes an object from the world space.  Fails if no object has the given name
+#[wasm_bindgen]
+pub fn remove_object(name: &str) -> Result<(), JsValue> {
+    let object = lock(&WORLD_SPACE).remove(name).ok_or_else(|| Error::NotFound(name.to_string()))?;
+    history::push(history::Command::Remove { name: name.to_string(), object });
+    Ok(())
+}
+
+// Changes the colour of an object's surfaces, which is the diffuse colour of its material.  Surfaces with their own
+// materials keep their own colours.  Fails if the colour isn't a CSS colour, or no object has the given name
+#[wasm_bindgen]
+pub fn set_object_colour(name: &str, colour: &str) -> Result<(), JsValue> {
+    material::check_colour("colour", colour).map_err(Error::Invalid)?;
+    let old;
+    {
+        let mut world_space = lock(&WORLD_SPACE);
+        match world_space.get_mut(name) {
+            Some(o) => {
+                old = std::mem::replace(&mut o.material.diffuse, colour.to_string());
             }
+            None => return Err(Error::NotFound(name.to_string()).into()),
+        }
+    }
+    history::push(history::Command::Colour { name: name.to_string(), old, new: colour.to_string() });
+    Ok(())
+}
+
+// Changes an object's material.  The material is given as a JSON object with any of the keys "diffuse", "opacity",
+// "specular", "shininess", "emissive", "edge_colour", "edge_width", "point_colour", and "point_size" (see the
+// material module), and the ones left out stay as they were.  The changes apply to the materials of the object's
+// surfaces too, eg so a plot whose surfaces have their own colours can be made see-through as a whole, and a
+// "point_colour" replaces any colours the points have of their own.  Fails if no object has the given name, or the
+// JSON isn't a valid material
+#[wasm_bindgen]
+pub fn set_object_material(name: &str, json: &str) -> Result<(), JsValue> {
+    let v: serde_json::Value =
+        serde_json::from_str(json).map_err(|e| Error::Invalid(format!("Invalid material JSON: {}", e)))?;
+    let old = lock(&WORLD_SPACE).get(name).cloned().ok_or_else(|| Error::NotFound(name.to_string()))?;
+    let mut ob = old.clone();
+    ob.material = ob.material.with_json(&v).map_err(Error::Invalid)?;
+    for m in ob.surface_materials.iter_mut() {
+        *m = m.with_json(&v).map_err(Error::Invalid)?;
+    }
+    if v.get("point_colour").is_some() {
+        ob.point_colours.clear();
+    }
+    let name = name.to_string();
+    history::execute(history::Command::Group(vec![
+        history::Command::Remove { name: name.clone(), object: old },
+        history::Command::Import { name, object: ob },
+    ]));
+    Ok(())
+}
 
-            // Draw the points on the graph
-            let mut px;
-            let mut py;
-            for point in obj.points.iter() {
-                px = center_x + (point.x * step);
-                py = center_y + ((point.y * step) * -1.0);
-                ctx.begin_path();
-                ctx.arc(px, py, 1.0, 0.0, 2.0 * std::f64::consts::PI);
-                ctx.fill();
+// Returns an object's material, as a JSON object in the form set_object_material() takes.  Fails if no object has the
+// given name
+#[wasm_bindgen]
+pub fn object_material(name: &str) -> Result<String, JsValue> {
+    let world_space = lock(&WORLD_SPACE);
+    let ob = world_space.get(name).ok_or_else(|| Error::NotFound(name.to_string()))?;
+    Ok(ob.material.json().to_string())
+}
+
+// Returns the problems with an object's edges and surfaces, such as point numbers which are out of range, surfaces
+// with no area or which repeat another, edges shared by more than two surfaces, neighbouring surfaces facing opposite
+// ways, and unused points.  Fails if no object has the given name
+#[wasm_bindgen]
+pub fn validate_object(name: &str) -> Result<Vec<String>, JsValue> {
+    let world_space = lock(&WORLD_SPACE);
+    let ob = world_space.get(name).ok_or_else(|| Error::NotFound(name.to_string()))?;
+    Ok(mesh::validate(ob).iter().map(|p| p.to_string()).collect())
+}
+
+// Repairs an object's edges and surfaces.  Points closer together than the weld tolerance are merged (a tolerance of 0
+// only merges points in the same place), edges and surfaces which are invalid or repeated are dropped, surfaces are
+// flipped to face the same way as their neighbours, and unused points are removed (unless the object is only points).
+// Returns the problems which are left.  Fails if no object has the given name
+#[wasm_bindgen]
+pub fn repair_object(name: &str, weld_tolerance: f64) -> Result<Vec<String>, JsValue> {
+    stop_operation();
+    let old = lock(&WORLD_SPACE).get(name).cloned().ok_or_else(|| Error::NotFound(name.to_string()))?;
+    let mut ob = old.clone();
+    let fatal: Vec<mesh::Problem> = mesh::validate(&ob).into_iter().filter(|p| p.is_fatal()).collect();
+    mesh::drop_invalid_faces(&mut ob, &fatal);
+    mesh::weld_vertices(&mut ob, weld_tolerance);
+    let invalid = mesh::validate(&ob);
+    mesh::drop_invalid_faces(&mut ob, &invalid);
+    mesh::reorient_windings(&mut ob);
+    mesh::remove_unused_points(&mut ob);
+    ob.update_surfaces();
+    let problems = mesh::validate(&ob).iter().map(|p| p.to_string()).collect();
+
+    // Recorded as swapping the old object for the new one, so the repair can be undone
+    let name = name.to_string();
+    history::execute(history::Command::Group(vec![
+        history::Command::Remove { name: name.clone(), object: old },
+        history::Command::Import { name, object: ob },
+    ]));
+    Ok(problems)
+}
+
+// Replaces an object's edges with ones worked out from its surfaces.  Without a crease angle every edge of every
+// surface is used.  With one (in degrees), only the edges where the surfaces meet at more than that angle are kept,
+// along with the edges of any holes.  Fails if no object has the given name
+#[wasm_bindgen]
+pub fn derive_edges(name: &str, crease_degrees: Option<f64>) -> Result<(), JsValue> {
+    let old = lock(&WORLD_SPACE).get(name).cloned().ok_or_else(|| Error::NotFound(name.to_string()))?;
+    let mut ob = old.clone();
+    ob.edges = match crease_degrees {
+        Some(degrees) => mesh::feature_edges(&ob.points, &ob.surfaces, degrees),
+        None => mesh::surface_edges(&ob.surfaces),
+    };
+    let name = name.to_string();
+    history::execute(history::Command::Group(vec![
+        history::Command::Remove { name: name.clone(), object: old },
+        history::Command::Import { name, object: ob },
+    ]));
+    Ok(())
+}
+
+// Do the rendering here
+fn render_frame() -> Result<(), Error> {
+    CANVAS.with(|c| {
+        let canvas = c.borrow();
+        let canvas = canvas.as_ref().ok_or(Error::NotStarted)?;
+        // Handle resizing, either to follow the window or the size the canvas is given by CSS
+        let auto_resize = lock(&layout::OPTIONS).auto_resize;
+        let win = window()?;
+        let (width, height) = if auto_resize {
+            (win.inner_width()?.as_f64().unwrap_or(0.0), win.inner_height()?.as_f64().unwrap_or(0.0))
+        } else {
+            (canvas.client_width() as f64, canvas.client_height() as f64)
+        };
+
+        // Everything is laid out and drawn in CSS pixels, including the mouse positions used for hit testing.  The
+        // canvas itself has devicePixelRatio times as many pixels, so it stays sharp on high DPI screens.  The ratio
+        // is checked every frame, as it changes when the window moves to another monitor or the page is zoomed
+        let ratio = win.device_pixel_ratio();
+        let ratio = if ratio > 0.0 { ratio } else { 1.0 };
+        let backing_width = (width * ratio).round() as u32;
+        let backing_height = (height * ratio).round() as u32;
+        if canvas.width() != backing_width || canvas.height() != backing_height {
+            canvas.set_width(backing_width);
+            canvas.set_height(backing_height);
+            if auto_resize {
+                let style = canvas.style();
+                style.set_property("width", &format!("{}px", width))?;
+                style.set_property("height", &format!("{}px", height))?;
             }
         }
 
-        // Set the clip region so drawing only occurs in the display area
-        ctx.restore();
+        // Get the 2D context for the canvas
+        let ctx = canvas
+            .get_context("2d")?
+            .ok_or(Error::NoContext)?
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+            .map_err(|_| Error::NoContext)?;
+
+        // Work out where the graph area and info panel go
+        let layout = layout::Layout::new(width, height, &lock(&layout::OPTIONS));
+        *lock(&layout::LAYOUT) = layout;
+        let graph = layout.graph;
+        let view = View::new(graph.width, graph.height);
+        let border = view.border;
+        let top = view.top;
+        let graph_width = view.graph_width;
+        let graph_height = view.graph_height;
+
+        // Scale the drawing from CSS pixels up to canvas pixels
+        ctx.set_transform(ratio, 0.0, 0.0, ratio, 0.0, 0.0)?;
+
+        // Clear the background
+        ctx.set_fill_style_str("white");
+        ctx.fill_rect(0.0, 0.0, width, height);
+
+        // Save the current graphics state - no clip region currently defined - as the default
         ctx.save();
+
+        // Set the clip region so drawing only occurs in the display area, and move the origin to its top left corner
         ctx.begin_path();
-        ctx.move_to(*graph_width, 0.0);
-        ctx.line_to(width, 0.0);
-        ctx.line_to(width, height);
-        ctx.line_to(*graph_width, height);
+        ctx.rect(graph.x, graph.y, graph.width, graph.height);
         ctx.clip();
-
-        // Draw the text describing the current operation
-        let mut text_y = top + 20.0;
-        ctx.set_fill_style(&"black".into());
-        ctx.set_font(&"bold 14px serif");
-        ctx.fill_text("Operation:", *graph_width + 20.0, text_y);
-        text_y += 20.0;
-        ctx.set_font(&"14px sans-serif");
-        {
-            let op_text = OP_TEXT.lock().unwrap();
-            ctx.fill_text(&*op_text, *graph_width + 20.0, text_y);
-        }
-        text_y += 30.0;
-
-        // Add the help text about control keys and mouse zoom
-        ctx.set_fill_style(&"blue".into());
-        ctx.set_font(&"14px sans-serif");
-        ctx.fill_text("Use wasd to move, numpad keys", *graph_width + 20.0, text_y);
-        text_y += 20.0;
-        ctx.fill_text("to rotate, mouse wheel to zoom.", *graph_width + 20.0, text_y);
-        text_y += 30.0;
-        ctx.fill_text("+ and - keys to change speed.", *graph_width + 20.0, text_y);
-        text_y += 30.0;
-        ctx.fill_text("Press a key a 2nd time to", *graph_width + 20.0, text_y);
-        text_y += 20.0;
-        ctx.fill_text("stop the current change.", *graph_width + 20.0, text_y);
-
-        // Clear the source code link area
-        ctx.set_fill_style(&"white".into());
-        ctx.fill_rect(*graph_width + 1.0, graph_height - 55.0, width, height);
-
-        // Add the URL to the source code
-        ctx.set_fill_style(&"black".into());
-        ctx.set_font("bold 14px serif".into());
-        ctx.fill_text("Source code:", *graph_width + 20.0, graph_height - 35.0);
-        ctx.set_fill_style(&"blue".into());
-        {
-            let high_light_source = HIGHLIGHT_SOURCE.lock().unwrap();
-            if *high_light_source == true {
-                ctx.set_font("bold 12px sans-serif".into());
-            } else {
-                ctx.set_font("12px sans-serif".into());
-            }
+        let _ = ctx.translate(graph.x, graph.y);
+
+        // Draw the graph area.  In z-buffer mode it's drawn by the software renderer at the canvas's own resolution,
+        // then copied onto the canvas, which ignores the transform and clip region
+        if *lock(&zbuffer::RENDER_MODE) == zbuffer::RenderMode::ZBuffer {
+            // The image is kept between frames, and only drawn again when the scene or the size of the graph area
+            // has changed
+            let img_width = (graph.width * ratio).round().max(1.0);
+            let img_height = (graph.height * ratio).round().max(1.0);
+            let img_view = view.resized(img_width, img_height);
+            let revision = *lock(&SCENE_REVISION);
+            let mut cached = lock(&ZBUFFER_IMAGE);
+            let img = match cached.take() {
+                Some((r, v, img)) if r == revision && v == img_view => img,
+                _ => render_image(img_width as usize, img_height as usize, &img_view),
+            };
+            let (w, h) = (img.width as u32, img.height as u32);
+            let data = ImageData::new_with_u8_clamped_array_and_sh(Clamped(&img.pixels), w, h)?;
+            ctx.put_image_data(&data, (graph.x * ratio).round(), (graph.y * ratio).round())?;
+            *cached = Some((revision, img_view, img));
+        } else {
+            draw_scene(&mut CanvasPainter { ctx: &ctx }, &view);
         }
-        ctx.fill_text(SOURCE_URL, *graph_width + 20.0, graph_height - 15.0);
 
         // Draw a border around the graph area
         ctx.set_line_width(2.0);
-        ctx.set_stroke_style(&"white".into());
-        ctx.begin_path();
-        ctx.move_to(0.0, 0.0);
-        ctx.line_to(width, 0.0);
-        ctx.line_to(width, height);
-        ctx.line_to(0.0, height);
-        ctx.close_path();
-        ctx.stroke();
-        ctx.set_line_width(2.0);
-        ctx.set_stroke_style(&"black".into());
+        ctx.set_stroke_style_str("black");
         ctx.begin_path();
         ctx.move_to(border, border);
-        ctx.line_to(*graph_width, border);
-        ctx.line_to(*graph_width, graph_height);
+        ctx.line_to(graph_width, border);
+        ctx.line_to(graph_width, graph_height);
         ctx.line_to(border, graph_height);
         ctx.close_path();
         ctx.stroke();
-
-        // Restore the default graphics state (eg no clip region)
         ctx.restore();
+
+        if let Some(panel) = layout.panel {
+            // Set the clip region so drawing only occurs in the info panel
+            ctx.save();
+            ctx.begin_path();
+            ctx.rect(panel.x, panel.y, panel.width, panel.height);
+            ctx.clip();
+            let text_x = panel.x + 20.0;
+            let bottom = panel.y + panel.height;
+
+            // Draw the text describing the current operation
+            let mut text_y = panel.y + top + 20.0;
+            ctx.set_fill_style_str("black");
+            ctx.set_font("bold 14px serif");
+            let _ = ctx.fill_text("Operation:", text_x, text_y);
+            text_y += 20.0;
+            ctx.set_font("14px sans-serif");
+            {
+                let op_text = lock(&OP_TEXT);
+                let _ = ctx.fill_text(&op_text, text_x, text_y);
+            }
+            text_y += 30.0;
+
+            // Add the help text about the keys bound to each action, and the mouse and touch gestures
+            ctx.set_fill_style_str("blue");
+            ctx.set_font("14px sans-serif");
+            for line in bindings::help_lines() {
+                let _ = ctx.fill_text(&line, text_x, text_y);
+                text_y += 18.0;
+            }
+            text_y += 12.0;
+            let _ = ctx.fill_text("Drag to rotate, wheel or pinch", text_x, text_y);
+            text_y += 20.0;
+            let _ = ctx.fill_text("to zoom, two fingers to move,", text_x, text_y);
+            text_y += 20.0;
+            let _ = ctx.fill_text("double tap to reset.", text_x, text_y);
+            text_y += 30.0;
+            let _ = ctx.fill_text("Press a key a 2nd time to", text_x, text_y);
+            text_y += 20.0;
+            let _ = ctx.fill_text("stop the current change.", text_x, text_y);
+
+            // Clear the source code link area
+            ctx.set_fill_style_str("white");
+            ctx.fill_rect(panel.x + 1.0, bottom - 55.0, panel.width, 55.0);
+
+            // Add the URL to the source code
+            ctx.set_fill_style_str("black");
+            ctx.set_font("bold 14px serif");
+            let _ = ctx.fill_text("Source code:", text_x, bottom - 35.0);
+            ctx.set_fill_style_str("blue");
+            {
+                let high_light_source = lock(&HIGHLIGHT_SOURCE);
+                if *high_light_source {
+                    ctx.set_font("bold 12px sans-serif");
+                } else {
+                    ctx.set_font("12px sans-serif");
+                }
+            }
+            let _ = ctx.fill_text(SOURCE_URL, text_x, bottom - 15.0);
+
+            // Restore the default graphics state (eg no clip region)
+            ctx.restore();
+        }
+        Ok(())
+    })
+}
+
+// Draws the graph area: the background grid, the objects, and everything else drawn in world space.  This is shared by
+// the canvas and the exporters, so exported images match what's on screen
+fn draw_scene(p: &mut dyn Painter, view: &View) {
+    draw_background(p, view);
+
+    let scene_matrix = *lock(&SCENE_MATRIX);
+    let grid_segments = ground_grid::segments(&scene_matrix);
+    let shading = *lock(&shading::SHADING);
+    let world_space = lock(&WORLD_SPACE);
+    let objects = objects_by_name(&world_space);
+    let shades: Vec<Vec<Option<shading::Shade>>> = objects.iter().map(|obj| shading.shade(obj)).collect();
+    for item in paint_order(&objects, &grid_segments) {
+        match item.part {
+            PaintPart::Segment(i) => ground_grid::draw_segment(p, &grid_segments[i], view),
+            PaintPart::Surface(i, k) => {
+                let obj = objects[i];
+                let surf = &obj.surfaces[k];
+                let points: Vec<(f64, f64)> = surf.iter().map(|n| view.project(&obj.points[*n as usize])).collect();
+                let triangles = obj.surface_triangles(k);
+                match shades[i].get(k) {
+                    Some(Some(shade)) => p.shaded_surface(&points, &triangles, shade),
+                    _ => p.surface(&points, &triangles, &obj.surface_material(k).fill_css()),
+                }
+            }
+            PaintPart::Edge(i, e) => {
+                let (obj, edge) = (objects[i], &objects[i].edges[e]);
+                let point1 = view.project(&obj.points[edge[0] as usize]);
+                let point2 = view.project(&obj.points[edge[1] as usize]);
+                p.line(point1, point2, &obj.material.edge_colour, obj.material.edge_width * view.line_width);
+            }
+            PaintPart::Point(i, n) => {
+                let obj = objects[i];
+                p.circle(view.project(&obj.points[n]), obj.material.point_size * view.line_width, obj.point_colour(n));
+            }
+        }
+    }
+
+    draw_overlays(p, &world_space, &scene_matrix, view);
+}
+
+// Draws the graph area the same as draw_scene(), but depth testing each pixel of the objects and the ground grid.
+// Everything solid is drawn first, then the grid (which is see-through), then the see-through surfaces furthest away
+// first, so they blend over whatever is behind them in the right order
+fn draw_scene_zbuffer(z: &mut zbuffer::ZBufferPainter, view: &View) {
+    draw_background(&mut z.image, view);
+
+    let scene_matrix = *lock(&SCENE_MATRIX);
+    let grid_segments = ground_grid::segments(&scene_matrix);
+    let shading = *lock(&shading::SHADING);
+    let world_space = lock(&WORLD_SPACE);
+    let objects = objects_by_name(&world_space);
+    let shades: Vec<Vec<Option<shading::Shade>>> = objects.iter().map(|obj| shading.shade(obj)).collect();
+    let vertices: Vec<Vec<zbuffer::Vertex>> = objects
+        .iter()
+        .map(|obj| obj.points.iter().map(|p| zbuffer::Vertex::project(view, p)).collect())
+        .collect();
+    let draw_surface = |z: &mut zbuffer::ZBufferPainter, i: usize, k: usize| {
+        let (obj, surf) = (objects[i], &objects[i].surfaces[k]);
+        let colour = obj.surface_material(k).fill_css();
+        for t in obj.surface_triangles(k).iter().copied() {
+            let v = t.map(|n| vertices[i][surf[n] as usize]);
+            match shades[i].get(k) {
+                Some(Some(shade)) => z.shaded_triangle(v, t, shade),
+                _ => z.triangle(v, &colour),
+            }
+        }
+    };
+
+    let order = paint_order(&objects, &grid_segments);
+    for item in order.iter() {
+        match item.part {
+            PaintPart::Surface(i, k) if !objects[i].surface_material(k).is_see_through() => draw_surface(z, i, k),
+            PaintPart::Edge(i, e) => {
+                let (m, edge) = (&objects[i].material, &objects[i].edges[e]);
+                let (from, to) = (vertices[i][edge[0] as usize], vertices[i][edge[1] as usize]);
+                z.line(from, to, &m.edge_colour, m.edge_width * view.line_width);
+            }
+            PaintPart::Point(i, n) => {
+                let obj = objects[i];
+                z.circle(vertices[i][n], obj.material.point_size * view.line_width, obj.point_colour(n));
+            }
+            _ => {}
+        }
+    }
+    for seg in grid_segments.iter() {
+        ground_grid::draw_segment_zbuffer(z, seg, view);
+    }
+    for item in order.iter() {
+        if let PaintPart::Surface(i, k) = item.part {
+            if objects[i].surface_material(k).is_see_through() {
+                draw_surface(z, i, k);
+            }
+        }
+    }
+
+    draw_overlays(&mut z.image, &world_space, &scene_matrix, view);
+}
+
+// The objects in the world space, in order of name so they're always drawn the same way
+fn objects_by_name(world_space: &HashMap<String, Object>) -> Vec<&Object> {
+    let mut named: Vec<(&String, &Object)> = world_space.iter().collect();
+    named.sort_by(|a, b| a.0.cmp(b.0));
+    named.into_iter().map(|(_, obj)| obj).collect()
+}
+
+// Works out the order to draw the objects and the ground grid in, furthest away first.  Each surface, edge, and point
+// is placed on its own rather than with the rest of its object, so the parts of different objects can go in between
+// each other, which see-through surfaces need for everything behind them to show.  Edges and points which are part of
+// a surface go straight after the nearest surface they're part of, so they're drawn over it but behind anything
+// nearer.  Any others go at their own depth
+fn paint_order(objects: &[&Object], grid_segments: &[ground_grid::GridSegment]) -> Vec<PaintItem> {
+    let mut order: Vec<PaintItem> = grid_segments
+        .iter()
+        .enumerate()
+        .map(|(i, seg)| PaintItem { part: PaintPart::Segment(i), depth: seg.mid_z })
+        .collect();
+    for (i, obj) in objects.iter().enumerate() {
+        // The depth of the nearest surface each edge and point is part of
+        let mut edge_depths: HashMap<(i32, i32), f64> = HashMap::new();
+        let mut point_depths = vec![f64::INFINITY; obj.points.len()];
+        for (k, surf) in obj.surfaces.iter().enumerate() {
+            let depth = surf.iter().map(|n| obj.points[*n as usize].z).sum::<f64>() / surf.len().max(1) as f64;
+            order.push(PaintItem { part: PaintPart::Surface(i, k), depth });
+            for (j, a) in surf.iter().enumerate() {
+                let b = surf[(j + 1) % surf.len()];
+                let nearest = edge_depths.entry((b.min(*a), b.max(*a))).or_insert(depth);
+                *nearest = nearest.min(depth);
+                point_depths[*a as usize] = point_depths[*a as usize].min(depth);
+            }
+        }
+
+        let m = &obj.material;
+        if m.edge_width > 0.0 {
+            for (e, edge) in obj.edges.iter().enumerate() {
+                let (a, b) = (edge[0], edge[1]);
+                let depth = match edge_depths.get(&(a.min(b), a.max(b))) {
+                    Some(d) => *d,
+                    None => (obj.points[a as usize].z + obj.points[b as usize].z) / 2.0,
+                };
+                order.push(PaintItem { part: PaintPart::Edge(i, e), depth });
+            }
+        }
+        if m.point_size > 0.0 {
+            for (n, point) in obj.points.iter().enumerate() {
+                let depth = if point_depths[n].is_finite() { point_depths[n] } else { point.z };
+                order.push(PaintItem { part: PaintPart::Point(i, n), depth });
+            }
+        }
+    }
+
+    // The sort keeps the order of parts at the same depth, so the grid goes behind objects and lines over surfaces
+    order.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+    order
+}
+
+// Draws the background grid lines
+fn draw_background(p: &mut dyn Painter, view: &View) {
+    let step = view.step;
+
+    // We use while loops here, because Rust doesn't seem able to iterate using an f64 step. eg .step_by(step)
+    // At least not yet: "the trait `std::iter::Step` is not implemented for `f64`"
+
+    // Vertical dashed lines
+    let mut i = view.left;
+    while i < view.graph_width - step {
+        p.line((i + step, view.top), (i + step, view.graph_height), GRID_COLOUR, view.line_width);
+        i += step;
+    }
+
+    // Horizontal dashed lines
+    let mut i = view.top;
+    while i < view.graph_height - step {
+        p.line((view.left, i + step), (view.graph_width - view.border, i + step), GRID_COLOUR, view.line_width);
+        i += step;
+    }
+}
+
+// Draws the things shown over the top of the objects
+fn draw_overlays(p: &mut dyn Painter, world_space: &HashMap<String, Object>, scene_matrix: &Matrix, view: &View) {
+    // Draw the bounding volumes over the objects, if they're turned on
+    bounds::draw(p, world_space, view);
+
+    // Draw the world space axes and the orientation gizmo, if they're turned on
+    axes::draw(p, scene_matrix, view);
+    axes::draw_gizmo(p, scene_matrix, view);
+}
+
+// Draws the graph area into an image of the given size with the software renderer, using the current render mode
+fn render_image(width: usize, height: usize, view: &View) -> raster::RasterPainter {
+    match *lock(&zbuffer::RENDER_MODE) {
+        zbuffer::RenderMode::Painter => {
+            let mut img = raster::RasterPainter::new(width, height);
+            draw_scene(&mut img, view);
+            img
+        }
+        zbuffer::RenderMode::ZBuffer => {
+            let mut z = zbuffer::ZBufferPainter::new(width, height, view);
+            draw_scene_zbuffer(&mut z, view);
+            z.image
+        }
+    }
+}
+
+// Returns the view of the graph area as last drawn on the canvas.  When nothing has been drawn yet (eg when running
+// headless), a default canvas size is used instead
+fn current_view() -> View {
+    let mut graph = lock(&layout::LAYOUT).graph;
+    if graph.width <= 0.0 || graph.height <= 0.0 {
+        graph = layout::Layout::new(DEFAULT_WIDTH, DEFAULT_HEIGHT, &lock(&layout::OPTIONS)).graph;
+    }
+    View::new(graph.width, graph.height)
+}
+
+// Renders the graph area into a PNG image of the given size.  This is export_png() for use outside the browser
+pub fn render_png(width: u32, height: u32) -> Result<Vec<u8>, Error> {
+    check_image_size(width, height)?;
+    let view = current_view().resized(width as f64, height as f64);
+    let img = render_image(width as usize, height as usize, &view);
+    raster::encode_png(img.width, img.height, &img.pixels).map_err(Error::Encode)
+}
+
+// Renders the scene turning around the Y axis into an animated image of the given size.  This is record_turntable()
+// for use outside the browser
+pub fn render_turntable(
+    frames: u32,
+    degrees_per_frame: f64,
+    format: &str,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, Error> {
+    let format = animation::Format::from_name(format)
+        .ok_or_else(|| Error::Invalid(format!("Unknown animation format '{}'.  It should be gif or apng", format)))?;
+    if frames == 0 || frames > MAX_TURNTABLE_FRAMES {
+        return Err(Error::Invalid(format!("The number of frames must be between 1 and {}", MAX_TURNTABLE_FRAMES)));
+    }
+    if !degrees_per_frame.is_finite() {
+        let msg = format!("The turn for each frame should be a number of degrees, not {}", degrees_per_frame);
+        return Err(Error::Invalid(msg));
+    }
+    check_image_size(width, height)?;
+
+    // The scene is turned for each frame, then put back how it was once they've all been drawn.  Any running operation
+    // is left alone, so it carries on from where it was afterwards
+    let saved_world_space = lock(&WORLD_SPACE).clone();
+    let saved_scene_matrix = *lock(&SCENE_MATRIX);
+    let step_matrix = rotate_around_y(&IDENTITY_MATRIX, degrees_per_frame);
+    let view = current_view().resized(width as f64, height as f64);
+    let result = animation::encode(format, width as usize, height as usize, frames as usize, |frame| {
+        if frame > 0 {
+            let mut world_space = lock(&WORLD_SPACE);
+            for o in world_space.values_mut() {
+                *o = transform_object(o, &step_matrix);
+            }
+            let mut scene_matrix = lock(&SCENE_MATRIX);
+            *scene_matrix = matrix_mult(&step_matrix, &scene_matrix);
+        }
+        render_image(width as usize, height as usize, &view).pixels
     });
+    *lock(&WORLD_SPACE) = saved_world_space;
+    *lock(&SCENE_MATRIX) = saved_scene_matrix;
+
+    result.map_err(Error::Encode)
+}
+
+// Renders the graph area into an SVG image of the given size
+pub fn render_svg(width: u32, height: u32) -> String {
+    let view = current_view().resized(width as f64, height as f64);
+    let mut svg = svg::SvgPainter::new(view.graph_width, view.graph_height);
+    draw_scene(&mut svg, &view);
+    svg.finish()
+}
+
+// Checks an image size is something the software renderer can draw
+fn check_image_size(width: u32, height: u32) -> Result<(), Error> {
+    if width == 0 || height == 0 || width > raster::MAX_SIZE || height > raster::MAX_SIZE {
+        let msg = format!("The image size must be between 1 and {} pixels in each direction", raster::MAX_SIZE);
+        return Err(Error::Invalid(msg));
+    }
+    if width as u64 * height as u64 * 4 > raster::MAX_BYTES {
+        let msg = format!("The image is too big.  It can have at most {} pixels", raster::MAX_BYTES / 4);
+        return Err(Error::Invalid(msg));
+    }
+    Ok(())
 }
 
 // The web_sys bindings (so far) only seem capable of calling request_animation_frame() with a closure :/
-fn req_anim_frame(z: &Closure<dyn FnMut()>) {
-    window()
-        .request_animation_frame(z.as_ref().unchecked_ref())
-        .expect("should register `requestAnimationFrame` OK");
+fn req_anim_frame(z: &Closure<dyn FnMut()>) -> Result<(), Error> {
+    window()?.request_animation_frame(z.as_ref().unchecked_ref())?;
+    Ok(())
+}
+
+// Locks one of the globals.  If code panicked while holding the lock, the value is used as it was left rather than
+// panicking again, as none of the globals can be left in an unusable state
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Notes that something drawn in the graph area has changed, so the z-buffer image is drawn again for the next frame.
+// Changes to the world space go through transform_world() or the history module, which call this for them
+fn scene_changed() {
+    *lock(&SCENE_REVISION) += 1;
+}
+
+// Reports a problem which doesn't stop things working, on the browser console or (outside the browser) stderr
+fn warn(msg: &str) {
+    #[cfg(target_arch = "wasm32")]
+    web_sys::console::warn_1(&msg.into());
+    #[cfg(not(target_arch = "wasm32"))]
+    eprintln!("{}", msg);
 }
 
 // Returns an object whose points have been transformed into 3D world space XYZ co-ordinates.  Also assigns a number
@@ -718,7 +1819,7 @@ fn import_object(ob: &Object, x: f64, y: f64, z: f64) -> Object {
         z: 0.0,
     };
     for j in ob.points.iter() {
-        let mut point_counter = POINT_COUNTER.lock().unwrap();
+        let mut point_counter = lock(&POINT_COUNTER);
         pt.x = (translate_matrix[0] * j.x) // 1st col, top
             + (translate_matrix[1] * j.y)
             + (translate_matrix[2] * j.z)
@@ -749,18 +1850,100 @@ fn import_object(ob: &Object, x: f64, y: f64, z: f64) -> Object {
     translated_object.mid_point.y = mid_y / num_pts;
     translated_object.mid_point.z = mid_z / num_pts;
 
-    // Copy the colour, edge, and surface definitions across
-    translated_object.colour = ob.colour.clone();
+    // Copy the material, edge, and surface definitions across
+    translated_object.material = ob.material.clone();
     for j in ob.edges.iter() {
         translated_object.edges.push(j.clone());
     }
     for j in ob.surfaces.iter() {
         translated_object.surfaces.push(j.clone());
     }
+    translated_object.surface_materials = ob.surface_materials.clone();
+    translated_object.point_colours = ob.point_colours.clone();
+    translated_object.bounds = bounds::Bounds::from_points(&translated_object.points);
+    translated_object.update_surfaces();
 
     translated_object
 }
 
+// Adds an object to the world space, recording it so the addition can be undone.  Edges and surfaces which can't be
+// drawn are left out, with a warning
+fn insert_object(name: &str, mut ob: Object) {
+    for msg in mesh::check_import(name, &mut ob) {
+        warn(&msg);
+    }
+    lock(&WORLD_SPACE).insert(name.to_string(), ob.clone());
+    history::push(history::Command::Import { name: name.to_string(), object: ob });
+}
+
+// Adds several objects to the world space, placed in the scene and recorded so they're undone together.  Nothing is
+// added if any of the names are already in use.  As with insert_object(), edges and surfaces which can't be drawn are
+// left out
+fn insert_objects(objects: Vec<(String, Object)>) -> Result<(), Error> {
+    let mut world_space = lock(&WORLD_SPACE);
+    if let Some((n, _)) = objects.iter().find(|(n, _)| world_space.contains_key(n)) {
+        return Err(Error::NameInUse(n.clone()));
+    }
+    let mut cmds = Vec::with_capacity(objects.len());
+    for (n, ob) in objects {
+        let mut ob = place_in_scene(&ob);
+        for msg in mesh::check_import(&n, &mut ob) {
+            warn(&msg);
+        }
+        world_space.insert(n.clone(), ob.clone());
+        cmds.push(history::Command::Import { name: n, object: ob });
+    }
+    drop(world_space);
+    history::push(history::Command::Group(cmds));
+    Ok(())
+}
+
+// Returns the inverse of a transformation matrix, or None if the matrix can't be inverted (eg a scale by zero)
+fn invert_matrix(m: &Matrix) -> Option<Matrix> {
+    let mut inv: Matrix = [0.0; 16];
+    inv[0] = m[5] * m[10] * m[15] - m[5] * m[11] * m[14] - m[9] * m[6] * m[15] + m[9] * m[7] * m[14]
+        + m[13] * m[6] * m[11] - m[13] * m[7] * m[10];
+    inv[4] = -m[4] * m[10] * m[15] + m[4] * m[11] * m[14] + m[8] * m[6] * m[15] - m[8] * m[7] * m[14]
+        - m[12] * m[6] * m[11] + m[12] * m[7] * m[10];
+    inv[8] = m[4] * m[9] * m[15] - m[4] * m[11] * m[13] - m[8] * m[5] * m[15] + m[8] * m[7] * m[13]
+        + m[12] * m[5] * m[11] - m[12] * m[7] * m[9];
+    inv[12] = -m[4] * m[9] * m[14] + m[4] * m[10] * m[13] + m[8] * m[5] * m[14] - m[8] * m[6] * m[13]
+        - m[12] * m[5] * m[10] + m[12] * m[6] * m[9];
+    inv[1] = -m[1] * m[10] * m[15] + m[1] * m[11] * m[14] + m[9] * m[2] * m[15] - m[9] * m[3] * m[14]
+        - m[13] * m[2] * m[11] + m[13] * m[3] * m[10];
+    inv[5] = m[0] * m[10] * m[15] - m[0] * m[11] * m[14] - m[8] * m[2] * m[15] + m[8] * m[3] * m[14]
+        + m[12] * m[2] * m[11] - m[12] * m[3] * m[10];
+    inv[9] = -m[0] * m[9] * m[15] + m[0] * m[11] * m[13] + m[8] * m[1] * m[15] - m[8] * m[3] * m[13]
+        - m[12] * m[1] * m[11] + m[12] * m[3] * m[9];
+    inv[13] = m[0] * m[9] * m[14] - m[0] * m[10] * m[13] - m[8] * m[1] * m[14] + m[8] * m[2] * m[13]
+        + m[12] * m[1] * m[10] - m[12] * m[2] * m[9];
+    inv[2] = m[1] * m[6] * m[15] - m[1] * m[7] * m[14] - m[5] * m[2] * m[15] + m[5] * m[3] * m[14]
+        + m[13] * m[2] * m[7] - m[13] * m[3] * m[6];
+    inv[6] = -m[0] * m[6] * m[15] + m[0] * m[7] * m[14] + m[4] * m[2] * m[15] - m[4] * m[3] * m[14]
+        - m[12] * m[2] * m[7] + m[12] * m[3] * m[6];
+    inv[10] = m[0] * m[5] * m[15] - m[0] * m[7] * m[13] - m[4] * m[1] * m[15] + m[4] * m[3] * m[13]
+        + m[12] * m[1] * m[7] - m[12] * m[3] * m[5];
+    inv[14] = -m[0] * m[5] * m[14] + m[0] * m[6] * m[13] + m[4] * m[1] * m[14] - m[4] * m[2] * m[13]
+        - m[12] * m[1] * m[6] + m[12] * m[2] * m[5];
+    inv[3] = -m[1] * m[6] * m[11] + m[1] * m[7] * m[10] + m[5] * m[2] * m[11] - m[5] * m[3] * m[10]
+        - m[9] * m[2] * m[7] + m[9] * m[3] * m[6];
+    inv[7] = m[0] * m[6] * m[11] - m[0] * m[7] * m[10] - m[4] * m[2] * m[11] + m[4] * m[3] * m[10]
+        + m[8] * m[2] * m[7] - m[8] * m[3] * m[6];
+    inv[11] = -m[0] * m[5] * m[11] + m[0] * m[7] * m[9] + m[4] * m[1] * m[11] - m[4] * m[3] * m[9]
+        - m[8] * m[1] * m[7] + m[8] * m[3] * m[5];
+    inv[15] = m[0] * m[5] * m[10] - m[0] * m[6] * m[9] - m[4] * m[1] * m[10] + m[4] * m[2] * m[9]
+        + m[8] * m[1] * m[6] - m[8] * m[2] * m[5];
+
+    let det = m[0] * inv[0] + m[1] * inv[4] + m[2] * inv[8] + m[3] * inv[12];
+    if det.abs() < f64::EPSILON {
+        return None;
+    }
+    for v in inv.iter_mut() {
+        *v /= det;
+    }
+    Some(inv)
+}
+
 // Multiplies one matrix by another
 fn matrix_mult(op_matrix: &Matrix, m: &Matrix) -> Matrix {
     let top0 = m[0];
@@ -848,6 +2031,12 @@ fn matrix_mult(op_matrix: &Matrix, m: &Matrix) -> Matrix {
     ]
 }
 
+// Returns a copy of the object, transformed the same way the rest of the world space has been so far
+fn place_in_scene(ob: &Object) -> Object {
+    let scene_matrix = lock(&SCENE_MATRIX);
+    transform_object(ob, &scene_matrix)
+}
+
 // Rotates a transformation matrix around the X axis by the given degrees
 fn rotate_around_x(m: &Matrix, degrees: f64) -> Matrix {
     let rad = degrees.to_radians();
@@ -950,29 +2139,32 @@ fn scale(m: &Matrix, x: f64, y: f64, z: f64) -> Matrix {
 
 // Set up the details for the transformation operation
 fn set_up_operation(op: OperationType, f: i32, x: f64, y: f64, z: f64) {
+    // Any operation already running is finished, so record it as a single undo step
+    history::commit_pending();
+
     let queue_parts: f64;
     {
-        let mut q = QUEUE_PARTS.lock().unwrap(); // Number of parts to break each transformation into
+        let mut q = lock(&QUEUE_PARTS); // Number of parts to break each transformation into
         *q = f;
         queue_parts = *q as f64;
     }
 
-    let mut transformation_matrix = TRANSFORM_MATRIX.lock().unwrap(); // Unlock the mutex
-    *transformation_matrix = IDENTITY_MATRIX.clone(); // Reset the transform matrix
+    let mut transformation_matrix = lock(&TRANSFORM_MATRIX); // Unlock the mutex
+    *transformation_matrix = IDENTITY_MATRIX; // Reset the transform matrix
     match op {
         // Rotate the objects in world space
         OperationType::ROTATE => {
             // Divide the desired angle into a small number of parts
             if x != 0.0 {
-                *transformation_matrix = rotate_around_x(&*transformation_matrix, x / queue_parts);
+                *transformation_matrix = rotate_around_x(&transformation_matrix, x / queue_parts);
             }
             if y != 0.0 {
-                *transformation_matrix = rotate_around_y(&*transformation_matrix, y / queue_parts);
+                *transformation_matrix = rotate_around_y(&transformation_matrix, y / queue_parts);
             }
             if z != 0.0 {
-                *transformation_matrix = rotate_around_z(&*transformation_matrix, z / queue_parts);
+                *transformation_matrix = rotate_around_z(&transformation_matrix, z / queue_parts);
             }
-            let mut op_text = OP_TEXT.lock().unwrap(); // Unlocks the mutex
+            let mut op_text = lock(&OP_TEXT); // Unlocks the mutex
             *op_text = format!("Rotation. X: {} Y: {} Z: {}", x, y, z); // Sets the new value
         }
 
@@ -990,32 +2182,43 @@ fn set_up_operation(op: OperationType, f: i32, x: f64, y: f64, z: f64) {
             if z != 1.0 {
                 z_part = ((z - 1.0) / queue_parts) + 1.0;
             }
-            *transformation_matrix = scale(&*transformation_matrix, x_part, y_part, z_part);
+            *transformation_matrix = scale(&transformation_matrix, x_part, y_part, z_part);
 
-            let mut op_text = OP_TEXT.lock().unwrap(); // Unlocks the mutex
+            let mut op_text = lock(&OP_TEXT); // Unlocks the mutex
             *op_text = format!("Scale. X: {} Y: {} Z: {}", x, y, z); // Sets the new value
         }
 
         // Translate (move) the objects in world space
         OperationType::TRANSLATE => {
             *transformation_matrix = translate(
-                &*transformation_matrix,
+                &transformation_matrix,
                 x / queue_parts,
                 y / queue_parts,
                 z / queue_parts,
             );
-            let mut op_text = OP_TEXT.lock().unwrap(); // Unlocks the mutex
+            let mut op_text = lock(&OP_TEXT); // Unlocks the mutex
             *op_text = format!("Translate. X: {} Y: {} Z: {}", x, y, z); // Sets the new value
         }
 
         // Nothing to do
-        OperationType::NOTHING => {}
+        OperationType::NOTHING | OperationType::FIT => {}
     }
 
-    let mut queue_op = QUEUE_OP.lock().unwrap(); // Unlocks the mutex
+    let mut queue_op = lock(&QUEUE_OP); // Unlocks the mutex
     *queue_op = op; // Sets the new value
 }
 
+// Stops any running operation, recording it in the undo history
+fn stop_operation() {
+    {
+        let mut queue_op = lock(&QUEUE_OP);
+        *queue_op = OperationType::NOTHING;
+        let mut prev_key = lock(&PREV_KEY);
+        *prev_key = KeyVal::KeyNone;
+    }
+    history::commit_pending();
+}
+
 // Transform the XYZ co-ordinates using the values from the transformation matrix
 fn transform(m: &Matrix, p: Point) -> Point {
     let top0 = m[0];
@@ -1043,6 +2246,55 @@ fn transform(m: &Matrix, p: Point) -> Point {
     }
 }
 
+// Returns a copy of the object, with its points and mid point transformed by the given matrix
+fn transform_object(ob: &Object, m: &Matrix) -> Object {
+    // Create a blank new object
+    let mut new_object = Object::new();
+
+    // Add the points to the new object, transformed from the original object
+    let mut new_points: Vec<Point> = Vec::new();
+    for orig_point in ob.points.iter() {
+        new_points.push(transform(m, *orig_point));
+    }
+    new_object.points = new_points;
+
+    // Copy the edges across from the original object
+    let mut new_edges: Vec<Edge> = Vec::new();
+    for orig_edge in ob.edges.iter() {
+        let mut new_edge = Edge::new();
+        for p in orig_edge.iter() {
+            new_edge.push(*p);
+        }
+        new_edges.push(new_edge);
+    }
+    new_object.edges = new_edges;
+
+    // Copy the surfaces across from the original object
+    let mut new_surfaces: Vec<Surface> = Vec::new();
+    for orig_surf in ob.surfaces.iter() {
+        let mut new_surf = Surface::new();
+        for p in orig_surf.iter() {
+            new_surf.push(*p);
+        }
+        new_surfaces.push(new_surf);
+    }
+    new_object.surfaces = new_surfaces;
+
+    // Copy the materials across from the original object
+    new_object.material = ob.material.clone();
+    new_object.surface_materials = ob.surface_materials.clone();
+    new_object.point_colours = ob.point_colours.clone();
+
+    // Transform the mid point of the object.  In theory, this should mean the mid point can
+    // always be used for a simple (not-cpu-intensive) way to sort the objects in Z depth order
+    new_object.mid_point = transform(m, ob.mid_point);
+    new_object.bounds = ob.bounds.transformed(m, &new_object.points);
+    new_object.triangles = ob.triangles.clone();
+    new_object.corner_normals = ob.corner_normals.transformed(m);
+
+    new_object
+}
+
 // Translates (moves) a transformation matrix by the given X, Y and Z values
 fn translate(m: &Matrix, translate_x: f64, translate_y: f64, translate_z: f64) -> Matrix {
     let translate_matrix = [
@@ -1068,5 +2320,5 @@ fn translate(m: &Matrix, translate_x: f64, translate_y: f64, translate_z: f64) -
         0.0,
         1.0,
     ];
-    matrix_mult(&translate_matrix, &m)
+    matrix_mult(&translate_matrix, m)
 }
@@ -0,0 +1,141 @@
+// How objects look: the colours of their surfaces, edges, and points, and how their surfaces take the light
+//
+// Every object has a material, and each of its surfaces can have its own as well.  Only the parts of a surface's
+// material about filling the surface are used, as edges and points belong to the whole object.  Materials are given
+// as JSON objects, with any of these keys:
+//
+//   diffuse       The colour of the surfaces, as a CSS colour
+//   opacity       How solid the surfaces are, from 0 (invisible) to 1 (solid)
+//   specular      The brightness of highlights on the surfaces when they're lit, from 0 (none, the default) to 1
+//   shininess     How small and sharp the highlights are, with higher values being smaller
+//   emissive      Light given off by the surfaces themselves, as a CSS colour added to the light falling on them
+//   edge_colour   The colour of the edges, as a CSS colour
+//   edge_width    The width of the edges, in pixels.  0 hides them
+//   point_colour  The colour of the points, as a CSS colour
+//   point_size    The radius of the points, in pixels.  0 hides them
+//
+// Keys which are left out keep the value from the material being changed.  The specular, shininess, and emissive
+// values only make a difference when the surfaces are lit (see the shading module).
+
+use serde_json::{json, Map, Value};
+
+use crate::colour::{parse_colour, Rgba};
+
+// The values of materials which don't give their own
+const DEFAULT_SPECULAR: f64 = 0.0;
+const DEFAULT_SHININESS: f64 = 32.0;
+const DEFAULT_LINE_COLOUR: &str = "black";
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Material {
+    pub(crate) diffuse: String,      // The colour of the surfaces
+    pub(crate) opacity: f64,         // How solid the surfaces are, from 0.0 to 1.0
+    pub(crate) specular: f64,        // The brightness of highlights, from 0.0 to 1.0
+    pub(crate) shininess: f64,       // How small and sharp the highlights are
+    pub(crate) emissive: String,     // The colour of light given off by the surfaces
+    pub(crate) edge_colour: String,  // The colour of the edges
+    pub(crate) edge_width: f64,      // The width of the edges, in pixels
+    pub(crate) point_colour: String, // The colour of the points
+    pub(crate) point_size: f64,      // The radius of the points, in pixels
+}
+
+impl Material {
+    // A material with the given surface colour, and black edges and points
+    pub(crate) fn new(diffuse: &str) -> Material {
+        Material {
+            diffuse: diffuse.to_string(),
+            opacity: 1.0,
+            specular: DEFAULT_SPECULAR,
+            shininess: DEFAULT_SHININESS,
+            emissive: "black".to_string(),
+            edge_colour: DEFAULT_LINE_COLOUR.to_string(),
+            edge_width: 1.0,
+            point_colour: DEFAULT_LINE_COLOUR.to_string(),
+            point_size: 1.0,
+        }
+    }
+
+    // Returns a copy of the material with a different surface colour
+    pub(crate) fn with_diffuse(&self, diffuse: &str) -> Material {
+        Material { diffuse: diffuse.to_string(), ..self.clone() }
+    }
+
+    // Returns a copy of the material changed by the values in a JSON object.  Fails if the JSON isn't an object, or
+    // has a key which isn't known or a value which isn't valid
+    pub(crate) fn with_json(&self, v: &Value) -> Result<Material, String> {
+        let map = v.as_object().ok_or("a material should be a JSON object")?;
+        let mut m = self.clone();
+        for (key, value) in map.iter() {
+            match key.as_str() {
+                "diffuse" => m.diffuse = colour(key, value)?,
+                "opacity" => m.opacity = number(key, value, 0.0, 1.0)?,
+                "specular" => m.specular = number(key, value, 0.0, 1.0)?,
+                "shininess" => m.shininess = number(key, value, 0.0, f64::INFINITY)?,
+                "emissive" => m.emissive = colour(key, value)?,
+                "edge_colour" => m.edge_colour = colour(key, value)?,
+                "edge_width" => m.edge_width = number(key, value, 0.0, f64::INFINITY)?,
+                "point_colour" => m.point_colour = colour(key, value)?,
+                "point_size" => m.point_size = number(key, value, 0.0, f64::INFINITY)?,
+                _ => return Err(format!("unknown material setting '{}'", key)),
+            }
+        }
+        Ok(m)
+    }
+
+    // Returns the material as a JSON object, in the form with_json() reads
+    pub(crate) fn json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert("diffuse".into(), json!(self.diffuse));
+        map.insert("opacity".into(), json!(self.opacity));
+        map.insert("specular".into(), json!(self.specular));
+        map.insert("shininess".into(), json!(self.shininess));
+        map.insert("emissive".into(), json!(self.emissive));
+        map.insert("edge_colour".into(), json!(self.edge_colour));
+        map.insert("edge_width".into(), json!(self.edge_width));
+        map.insert("point_colour".into(), json!(self.point_colour));
+        map.insert("point_size".into(), json!(self.point_size));
+        Value::Object(map)
+    }
+
+    // The colour to fill the surfaces with, including their opacity.  Returns None if it can't be worked out
+    pub(crate) fn fill(&self) -> Option<Rgba> {
+        parse_colour(&self.diffuse).map(|c| Rgba { a: c.a * self.opacity, ..c })
+    }
+
+    // Returns true if things behind the surfaces show through them
+    pub(crate) fn is_see_through(&self) -> bool {
+        self.fill().is_some_and(|c| c.a < 1.0)
+    }
+
+    // The fill colour as a CSS colour.  Colours which can't be worked out are passed along as they are, for the
+    // canvas to make sense of
+    pub(crate) fn fill_css(&self) -> String {
+        match self.fill() {
+            Some(c) if c.a < 1.0 => c.css(),
+            _ => self.diffuse.clone(),
+        }
+    }
+}
+
+// Checks a colour can be worked out, so it can be saved in a material and read back.  The key names what the colour
+// is for, in the error
+pub(crate) fn check_colour(key: &str, s: &str) -> Result<(), String> {
+    match parse_colour(s) {
+        Some(_) => Ok(()),
+        None => Err(format!("{} '{}' isn't a colour", key, s)),
+    }
+}
+
+fn colour(key: &str, v: &Value) -> Result<String, String> {
+    let s = v.as_str().ok_or_else(|| format!("{} should be a string", key))?;
+    check_colour(key, s)?;
+    Ok(s.to_string())
+}
+
+fn number(key: &str, v: &Value, min: f64, max: f64) -> Result<f64, String> {
+    match v.as_f64() {
+        Some(n) if n >= min && n <= max => Ok(n),
+        _ if max.is_finite() => Err(format!("{} should be a number from {} to {}", key, min, max)),
+        _ => Err(format!("{} should be a number, {} or more", key, min)),
+    }
+}
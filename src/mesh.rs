@@ -0,0 +1,753 @@
+// Checks that an object's edges and surfaces make sense, and repairs the common problems
+//
+// Objects come from scene files and user code, so nothing guarantees their point numbers are in range.  Every object
+// is checked as it's added to the world space, and anything which would stop it being drawn is dropped.  The other
+// problems are only reported, as they're often harmless (eg an open surface, or a point cloud), and can be fixed with
+// the repairs here when they're not.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::bounds::{Aabb, Bounds};
+use crate::vector::{add, dot, length, unit, Vec3};
+use crate::{Edge, Object, Point, Surface};
+
+// The most problems reported for each object added to the world space, so a badly broken model doesn't flood the log
+const MAX_IMPORT_WARNINGS: usize = 10;
+
+// The crease angle (in degrees) used for the edges of OBJ and STL models, which are usually made of many small surfaces
+pub(crate) const DEFAULT_CREASE_ANGLE: f64 = 30.0;
+
+// Surfaces with less area than this (relative to the square of the object's size) are degenerate
+const AREA_EPSILON: f64 = 1e-12;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Problem {
+    EdgeIndex { edge: usize, index: i32 },       // An edge refers to a point which doesn't exist
+    SurfaceIndex { surface: usize, index: i32 }, // A surface refers to a point which doesn't exist
+    ShortEdge(usize),                            // An edge without two different points
+    DegenerateSurface(usize),                    // A surface with fewer than three different points, or no area
+    DuplicateSurface { surface: usize, first: usize }, // A surface using the same points as an earlier one
+    NonManifoldEdge { a: i32, b: i32, surfaces: usize }, // An edge shared by more than two surfaces
+    InconsistentWinding { a: i32, b: i32 },     // Two surfaces go around their shared edge the same way
+    UnreferencedPoint(usize),                    // A point no edge or surface uses
+}
+
+impl Problem {
+    // Returns true for the problems which stop an object being drawn at all
+    pub(crate) fn is_fatal(&self) -> bool {
+        matches!(self, Problem::EdgeIndex { .. } | Problem::SurfaceIndex { .. } | Problem::ShortEdge(_))
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::EdgeIndex { edge, index } => {
+                write!(f, "edge {} refers to point {}, which doesn't exist", edge, index)
+            }
+            Problem::SurfaceIndex { surface, index } => {
+                write!(f, "surface {} refers to point {}, which doesn't exist", surface, index)
+            }
+            Problem::ShortEdge(edge) => write!(f, "edge {} doesn't join two different points", edge),
+            Problem::DegenerateSurface(surface) => write!(f, "surface {} has no area", surface),
+            Problem::DuplicateSurface { surface, first } => {
+                write!(f, "surface {} uses the same points as surface {}", surface, first)
+            }
+            Problem::NonManifoldEdge { a, b, surfaces } => {
+                write!(f, "the edge between points {} and {} is shared by {} surfaces", a, b, surfaces)
+            }
+            Problem::InconsistentWinding { a, b } => {
+                write!(f, "the surfaces either side of the edge between points {} and {} face opposite ways", a, b)
+            }
+            Problem::UnreferencedPoint(point) => write!(f, "point {} isn't used by any edge or surface", point),
+        }
+    }
+}
+
+// Returns the problems with an object's edges and surfaces.  Unused points are only a problem for objects which have
+// edges or surfaces, as objects made only of points (eg scatter plots) are fine
+pub(crate) fn validate(ob: &Object) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let num_points = ob.points.len();
+    let in_range = |n: i32| n >= 0 && (n as usize) < num_points;
+    let mut used = vec![false; num_points];
+
+    for (k, edge) in ob.edges.iter().enumerate() {
+        match edge.iter().find(|n| !in_range(**n)) {
+            Some(n) => problems.push(Problem::EdgeIndex { edge: k, index: *n }),
+            None if edge.len() < 2 || edge[0] == edge[1] => problems.push(Problem::ShortEdge(k)),
+            None => {}
+        }
+        for n in edge.iter().filter(|n| in_range(**n)) {
+            used[*n as usize] = true;
+        }
+    }
+
+    // Only the surfaces which are usable are checked against each other
+    let min_area = AREA_EPSILON * size_squared(ob);
+    let mut seen: HashMap<Vec<i32>, usize> = HashMap::new();
+    let mut good = Vec::new();
+    for (k, surf) in ob.surfaces.iter().enumerate() {
+        for n in surf.iter().filter(|n| in_range(**n)) {
+            used[*n as usize] = true;
+        }
+        if let Some(n) = surf.iter().find(|n| !in_range(**n)) {
+            problems.push(Problem::SurfaceIndex { surface: k, index: *n });
+            continue;
+        }
+        let mut key = surf.clone();
+        key.sort_unstable();
+        key.dedup();
+        if key.len() < 3 || length(normal(&ob.points, surf)) <= min_area {
+            problems.push(Problem::DegenerateSurface(k));
+            continue;
+        }
+        match seen.get(&key) {
+            Some(first) => problems.push(Problem::DuplicateSurface { surface: k, first: *first }),
+            None => {
+                seen.insert(key, k);
+                good.push(k);
+            }
+        }
+    }
+
+    // Each edge should be shared by at most two surfaces, which go along it in opposite directions
+    let shared = shared_edges(&ob.surfaces, &good);
+    let mut edges: Vec<&(i32, i32)> = shared.keys().collect();
+    edges.sort_unstable();
+    for key in edges {
+        let sides = &shared[key];
+        if sides.len() > 2 {
+            problems.push(Problem::NonManifoldEdge { a: key.0, b: key.1, surfaces: sides.len() });
+        } else if sides.len() == 2 && sides[0].1 == sides[1].1 {
+            problems.push(Problem::InconsistentWinding { a: key.0, b: key.1 });
+        }
+    }
+
+    if !ob.edges.is_empty() || !ob.surfaces.is_empty() {
+        problems.extend(used.iter().enumerate().filter(|(_, u)| !**u).map(|(n, _)| Problem::UnreferencedPoint(n)));
+    }
+    problems
+}
+
+// Checks an object being added to the world space, dropping the edges and surfaces which can't be drawn, and works out
+// the triangles of the rest.  Returns a message for each problem found, up to a limit
+pub(crate) fn check_import(name: &str, ob: &mut Object) -> Vec<String> {
+    let problems = validate(ob);
+    let fatal: Vec<Problem> = problems.iter().filter(|p| p.is_fatal()).cloned().collect();
+    drop_invalid_faces(ob, &fatal);
+
+    let mut messages: Vec<String> =
+        problems.iter().take(MAX_IMPORT_WARNINGS).map(|p| format!("{}: {}", name, p)).collect();
+    if problems.len() > MAX_IMPORT_WARNINGS {
+        messages.push(format!("{}: and {} more problems", name, problems.len() - MAX_IMPORT_WARNINGS));
+    }
+    if !fatal.is_empty() {
+        messages.push(format!("{}: {} edges or surfaces which couldn't be drawn were left out", name, fatal.len()));
+    }
+    ob.update_surfaces();
+    messages
+}
+
+// Removes the edges and surfaces with the given problems, along with their surface materials.  Returns how many were
+// removed
+pub(crate) fn drop_invalid_faces(ob: &mut Object, problems: &[Problem]) -> usize {
+    let mut bad_edges = HashSet::new();
+    let mut bad_surfaces = HashSet::new();
+    for p in problems {
+        match p {
+            Problem::EdgeIndex { edge, .. } | Problem::ShortEdge(edge) => {
+                bad_edges.insert(*edge);
+            }
+            Problem::SurfaceIndex { surface, .. }
+            | Problem::DegenerateSurface(surface)
+            | Problem::DuplicateSurface { surface, .. } => {
+                bad_surfaces.insert(*surface);
+            }
+            _ => {}
+        }
+    }
+
+    let mut k = 0;
+    ob.edges.retain(|_| {
+        k += 1;
+        !bad_edges.contains(&(k - 1))
+    });
+    remove_surfaces(ob, &bad_surfaces);
+    bad_edges.len() + bad_surfaces.len()
+}
+
+// Merges points which are within the given distance of each other, so surfaces which were only touching become joined.
+// Surfaces and edges which collapse as a result are removed.  Returns how many points were merged away.  Like
+// remove_unused_points(), this needs any out of range point numbers to have been dropped first
+pub(crate) fn weld_vertices(ob: &mut Object, tolerance: f64) -> usize {
+    // Points are bucketed into cells the size of the tolerance, so each only needs comparing with its neighbours
+    let tolerance = tolerance.max(0.0);
+    let cell_size = if tolerance > 0.0 { tolerance } else { 1.0 };
+    let cell = |p: &Point| {
+        [(p.x / cell_size).floor() as i64, (p.y / cell_size).floor() as i64, (p.z / cell_size).floor() as i64]
+    };
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    let mut new_index = vec![0; ob.points.len()];
+    let mut kept: Vec<Point> = Vec::new();
+    let mut kept_from = Vec::new();
+    for (n, p) in ob.points.iter().enumerate() {
+        let c = cell(p);
+        let mut found = None;
+        'search: for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let near = cells.get(&[c[0] + dx, c[1] + dy, c[2] + dz]).map(|v| v.as_slice()).unwrap_or(&[]);
+                    if let Some(k) = near.iter().find(|k| distance(&kept[**k], p) <= tolerance) {
+                        found = Some(*k);
+                        break 'search;
+                    }
+                }
+            }
+        }
+        new_index[n] = match found {
+            Some(k) => k,
+            None => {
+                cells.entry(c).or_default().push(kept.len());
+                kept.push(*p);
+                kept_from.push(n);
+                kept.len() - 1
+            }
+        };
+    }
+
+    let merged = ob.points.len() - kept.len();
+    if merged > 0 {
+        ob.points = kept;
+        keep_point_colours(ob, &kept_from);
+        renumber(ob, &new_index);
+        refresh(ob);
+    }
+    merged
+}
+
+// Removes the points which no edge or surface uses.  Objects made only of points (eg scatter plots) are left alone, as
+// all their points are meant to be there.  Returns how many were removed
+pub(crate) fn remove_unused_points(ob: &mut Object) -> usize {
+    if ob.edges.is_empty() && ob.surfaces.is_empty() {
+        return 0;
+    }
+    let mut used = vec![false; ob.points.len()];
+    for n in ob.edges.iter().chain(ob.surfaces.iter()).flatten() {
+        if *n >= 0 && (*n as usize) < used.len() {
+            used[*n as usize] = true;
+        }
+    }
+    let mut new_index = vec![0; ob.points.len()];
+    let mut kept = Vec::new();
+    let mut kept_from = Vec::new();
+    for (n, p) in ob.points.iter().enumerate() {
+        if used[n] {
+            new_index[n] = kept.len();
+            kept.push(*p);
+            kept_from.push(n);
+        }
+    }
+
+    let removed = ob.points.len() - kept.len();
+    if removed > 0 {
+        ob.points = kept;
+        keep_point_colours(ob, &kept_from);
+        renumber(ob, &new_index);
+        refresh(ob);
+    }
+    removed
+}
+
+// Flips surfaces so each one goes around its shared edges the opposite way to its neighbours, which makes them all
+// face the same way.  Closed shapes are then turned outwards, as the generated shapes are.  Returns how many surfaces
+// were flipped
+pub(crate) fn reorient_windings(ob: &mut Object) -> usize {
+    let num_points = ob.points.len() as i32;
+    let good: Vec<usize> = (0..ob.surfaces.len())
+        .filter(|k| {
+            let surf = &ob.surfaces[*k];
+            surf.len() >= 3 && surf.iter().all(|n| *n >= 0 && *n < num_points)
+        })
+        .collect();
+    let shared = shared_edges(&ob.surfaces, &good);
+
+    // Work through each group of connected surfaces, flipping neighbours to match the first surface in the group
+    let mut flipped = vec![false; ob.surfaces.len()];
+    let mut visited = vec![false; ob.surfaces.len()];
+    let mut count = 0;
+    for start in good.iter() {
+        if visited[*start] {
+            continue;
+        }
+        visited[*start] = true;
+        let mut group = vec![*start];
+        let mut closed = true;
+        let mut next = 0;
+        while next < group.len() {
+            let k = group[next];
+            next += 1;
+            for (a, b) in directed_edges(&ob.surfaces[k]) {
+                let forwards = (a < b) != flipped[k];
+                let sides = &shared[&(a.min(b), a.max(b))];
+                if sides.len() != 2 {
+                    closed = false;
+                    continue;
+                }
+                let (other, other_forwards) = if sides[0].0 == k { sides[1] } else { sides[0] };
+                if !visited[other] {
+                    visited[other] = true;
+                    flipped[other] = other_forwards == forwards;
+                    group.push(other);
+                }
+            }
+        }
+
+        // A closed shape has a positive volume when it faces outwards
+        let flip_all = closed && {
+            let volume: f64 = group
+                .iter()
+                .map(|k| {
+                    let v = signed_volume(&ob.points, &ob.surfaces[*k]);
+                    if flipped[*k] {
+                        -v
+                    } else {
+                        v
+                    }
+                })
+                .sum();
+            volume < 0.0
+        };
+        for k in group {
+            if flipped[k] != flip_all {
+                ob.surfaces[k].reverse();
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+// Returns each edge used by the surfaces, once.  Edges which a surface goes along in both directions are the seams
+// joining holes to the outside of the surface (see triangulate::bridge_holes()), so they're left out
+pub(crate) fn surface_edges(surfaces: &[Surface]) -> Vec<Edge> {
+    let mut seen: HashSet<(i32, i32)> = HashSet::new();
+    let mut edges = Vec::new();
+    for surf in surfaces.iter() {
+        let directed = directed_edges(surf);
+        for (a, b) in directed.iter().copied() {
+            let key = (a.min(b), a.max(b));
+            if !directed.contains(&(b, a)) && seen.insert(key) {
+                edges.push(vec![key.0, key.1]);
+            }
+        }
+    }
+    edges
+}
+
+// Returns the edges used by the surfaces which mark out the shape's features: where the surfaces either side meet at
+// more than the crease angle (in degrees), and the edges of holes and open sides.  Edges between surfaces which are
+// nearly flat with each other are left out, so curved areas made of many small surfaces don't fill in with lines
+pub(crate) fn feature_edges(points: &[Point], surfaces: &[Surface], crease_degrees: f64) -> Vec<Edge> {
+    let num_points = points.len() as i32;
+    let good: Vec<usize> =
+        (0..surfaces.len()).filter(|k| surfaces[*k].iter().all(|n| *n >= 0 && *n < num_points)).collect();
+    let normals: HashMap<usize, Vec3> = good.iter().map(|k| (*k, normal(points, &surfaces[*k]))).collect();
+    let shared = shared_edges(surfaces, &good);
+    let min_cos = crease_degrees.to_radians().cos();
+    surface_edges(surfaces)
+        .into_iter()
+        .filter(|e| match shared.get(&(e[0], e[1])).map(|s| s.as_slice()) {
+            Some([(j, j_forwards), (k, k_forwards)]) => {
+                let (a, b) = (normals[j], normals[k]);
+                let lengths = length(a) * length(b);
+                if lengths <= 0.0 {
+                    return true;
+                }
+
+                // Surfaces which go along the edge the same way face opposite ways, so one of them is turned around
+                let cos = dot(a, b) / lengths;
+                let cos = if j_forwards == k_forwards { -cos } else { cos };
+                cos < min_cos
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+// Returns the normal at each corner of each surface, for smooth shading.  Each is the average of the normals of the
+// surfaces around that point, weighted by their area, leaving out any which meet the surface at more than the crease
+// angle (in degrees) so sharp edges stay sharp.  The normals are unit length, and face the same side as the surface
+pub(crate) fn corner_normals(points: &[Point], surfaces: &[Surface], crease_degrees: f64) -> Vec<Vec<Vec3>> {
+    let faces: Vec<Vec3> = surfaces.iter().map(|s| normal(points, s)).collect();
+    let mut around: HashMap<i32, Vec<usize>> = HashMap::new();
+    for (k, surf) in surfaces.iter().enumerate() {
+        for n in surf.iter() {
+            let list = around.entry(*n).or_default();
+            if list.last() != Some(&k) {
+                list.push(k);
+            }
+        }
+    }
+
+    let min_cos = crease_degrees.to_radians().cos();
+    surfaces
+        .iter()
+        .enumerate()
+        .map(|(k, surf)| {
+            let own = unit(faces[k]);
+            surf.iter()
+                .map(|n| {
+                    let mut sum: Vec3 = [0.0; 3];
+                    for j in around[n].iter() {
+                        let f = faces[*j];
+                        let u = unit(f);
+                        if *j == k || dot(own, u) >= min_cos {
+                            sum = add(sum, f);
+                        }
+                    }
+                    unit(sum)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Maps each undirected edge of the given surfaces to the surfaces using it, and whether they go along it from its
+// lower numbered point to its higher one
+fn shared_edges(surfaces: &[Surface], which: &[usize]) -> HashMap<(i32, i32), Vec<(usize, bool)>> {
+    let mut shared: HashMap<(i32, i32), Vec<(usize, bool)>> = HashMap::new();
+    for k in which {
+        for (a, b) in directed_edges(&surfaces[*k]) {
+            shared.entry((a.min(b), a.max(b))).or_default().push((*k, a < b));
+        }
+    }
+    shared
+}
+
+// Returns the edges going around a surface, skipping any between repeats of the same point
+fn directed_edges(surf: &[i32]) -> Vec<(i32, i32)> {
+    (0..surf.len()).map(|i| (surf[i], surf[(i + 1) % surf.len()])).filter(|(a, b)| a != b).collect()
+}
+
+// Keeps the colours of the points which are left, given the old number of each one, so they stay with their points
+fn keep_point_colours(ob: &mut Object, kept_from: &[usize]) {
+    if !ob.point_colours.is_empty() {
+        ob.point_colours = kept_from.iter().map(|n| ob.point_colours[*n].clone()).collect();
+    }
+}
+
+// Points the edges and surfaces at the new point numbers, removing any which collapse.  Any using point numbers which
+// are out of range are removed too, though callers should have dropped them already
+fn renumber(ob: &mut Object, new_index: &[usize]) {
+    let map = |face: &[i32]| -> Option<Vec<i32>> {
+        let number = |n: i32| if n >= 0 { new_index.get(n as usize).map(|k| *k as i32) } else { None };
+        face.iter().map(|n| number(*n)).collect()
+    };
+    let mut edges: Vec<Vec<i32>> = Vec::new();
+    let mut seen = HashSet::new();
+    for edge in ob.edges.iter() {
+        let e = map(edge).unwrap_or_default();
+        if e.len() >= 2 && e[0] != e[1] && seen.insert((e[0].min(e[1]), e[0].max(e[1]))) {
+            edges.push(e);
+        }
+    }
+    ob.edges = edges;
+
+    let mut collapsed = HashSet::new();
+    for (k, surf) in ob.surfaces.iter_mut().enumerate() {
+        let mut s = map(surf).unwrap_or_default();
+        s.dedup();
+        while s.len() > 1 && s.first() == s.last() {
+            s.pop();
+        }
+        if s.len() < 3 {
+            collapsed.insert(k);
+        }
+        *surf = s;
+    }
+    remove_surfaces(ob, &collapsed);
+}
+
+fn remove_surfaces(ob: &mut Object, remove: &HashSet<usize>) {
+    if remove.is_empty() {
+        return;
+    }
+    let keep = |k: usize| !remove.contains(&k);
+    ob.surfaces = ob.surfaces.drain(..).enumerate().filter(|(k, _)| keep(*k)).map(|(_, s)| s).collect();
+    ob.surface_materials =
+        ob.surface_materials.drain(..).enumerate().filter(|(k, _)| keep(*k)).map(|(_, m)| m).collect();
+}
+
+// Updates the mid point and bounding volumes after the points change
+fn refresh(ob: &mut Object) {
+    let n = ob.points.len().max(1) as f64;
+    ob.mid_point.x = ob.points.iter().map(|p| p.x).sum::<f64>() / n;
+    ob.mid_point.y = ob.points.iter().map(|p| p.y).sum::<f64>() / n;
+    ob.mid_point.z = ob.points.iter().map(|p| p.z).sum::<f64>() / n;
+    ob.bounds = Bounds::from_points(&ob.points);
+}
+
+fn distance(a: &Point, b: &Point) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+fn size_squared(ob: &Object) -> f64 {
+    let s = Aabb::from_points(&ob.points).size();
+    let d = dot(s, s);
+    if d > 0.0 && d.is_finite() {
+        d
+    } else {
+        1.0
+    }
+}
+
+// Returns a surface's normal, using Newell's method so surfaces with more than three points work too.  Its length is
+// twice the surface's area
+pub(crate) fn normal(points: &[Point], surf: &[i32]) -> Vec3 {
+    let mut normal: Vec3 = [0.0; 3];
+    for i in 0..surf.len() {
+        let a = points[surf[i] as usize];
+        let b = points[surf[(i + 1) % surf.len()] as usize];
+        normal[0] += (a.y - b.y) * (a.z + b.z);
+        normal[1] += (a.z - b.z) * (a.x + b.x);
+        normal[2] += (a.x - b.x) * (a.y + b.y);
+    }
+    normal
+}
+
+// Returns six times the signed volume between a surface and the origin.  Summed over a closed shape, this is positive
+// when the surfaces wind counter-clockwise seen from outside
+fn signed_volume(points: &[Point], surf: &[i32]) -> f64 {
+    let p0 = points[surf[0] as usize];
+    (1..surf.len() - 1)
+        .map(|i| {
+            let p1 = points[surf[i] as usize];
+            let p2 = points[surf[i + 1] as usize];
+            p0.x * (p1.y * p2.z - p1.z * p2.y) + p0.y * (p1.z * p2.x - p1.x * p2.z) + p0.z * (p1.x * p2.y - p1.y * p2.x)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::cube;
+
+    fn object(points: &[[f64; 3]], edges: Vec<Edge>, surfaces: Vec<Surface>) -> Object {
+        let mut ob = Object::new();
+        for (n, p) in points.iter().enumerate() {
+            ob.points.push(Point { num: n as i32, x: p[0], y: p[1], z: p[2] });
+        }
+        ob.edges = edges;
+        ob.surfaces = surfaces;
+        ob
+    }
+
+    // A square of two triangles, which go around their shared edge (0 to 2) in opposite directions
+    const SQUARE: [[f64; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+
+    #[test]
+    fn a_good_object_has_no_problems() {
+        let ob = object(&SQUARE, vec![vec![0, 1]], vec![vec![0, 1, 2], vec![0, 2, 3]]);
+        assert_eq!(validate(&ob), vec![]);
+        assert_eq!(validate(&cube(2.0, 2)), vec![]);
+    }
+
+    #[test]
+    fn out_of_range_edges() {
+        let ob = object(&SQUARE, vec![vec![0, 1], vec![-1, 2], vec![3, 4]], vec![]);
+        let problems = validate(&ob);
+        assert_eq!(problems, vec![Problem::EdgeIndex { edge: 1, index: -1 }, Problem::EdgeIndex { edge: 2, index: 4 }]);
+        assert!(problems.iter().all(Problem::is_fatal));
+    }
+
+    #[test]
+    fn out_of_range_surfaces() {
+        let ob = object(&SQUARE, vec![], vec![vec![0, 1, 2], vec![0, 2, -3], vec![7, 2, 3]]);
+        let problems = validate(&ob);
+        assert_eq!(
+            problems,
+            vec![Problem::SurfaceIndex { surface: 1, index: -3 }, Problem::SurfaceIndex { surface: 2, index: 7 }]
+        );
+        assert!(problems.iter().all(Problem::is_fatal));
+    }
+
+    #[test]
+    fn short_edges() {
+        let ob = object(&SQUARE[..2], vec![vec![1, 1], vec![0], vec![0, 1]], vec![]);
+        assert_eq!(validate(&ob), vec![Problem::ShortEdge(0), Problem::ShortEdge(1)]);
+    }
+
+    #[test]
+    fn degenerate_surfaces() {
+        // One with a repeated point, and one whose points are all in a line
+        let points = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let ob = object(&points, vec![], vec![vec![0, 0, 1], vec![0, 1, 2], vec![0, 1, 3]]);
+        assert_eq!(validate(&ob), vec![Problem::DegenerateSurface(0), Problem::DegenerateSurface(1)]);
+    }
+
+    #[test]
+    fn duplicate_surfaces() {
+        // The same points in a different order count as the same surface
+        let ob = object(&SQUARE, vec![], vec![vec![0, 1, 2], vec![0, 2, 3], vec![2, 1, 0]]);
+        assert_eq!(validate(&ob), vec![Problem::DuplicateSurface { surface: 2, first: 0 }]);
+    }
+
+    #[test]
+    fn non_manifold_edges() {
+        let points = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0]];
+        let ob = object(&points, vec![], vec![vec![0, 1, 2], vec![1, 0, 3], vec![1, 0, 4]]);
+        assert_eq!(validate(&ob), vec![Problem::NonManifoldEdge { a: 0, b: 1, surfaces: 3 }]);
+    }
+
+    #[test]
+    fn inconsistent_windings() {
+        let ob = object(&SQUARE, vec![], vec![vec![0, 1, 2], vec![0, 3, 2]]);
+        assert_eq!(validate(&ob), vec![Problem::InconsistentWinding { a: 0, b: 2 }]);
+    }
+
+    #[test]
+    fn unreferenced_points() {
+        let ob = object(&SQUARE, vec![], vec![vec![0, 1, 2]]);
+        assert_eq!(validate(&ob), vec![Problem::UnreferencedPoint(3)]);
+        assert!(!Problem::UnreferencedPoint(3).is_fatal());
+
+        // Objects made only of points are meant to be that way
+        assert_eq!(validate(&object(&SQUARE, vec![], vec![])), vec![]);
+    }
+
+    #[test]
+    fn check_import_drops_what_can_t_be_drawn() {
+        let mut ob = object(&SQUARE, vec![vec![0, 1], vec![0, 9]], vec![vec![0, 1, 2], vec![0, -1, 2], vec![2, 1, 0]]);
+        let messages = check_import("test", &mut ob);
+        assert_eq!(ob.edges, vec![vec![0, 1]]);
+
+        // The duplicate surface isn't fatal, so it's only reported
+        assert_eq!(ob.surfaces, vec![vec![0, 1, 2], vec![2, 1, 0]]);
+        assert_eq!(ob.triangles.len(), 2);
+        assert_eq!(
+            messages,
+            vec![
+                "test: edge 1 refers to point 9, which doesn't exist",
+                "test: surface 1 refers to point -1, which doesn't exist",
+                "test: surface 2 uses the same points as surface 0",
+                "test: point 3 isn't used by any edge or surface",
+                "test: 2 edges or surfaces which couldn't be drawn were left out",
+            ]
+        );
+    }
+
+    #[test]
+    fn check_import_limits_its_messages() {
+        let edges = (0..20).map(|n| vec![0, 10 + n]).collect();
+        let mut ob = object(&SQUARE, edges, vec![vec![0, 1, 2, 3]]);
+        let messages = check_import("test", &mut ob);
+        assert_eq!(messages.len(), MAX_IMPORT_WARNINGS + 2);
+        assert_eq!(messages[MAX_IMPORT_WARNINGS], "test: and 10 more problems");
+        assert!(ob.edges.is_empty());
+    }
+
+    #[test]
+    fn dropping_faces_keeps_surface_materials_in_step() {
+        let mut ob = object(&SQUARE, vec![vec![0, 1], vec![2, 2]], vec![vec![0, 1, 2], vec![0, 0, 2], vec![0, 2, 3]]);
+        ob.surface_materials = ["red", "green", "blue"].iter().map(|c| ob.material.with_diffuse(c)).collect();
+        let problems = validate(&ob);
+        assert_eq!(drop_invalid_faces(&mut ob, &problems), 2);
+        assert_eq!(ob.edges, vec![vec![0, 1]]);
+        assert_eq!(ob.surfaces, vec![vec![0, 1, 2], vec![0, 2, 3]]);
+        let colours: Vec<&str> = ob.surface_materials.iter().map(|m| m.diffuse.as_str()).collect();
+        assert_eq!(colours, vec!["red", "blue"]);
+    }
+
+    #[test]
+    fn welding_merges_and_renumbers_points() {
+        // Two triangles which touch along an edge, but each have their own copy of its points
+        let points = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0005],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let mut ob = object(&points, vec![vec![0, 2], vec![3, 4], vec![4, 5]], vec![vec![0, 1, 2], vec![3, 4, 5]]);
+        ob.point_colours = ["a", "b", "c", "d", "e", "f"].iter().map(|c| c.to_string()).collect();
+        assert!(validate(&ob).iter().all(|p| !matches!(p, Problem::InconsistentWinding { .. })));
+        assert_eq!(weld_vertices(&mut ob, 0.001), 2);
+        assert_eq!(ob.points.len(), 4);
+        assert_eq!(ob.points.iter().map(|p| p.num).collect::<Vec<_>>(), vec![0, 1, 2, 5]);
+        assert_eq!(ob.point_colours, vec!["a", "b", "c", "f"]);
+        assert_eq!(ob.surfaces, vec![vec![0, 1, 2], vec![0, 2, 3]]);
+
+        // The edge which was in both triangles is now only there once
+        assert_eq!(ob.edges, vec![vec![0, 2], vec![2, 3]]);
+        assert_eq!(validate(&ob), vec![]);
+    }
+
+    #[test]
+    fn welding_removes_what_collapses() {
+        let points = [[0.0, 0.0, 0.0], [0.0001, 0.0, 0.0], [0.0, 0.0001, 0.0], [1.0, 0.0, 0.0]];
+        let mut ob = object(&points, vec![vec![0, 1], vec![1, 3]], vec![vec![0, 1, 2]]);
+        assert_eq!(weld_vertices(&mut ob, 0.001), 2);
+        assert_eq!(ob.points.len(), 2);
+        assert_eq!(ob.edges, vec![vec![0, 1]]);
+        assert!(ob.surfaces.is_empty());
+    }
+
+    #[test]
+    fn welding_nothing_changes_nothing() {
+        let mut ob = cube(2.0, 1);
+        let surfaces = ob.surfaces.clone();
+        assert_eq!(weld_vertices(&mut ob, 0.001), 0);
+        assert_eq!(ob.surfaces, surfaces);
+    }
+
+    #[test]
+    fn removing_unused_points_renumbers_the_rest() {
+        let mut ob = object(&SQUARE, vec![vec![1, 3]], vec![vec![1, 2, 3]]);
+        ob.point_colours = ["a", "b", "c", "d"].iter().map(|c| c.to_string()).collect();
+        assert_eq!(remove_unused_points(&mut ob), 1);
+        assert_eq!(ob.edges, vec![vec![0, 2]]);
+        assert_eq!(ob.surfaces, vec![vec![0, 1, 2]]);
+        assert_eq!(ob.point_colours, vec!["b", "c", "d"]);
+
+        // Point clouds are left alone
+        let mut ob = object(&SQUARE, vec![], vec![]);
+        assert_eq!(remove_unused_points(&mut ob), 0);
+        assert_eq!(ob.points.len(), 4);
+    }
+
+    #[test]
+    fn reorienting_fixes_a_flipped_face() {
+        let mut ob = cube(2.0, 1);
+        let outwards = ob.surfaces.clone();
+        ob.surfaces[3].reverse();
+        assert_eq!(validate(&ob).len(), 4);
+        assert!(validate(&ob).iter().all(|p| matches!(p, Problem::InconsistentWinding { .. })));
+        assert_eq!(reorient_windings(&mut ob), 1);
+        assert_eq!(ob.surfaces, outwards);
+        assert_eq!(validate(&ob), vec![]);
+    }
+
+    #[test]
+    fn reorienting_turns_closed_shapes_outwards() {
+        let mut ob = cube(2.0, 1);
+        let outwards = ob.surfaces.clone();
+        for surf in ob.surfaces.iter_mut() {
+            surf.reverse();
+        }
+        assert_eq!(validate(&ob), vec![]);
+        assert_eq!(reorient_windings(&mut ob), 6);
+        assert_eq!(ob.surfaces, outwards);
+    }
+
+    #[test]
+    fn reorienting_leaves_open_surfaces_facing_their_first_surface() {
+        let mut ob = object(&SQUARE, vec![], vec![vec![2, 1, 0], vec![0, 2, 3]]);
+        assert_eq!(reorient_windings(&mut ob), 1);
+        assert_eq!(ob.surfaces, vec![vec![2, 1, 0], vec![3, 2, 0]]);
+        assert_eq!(validate(&ob), vec![]);
+    }
+}
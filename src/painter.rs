@@ -0,0 +1,173 @@
+// The drawing operations used to render the graph area
+//
+// The scene is drawn through this trait rather than straight onto the canvas, so the same drawing code can also
+// produce exported images.  Co-ordinates are in pixels, with the origin at the top left of the graph area.
+
+use web_sys::CanvasRenderingContext2d;
+
+use crate::colour::Rgba;
+use crate::shading::Shade;
+
+pub(crate) trait Painter {
+    // Fills a closed polygon
+    fn polygon(&mut self, points: &[(f64, f64)], fill: &str);
+
+    // Fills a surface, given its outline and the triangles it splits into (as positions in the outline).  The
+    // triangles are what's filled, so surfaces which cross over themselves or don't lie flat are drawn correctly
+    fn surface(&mut self, outline: &[(f64, f64)], triangles: &[[usize; 3]], fill: &str) {
+        for t in triangle_points(outline, triangles) {
+            self.polygon(&t, fill);
+        }
+    }
+
+    // Fills a surface whose colour changes across it with the lighting.  Painters which can't blend colours fill it
+    // with its overall colour
+    fn shaded_surface(&mut self, outline: &[(f64, f64)], triangles: &[[usize; 3]], shade: &Shade) {
+        self.surface(outline, triangles, &shade.average().css());
+    }
+
+    // Draws a straight line
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), colour: &str, width: f64);
+
+    // Fills a circle
+    fn circle(&mut self, centre: (f64, f64), radius: f64, fill: &str);
+
+    // Draws text, with its baseline starting at the given position.  The font uses the CSS font syntax
+    fn text(&mut self, text: &str, pos: (f64, f64), font: &str, colour: &str);
+}
+
+// Draws onto a canvas 2D context
+pub(crate) struct CanvasPainter<'a> {
+    pub(crate) ctx: &'a CanvasRenderingContext2d,
+}
+
+impl Painter for CanvasPainter<'_> {
+    fn polygon(&mut self, points: &[(f64, f64)], fill: &str) {
+        if points.is_empty() {
+            return;
+        }
+        self.ctx.set_fill_style_str(fill);
+        self.ctx.begin_path();
+        self.ctx.move_to(points[0].0, points[0].1);
+        for (x, y) in points[1..].iter() {
+            self.ctx.line_to(*x, *y);
+        }
+        self.ctx.close_path();
+        self.ctx.fill();
+    }
+
+    // The triangles are filled together as a single path, which keeps the edges between them from showing
+    fn surface(&mut self, outline: &[(f64, f64)], triangles: &[[usize; 3]], fill: &str) {
+        self.ctx.set_fill_style_str(fill);
+        self.ctx.begin_path();
+        for t in triangle_points(outline, triangles) {
+            self.ctx.move_to(t[0].0, t[0].1);
+            self.ctx.line_to(t[1].0, t[1].1);
+            self.ctx.line_to(t[2].0, t[2].1);
+            self.ctx.close_path();
+        }
+        self.ctx.fill();
+    }
+
+    // The canvas can only blend colours in one direction, so each triangle is filled with a linear gradient running
+    // the way its colour changes fastest.  The surface is filled whole first, so no gaps show between the triangles.
+    // See-through surfaces are only filled whole, with their overall colour, as the smoothed edges of triangles
+    // filled one at a time would be blended twice where they meet, and show as seams
+    fn shaded_surface(&mut self, outline: &[(f64, f64)], triangles: &[[usize; 3]], shade: &Shade) {
+        let average = shade.average();
+        self.surface(outline, triangles, &average.css());
+        if shade.is_even() || average.a < 1.0 {
+            return;
+        }
+        for t in triangles.iter() {
+            let (p, c) = (t.map(|i| outline[i]), t.map(|i| shade.corner(i)));
+            match gradient(p, c) {
+                Some((from, to, stops)) => {
+                    let g = self.ctx.create_linear_gradient(from.0, from.1, to.0, to.1);
+                    for (offset, colour) in stops.iter() {
+                        let _ = g.add_color_stop(*offset as f32, &colour.css());
+                    }
+                    self.ctx.set_fill_style_canvas_gradient(&g);
+                }
+                None => self.ctx.set_fill_style_str(&shade.blend(*t, [1.0 / 3.0; 3]).css()),
+            }
+            self.ctx.begin_path();
+            self.ctx.move_to(p[0].0, p[0].1);
+            self.ctx.line_to(p[1].0, p[1].1);
+            self.ctx.line_to(p[2].0, p[2].1);
+            self.ctx.close_path();
+            self.ctx.fill();
+        }
+    }
+
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), colour: &str, width: f64) {
+        self.ctx.set_stroke_style_str(colour);
+        self.ctx.set_line_width(width);
+        self.ctx.begin_path();
+        self.ctx.move_to(from.0, from.1);
+        self.ctx.line_to(to.0, to.1);
+        self.ctx.stroke();
+    }
+
+    fn circle(&mut self, centre: (f64, f64), radius: f64, fill: &str) {
+        self.ctx.set_fill_style_str(fill);
+        self.ctx.begin_path();
+        let _ = self.ctx.arc(centre.0, centre.1, radius, 0.0, 2.0 * std::f64::consts::PI);
+        self.ctx.fill();
+    }
+
+    fn text(&mut self, text: &str, pos: (f64, f64), font: &str, colour: &str) {
+        self.ctx.set_font(font);
+        self.ctx.set_fill_style_str(colour);
+        let _ = self.ctx.fill_text(text, pos.0, pos.1);
+    }
+}
+
+// Returns the corners of each triangle of a surface, all wound the same way.  Filled together as a single path with the
+// nonzero rule, the triangles then cover exactly the surface, even where a surface crossing over itself has left
+// some of them wound the other way
+pub(crate) fn triangle_points(outline: &[(f64, f64)], triangles: &[[usize; 3]]) -> Vec<[(f64, f64); 3]> {
+    triangles
+        .iter()
+        .map(|t| {
+            let (a, b, c) = (outline[t[0]], outline[t[1]], outline[t[2]]);
+            if ((b.0 - a.0) * (c.1 - a.1)) - ((b.1 - a.1) * (c.0 - a.0)) < 0.0 {
+                [a, c, b]
+            } else {
+                [a, b, c]
+            }
+        })
+        .collect()
+}
+
+// A linear gradient, as where it starts and ends and its colour stops
+type Gradient = ((f64, f64), (f64, f64), Vec<(f64, Rgba)>);
+
+// Works out a linear gradient across a triangle with the given colours at its corners.  The gradient runs the way the
+// brightness changes fastest, with a stop for each corner, so it matches blending the colours exactly when they only
+// differ in brightness.  Returns the start and end of the gradient and its stops, or None if the colour barely changes
+fn gradient(p: [(f64, f64); 3], c: [Rgba; 3]) -> Option<Gradient> {
+    let l = c.map(|c| (0.3 * c.r as f64) + (0.59 * c.g as f64) + (0.11 * c.b as f64));
+    let (e1, e2) = ((p[1].0 - p[0].0, p[1].1 - p[0].1), (p[2].0 - p[0].0, p[2].1 - p[0].1));
+    let det = (e1.0 * e2.1) - (e1.1 * e2.0);
+    if det.abs() < 1e-9 {
+        return None;
+    }
+    let (dl1, dl2) = (l[1] - l[0], l[2] - l[0]);
+    let (gx, gy) = (((dl1 * e2.1) - (dl2 * e1.1)) / det, ((e1.0 * dl2) - (e2.0 * dl1)) / det);
+    let len = (gx * gx + gy * gy).sqrt();
+    if !(len > 1e-6 && len.is_finite()) {
+        return None;
+    }
+
+    // Measure how far along the gradient each corner is
+    let (ux, uy) = (gx / len, gy / len);
+    let t = p.map(|q| ((q.0 - p[0].0) * ux) + ((q.1 - p[0].1) * uy));
+    let (lo, hi) = (t[0].min(t[1]).min(t[2]), t[0].max(t[1]).max(t[2]));
+    if hi - lo < 1e-9 {
+        return None;
+    }
+    let mut stops: Vec<(f64, Rgba)> = (0..3).map(|k| ((t[k] - lo) / (hi - lo), c[k])).collect();
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Some(((p[0].0 + ux * lo, p[0].1 + uy * lo), (p[0].0 + ux * hi, p[0].1 + uy * hi), stops))
+}
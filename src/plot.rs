@@ -0,0 +1,510 @@
+// Plots 3D function surfaces, z = f(x, y)
+//
+// The expression is parsed once into a small syntax tree, then evaluated at each point of a grid.  The result is an
+// object made of quads, coloured by height.  As the viewer has Y pointing up the screen, the function's X and Y are
+// laid out across the world space X and Z axes, and its result becomes the height along the world space Y axis.
+
+use crate::colour::colour_map;
+use crate::mesh::surface_edges;
+use crate::{mesh, Object, Point};
+
+// The most divisions allowed along each side of the grid
+const MAX_RESOLUTION: u32 = 200;
+
+// The longest expression allowed, in characters, and how deeply brackets, signs, and powers can be nested in it.  The
+// parser and the syntax tree it builds are both recursive, so these keep them from running out of stack
+const MAX_LENGTH: usize = 1000;
+const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Sqrt,
+    Abs,
+    Exp,
+    Ln,
+    Log,
+    Floor,
+    Ceil,
+    Min,
+    Max,
+    Atan2,
+    Pow,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Func> {
+        Some(match name {
+            "sin" => Func::Sin,
+            "cos" => Func::Cos,
+            "tan" => Func::Tan,
+            "asin" => Func::Asin,
+            "acos" => Func::Acos,
+            "atan" => Func::Atan,
+            "sinh" => Func::Sinh,
+            "cosh" => Func::Cosh,
+            "tanh" => Func::Tanh,
+            "sqrt" => Func::Sqrt,
+            "abs" => Func::Abs,
+            "exp" => Func::Exp,
+            "ln" => Func::Ln,
+            "log" => Func::Log,
+            "floor" => Func::Floor,
+            "ceil" => Func::Ceil,
+            "min" => Func::Min,
+            "max" => Func::Max,
+            "atan2" => Func::Atan2,
+            "pow" => Func::Pow,
+            _ => return None,
+        })
+    }
+
+    // The number of arguments the function takes
+    fn arity(self) -> usize {
+        match self {
+            Func::Min | Func::Max | Func::Atan2 | Func::Pow => 2,
+            _ => 1,
+        }
+    }
+}
+
+// A parsed expression
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Expr {
+    Num(f64),
+    X,
+    Y,
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+impl Expr {
+    // Works out the value of the expression at the given point.  Invalid results (eg a division by zero) are returned
+    // as NaN or infinity, rather than being treated as errors
+    pub(crate) fn eval(&self, x: f64, y: f64) -> f64 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::X => x,
+            Expr::Y => y,
+            Expr::Neg(a) => -a.eval(x, y),
+            Expr::Add(a, b) => a.eval(x, y) + b.eval(x, y),
+            Expr::Sub(a, b) => a.eval(x, y) - b.eval(x, y),
+            Expr::Mul(a, b) => a.eval(x, y) * b.eval(x, y),
+            Expr::Div(a, b) => a.eval(x, y) / b.eval(x, y),
+            Expr::Pow(a, b) => a.eval(x, y).powf(b.eval(x, y)),
+            Expr::Call(f, args) => {
+                let a = args[0].eval(x, y);
+                let b = || args[1].eval(x, y);
+                match f {
+                    Func::Sin => a.sin(),
+                    Func::Cos => a.cos(),
+                    Func::Tan => a.tan(),
+                    Func::Asin => a.asin(),
+                    Func::Acos => a.acos(),
+                    Func::Atan => a.atan(),
+                    Func::Sinh => a.sinh(),
+                    Func::Cosh => a.cosh(),
+                    Func::Tanh => a.tanh(),
+                    Func::Sqrt => a.sqrt(),
+                    Func::Abs => a.abs(),
+                    Func::Exp => a.exp(),
+                    Func::Ln => a.ln(),
+                    Func::Log => a.log10(),
+                    Func::Floor => a.floor(),
+                    Func::Ceil => a.ceil(),
+                    Func::Min => a.min(b()),
+                    Func::Max => a.max(b()),
+                    Func::Atan2 => a.atan2(b()),
+                    Func::Pow => a.powf(b()),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+}
+
+// Splits the expression text into tokens, each paired with its (1 based) character position for error messages
+fn tokenise(text: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+
+            // Exponents, eg 1.5e-3
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let s: String = chars[start..i].iter().collect();
+            match s.parse::<f64>() {
+                Ok(n) => tokens.push((Token::Num(n), start + 1)),
+                Err(_) => return Err(format!("invalid number '{}' at position {}", s, start + 1)),
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let s: String = chars[start..i].iter().collect();
+            tokens.push((Token::Ident(s.to_lowercase()), start + 1));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push((Token::Op(c), start + 1));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character '{}' at position {}", c, start + 1));
+        }
+    }
+    Ok(tokens)
+}
+
+// A recursive descent parser for the usual arithmetic precedence rules.  The grammar is:
+//
+//   expr    = term (('+' | '-') term)*
+//   term    = unary (('*' | '/') unary)*
+//   unary   = ('-' | '+') unary | power
+//   power   = primary ('^' unary)?
+//   primary = number | 'x' | 'y' | 'pi' | 'e' | function '(' expr (',' expr)* ')' | '(' expr ')'
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,   // Position just past the end of the text, for errors about running out of input
+    depth: usize, // How deeply unary() is nested, which every nested part of the grammar goes through
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map(|(_, p)| *p).unwrap_or(self.end)
+    }
+
+    fn next_is(&self, op: char) -> bool {
+        self.peek() == Some(&Token::Op(op))
+    }
+
+    fn expect(&mut self, op: char) -> Result<(), String> {
+        if self.next_is(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at position {}", op, self.position()))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        loop {
+            if self.next_is('+') {
+                self.pos += 1;
+                lhs = Expr::Add(Box::new(lhs), Box::new(self.term()?));
+            } else if self.next_is('-') {
+                self.pos += 1;
+                lhs = Expr::Sub(Box::new(lhs), Box::new(self.term()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            if self.next_is('*') {
+                self.pos += 1;
+                lhs = Expr::Mul(Box::new(lhs), Box::new(self.unary()?));
+            } else if self.next_is('/') {
+                self.pos += 1;
+                lhs = Expr::Div(Box::new(lhs), Box::new(self.unary()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("expression is nested too deeply".to_string());
+        }
+        let e = if self.next_is('-') {
+            self.pos += 1;
+            self.unary().map(|a| Expr::Neg(Box::new(a)))
+        } else if self.next_is('+') {
+            self.pos += 1;
+            self.unary()
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+        e
+    }
+
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.primary()?;
+        if self.next_is('^') {
+            self.pos += 1;
+            return Ok(Expr::Pow(Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let at = self.position();
+        let tok = match self.tokens.get(self.pos) {
+            Some((t, _)) => t.clone(),
+            None => return Err(format!("unexpected end of expression at position {}", at)),
+        };
+        self.pos += 1;
+        match tok {
+            Token::Num(n) => Ok(Expr::Num(n)),
+            Token::Op('(') => {
+                let e = self.expr()?;
+                self.expect(')')?;
+                Ok(e)
+            }
+            Token::Ident(name) => match name.as_str() {
+                "x" => Ok(Expr::X),
+                "y" => Ok(Expr::Y),
+                "pi" => Ok(Expr::Num(std::f64::consts::PI)),
+                "e" => Ok(Expr::Num(std::f64::consts::E)),
+                _ => {
+                    let f = match Func::from_name(&name) {
+                        Some(f) => f,
+                        None => return Err(format!("unknown name '{}' at position {}", name, at)),
+                    };
+                    self.expect('(')?;
+                    let mut args = vec![self.expr()?];
+                    while self.next_is(',') {
+                        self.pos += 1;
+                        args.push(self.expr()?);
+                    }
+                    self.expect(')')?;
+                    if args.len() != f.arity() {
+                        return Err(format!(
+                            "'{}' at position {} takes {} argument(s), but was given {}",
+                            name,
+                            at,
+                            f.arity(),
+                            args.len()
+                        ));
+                    }
+                    Ok(Expr::Call(f, args))
+                }
+            },
+            Token::Op(c) => Err(format!("unexpected '{}' at position {}", c, at)),
+        }
+    }
+}
+
+// Parses an expression in x and y, eg "sin(x) * cos(y)"
+pub(crate) fn parse(text: &str) -> Result<Expr, String> {
+    let len = text.chars().count();
+    if len > MAX_LENGTH {
+        return Err(format!("expression is too long, at {} characters.  It can have at most {}", len, MAX_LENGTH));
+    }
+    let tokens = tokenise(text)?;
+    let mut p = Parser {
+        tokens,
+        pos: 0,
+        end: len + 1,
+        depth: 0,
+    };
+    let e = p.expr()?;
+    if p.pos < p.tokens.len() {
+        return Err(format!("unexpected input at position {}", p.position()));
+    }
+    Ok(e)
+}
+
+// Samples the function across the given range, creating a grid of quads.  Grid points where the function doesn't give
+// a finite value are left out, along with the quads using them, leaving holes in the surface.  Any grid points cut off
+// from the surface by the holes are left out too
+pub(crate) fn surface(f: &Expr, x_min: f64, x_max: f64, y_min: f64, y_max: f64, resolution: u32) -> Object {
+    let n = resolution.clamp(1, MAX_RESOLUTION);
+    let mut ob = Object::new();
+
+    // The index of each grid point in the object, if it has a valid value
+    let mut grid: Vec<Option<i32>> = Vec::with_capacity(((n + 1) * (n + 1)) as usize);
+    let mut low = f64::INFINITY;
+    let mut high = f64::NEG_INFINITY;
+    for i in 0..=n {
+        let y = y_min + (y_max - y_min) * i as f64 / n as f64;
+        for j in 0..=n {
+            let x = x_min + (x_max - x_min) * j as f64 / n as f64;
+            let z = f.eval(x, y);
+            if z.is_finite() {
+                let num = ob.points.len() as i32;
+                ob.points.push(Point { num, x, y: z, z: y });
+                grid.push(Some(num));
+                low = low.min(z);
+                high = high.max(z);
+            } else {
+                grid.push(None);
+            }
+        }
+    }
+
+    // Only quads with all four corners valid are kept
+    let idx = |i: u32, j: u32| grid[(i * (n + 1) + j) as usize];
+    for i in 0..n {
+        for j in 0..n {
+            if let (Some(a), Some(b), Some(c), Some(d)) = (idx(i, j), idx(i + 1, j), idx(i + 1, j + 1), idx(i, j + 1)) {
+                let height = (ob.points[a as usize].y
+                    + ob.points[b as usize].y
+                    + ob.points[c as usize].y
+                    + ob.points[d as usize].y)
+                    / 4.0;
+                let t = if high > low { (height - low) / (high - low) } else { 0.5 };
+                ob.surfaces.push(vec![a, b, c, d]);
+                ob.surface_materials.push(ob.material.with_diffuse(&colour_map(t)));
+            }
+        }
+    }
+    ob.edges = surface_edges(&ob.surfaces);
+    ob.material.diffuse = colour_map(0.5);
+    mesh::remove_unused_points(&mut ob);
+    ob
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(text: &str, x: f64, y: f64) -> f64 {
+        parse(text).unwrap().eval(x, y)
+    }
+
+    #[test]
+    fn tokens_have_their_positions() {
+        let tokens = tokenise("2.5e-1 * Sin(x)").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Num(0.25), 1),
+                (Token::Op('*'), 8),
+                (Token::Ident("sin".to_string()), 10),
+                (Token::Op('('), 13),
+                (Token::Ident("x".to_string()), 14),
+                (Token::Op(')'), 15),
+            ]
+        );
+        assert_eq!(tokenise("x $ y"), Err("unexpected character '$' at position 3".to_string()));
+        assert_eq!(tokenise("1.2.3"), Err("invalid number '1.2.3' at position 1".to_string()));
+    }
+
+    #[test]
+    fn multiplication_comes_before_addition() {
+        let two_times_three = Expr::Mul(Box::new(Expr::Num(2.0)), Box::new(Expr::Num(3.0)));
+        assert_eq!(parse("1 + 2 * 3"), Ok(Expr::Add(Box::new(Expr::Num(1.0)), Box::new(two_times_three))));
+        assert_eq!(value("(1 + 2) * 3", 0.0, 0.0), 9.0);
+        assert_eq!(value("8 / 4 / 2", 0.0, 0.0), 1.0);
+        assert_eq!(value("8 - 4 - 2", 0.0, 0.0), 2.0);
+        assert_eq!(value("x * 2 + y", 3.0, 4.0), 10.0);
+    }
+
+    #[test]
+    fn powers_group_to_the_right() {
+        assert_eq!(value("2 ^ 3 ^ 2", 0.0, 0.0), 512.0);
+        assert_eq!(value("2 ^ -1", 0.0, 0.0), 0.5);
+        assert_eq!(value("2 * 3 ^ 2", 0.0, 0.0), 18.0);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(value("-2 ^ 2", 0.0, 0.0), -4.0);
+        assert_eq!(value("--x", 3.0, 0.0), 3.0);
+        assert_eq!(value("3 - -2", 0.0, 0.0), 5.0);
+        assert_eq!(value("+x * -y", 2.0, 5.0), -10.0);
+    }
+
+    #[test]
+    fn functions_and_constants() {
+        assert_eq!(value("max(x, y) - min(x, y)", 2.0, 7.0), 5.0);
+        assert_eq!(value("pow(2, 10)", 0.0, 0.0), 1024.0);
+        assert_eq!(value("log(1000)", 0.0, 0.0), 3.0);
+        assert_eq!(value("ln(e)", 0.0, 0.0), 1.0);
+        assert_eq!(value("cos(pi)", 0.0, 0.0), -1.0);
+        assert!(value("sqrt(x)", -1.0, 0.0).is_nan());
+    }
+
+    #[test]
+    fn errors_give_their_positions() {
+        assert_eq!(parse("min(1)"), Err("'min' at position 1 takes 2 argument(s), but was given 1".to_string()));
+        assert_eq!(parse("1 + sin(x, y)"), Err("'sin' at position 5 takes 1 argument(s), but was given 2".to_string()));
+        assert_eq!(parse("2 * z"), Err("unknown name 'z' at position 5".to_string()));
+        assert_eq!(parse("(x + 1"), Err("expected ')' at position 7".to_string()));
+        assert_eq!(parse("x +"), Err("unexpected end of expression at position 4".to_string()));
+        assert_eq!(parse("x y"), Err("unexpected input at position 3".to_string()));
+        assert_eq!(parse("* x"), Err("unexpected '*' at position 1".to_string()));
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |n: usize| format!("{}x{}", "(".repeat(n), ")".repeat(n));
+        assert_eq!(value(&nested(100), 2.0, 0.0), 2.0);
+        assert_eq!(parse(&nested(300)), Err("expression is nested too deeply".to_string()));
+        assert_eq!(parse(&format!("{}x", "-".repeat(300))), Err("expression is nested too deeply".to_string()));
+        assert_eq!(parse(&format!("x{}", "^x".repeat(300))), Err("expression is nested too deeply".to_string()));
+        assert!(parse(&format!("x{}", " + 1".repeat(300))).unwrap_err().starts_with("expression is too long"));
+    }
+
+    #[test]
+    fn surface_grid() {
+        let ob = surface(&parse("x + y").unwrap(), 0.0, 2.0, 0.0, 4.0, 2);
+        assert_eq!(ob.points.len(), 9);
+        assert_eq!(ob.surfaces.len(), 4);
+        assert_eq!(ob.surface_materials.len(), 4);
+        assert_eq!(ob.edges.len(), 12);
+
+        // The function's Y runs along the world space Z axis, and its value up the Y axis
+        let p = ob.points[8];
+        assert_eq!((p.x, p.y, p.z), (2.0, 6.0, 4.0));
+    }
+
+    #[test]
+    fn holes_leave_out_quads_and_their_unused_points() {
+        // Valid in the bottom left grid square, and at the top right corner on its own
+        let f = parse("sqrt(max(1 - max(x, y), min(x, y) - 2))").unwrap();
+        let ob = surface(&f, 0.0, 2.0, 0.0, 2.0, 2);
+        assert_eq!(ob.surfaces, vec![vec![0, 2, 3, 1]]);
+        assert_eq!(ob.edges.len(), 4);
+        let corners: Vec<(f64, f64)> = ob.points.iter().map(|p| (p.x, p.z)).collect();
+        assert_eq!(corners, vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]);
+    }
+}
@@ -0,0 +1,289 @@
+// Procedural generators for the basic shapes
+//
+// Each generator returns an object centred on the origin, ready to be passed to import_object().  Surfaces are wound
+// counter-clockwise when viewed from outside the shape, and the edges are the unique boundaries of the surfaces.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use crate::vector::{scaled, unit};
+use crate::mesh::surface_edges;
+use crate::{Object, Point};
+
+// The most times an icosphere can be subdivided.  Each subdivision quadruples the number of surfaces
+const MAX_ICO_SUBDIVISIONS: u32 = 5;
+
+// The most segments or rings a round shape can have, and the most divisions along each side of a cube face or plane
+const MAX_SEGMENTS: u32 = 256;
+const MAX_DIVISIONS: u32 = 200;
+
+// Generates a shape from its name and params (see add_primitive() for the list).  Params which are left out use
+// defaults.  Also returns the number of params the shape takes.  Returns None if the kind of shape isn't known
+pub(crate) fn by_name(kind: &str, params: &[f64]) -> Option<(Object, usize)> {
+    let p = |i: usize, default: f64| -> f64 { params.get(i).copied().unwrap_or(default) };
+    let shape = match kind {
+        "cube" => (cube(p(0, 2.0), p(1, 1.0) as u32), 2),
+        "sphere" => (uv_sphere(p(0, 1.0), p(1, 16.0) as u32, p(2, 8.0) as u32), 3),
+        "icosphere" => (icosphere(p(0, 1.0), p(1, 1.0) as u32), 2),
+        "cylinder" => (cylinder(p(0, 1.0), p(1, 2.0), p(2, 16.0) as u32), 3),
+        "cone" => (cone(p(0, 1.0), p(1, 2.0), p(2, 16.0) as u32), 3),
+        "torus" => (torus(p(0, 1.5), p(1, 0.5), p(2, 16.0) as u32, p(3, 8.0) as u32), 4),
+        "plane" => (plane(p(0, 4.0), p(1, 4.0), p(2, 4.0) as u32, p(3, 4.0) as u32), 4),
+        _ => return None,
+    };
+    Some(shape)
+}
+
+// A cube with each face split into divisions x divisions squares
+pub(crate) fn cube(size: f64, divisions: u32) -> Object {
+    let n = divisions.clamp(1, MAX_DIVISIONS) as i32;
+    let step = size / n as f64;
+    let half = size / 2.0;
+
+    // Each face is given as a starting corner plus the two directions to walk across it, in lattice units.  The cross
+    // product of the two directions points outwards, which keeps the winding consistent
+    let faces: [([i32; 3], [i32; 3], [i32; 3]); 6] = [
+        ([n, 0, 0], [0, 1, 0], [0, 0, 1]), // +X
+        ([0, 0, 0], [0, 0, 1], [0, 1, 0]), // -X
+        ([0, n, 0], [0, 0, 1], [1, 0, 0]), // +Y
+        ([0, 0, 0], [1, 0, 0], [0, 0, 1]), // -Y
+        ([0, 0, n], [1, 0, 0], [0, 1, 0]), // +Z
+        ([0, 0, 0], [0, 1, 0], [1, 0, 0]), // -Z
+    ];
+
+    // Points along the cube edges are shared between faces, so they're looked up by their lattice position
+    let mut ob = Object::new();
+    let mut lattice: HashMap<[i32; 3], i32> = HashMap::new();
+    for (origin, u, v) in faces.iter() {
+        let mut idx = |a: i32, b: i32| -> i32 {
+            let pos = [
+                origin[0] + u[0] * a + v[0] * b,
+                origin[1] + u[1] * a + v[1] * b,
+                origin[2] + u[2] * a + v[2] * b,
+            ];
+            *lattice.entry(pos).or_insert_with(|| {
+                add_point(
+                    &mut ob,
+                    pos[0] as f64 * step - half,
+                    pos[1] as f64 * step - half,
+                    pos[2] as f64 * step - half,
+                )
+            })
+        };
+        let mut quads = Vec::new();
+        for a in 0..n {
+            for b in 0..n {
+                quads.push(vec![idx(a, b), idx(a + 1, b), idx(a + 1, b + 1), idx(a, b + 1)]);
+            }
+        }
+        ob.surfaces.append(&mut quads);
+    }
+    finish(ob)
+}
+
+// A sphere made from rings of latitude and segments of longitude
+pub(crate) fn uv_sphere(radius: f64, segments: u32, rings: u32) -> Object {
+    let segments = segments.clamp(3, MAX_SEGMENTS);
+    let rings = rings.clamp(2, MAX_SEGMENTS);
+    let mut ob = Object::new();
+
+    let top = add_point(&mut ob, 0.0, radius, 0.0);
+    for i in 1..rings {
+        let phi = PI * i as f64 / rings as f64;
+        for j in 0..segments {
+            let theta = 2.0 * PI * j as f64 / segments as f64;
+            add_point(&mut ob, radius * phi.sin() * theta.cos(), radius * phi.cos(), radius * phi.sin() * theta.sin());
+        }
+    }
+    let bottom = add_point(&mut ob, 0.0, -radius, 0.0);
+
+    // Index of point j on ring i, where ring 1 is the one closest to the top
+    let ring = |i: u32, j: u32| -> i32 { (1 + (i - 1) * segments + (j % segments)) as i32 };
+    for j in 0..segments {
+        ob.surfaces.push(vec![top, ring(1, j + 1), ring(1, j)]);
+    }
+    for i in 1..rings - 1 {
+        for j in 0..segments {
+            ob.surfaces.push(vec![ring(i, j), ring(i, j + 1), ring(i + 1, j + 1), ring(i + 1, j)]);
+        }
+    }
+    for j in 0..segments {
+        ob.surfaces.push(vec![bottom, ring(rings - 1, j), ring(rings - 1, j + 1)]);
+    }
+    finish(ob)
+}
+
+// A sphere made by repeatedly subdividing the triangles of an icosahedron.  The surfaces are more even in size than
+// those of a UV sphere
+pub(crate) fn icosphere(radius: f64, subdivisions: u32) -> Object {
+    let t = (1.0 + 5.0_f64.sqrt()) / 2.0;
+    let mut verts: Vec<[f64; 3]> = vec![
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ];
+    let mut faces: Vec<[usize; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    // Split each triangle into four, reusing the mid points of edges shared with neighbouring triangles
+    for _ in 0..subdivisions.min(MAX_ICO_SUBDIVISIONS) {
+        let mut mid_points: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut mid = |a: usize, b: usize, verts: &mut Vec<[f64; 3]>| -> usize {
+            let key = if a < b { (a, b) } else { (b, a) };
+            *mid_points.entry(key).or_insert_with(|| {
+                let (p, q) = (verts[a], verts[b]);
+                verts.push([(p[0] + q[0]) / 2.0, (p[1] + q[1]) / 2.0, (p[2] + q[2]) / 2.0]);
+                verts.len() - 1
+            })
+        };
+        let mut new_faces = Vec::with_capacity(faces.len() * 4);
+        for f in faces.iter() {
+            let ab = mid(f[0], f[1], &mut verts);
+            let bc = mid(f[1], f[2], &mut verts);
+            let ca = mid(f[2], f[0], &mut verts);
+            new_faces.push([f[0], ab, ca]);
+            new_faces.push([f[1], bc, ab]);
+            new_faces.push([f[2], ca, bc]);
+            new_faces.push([ab, bc, ca]);
+        }
+        faces = new_faces;
+    }
+
+    // Push every point out onto the surface of the sphere
+    let mut ob = Object::new();
+    for v in verts.iter() {
+        let v = scaled(unit(*v), radius);
+        add_point(&mut ob, v[0], v[1], v[2]);
+    }
+    for f in faces.iter() {
+        ob.surfaces.push(vec![f[0] as i32, f[1] as i32, f[2] as i32]);
+    }
+    finish(ob)
+}
+
+// A cylinder standing upright on the Y axis, with its ends capped
+pub(crate) fn cylinder(radius: f64, height: f64, segments: u32) -> Object {
+    let segments = segments.clamp(3, MAX_SEGMENTS);
+    let mut ob = Object::new();
+    for y in [-height / 2.0, height / 2.0].iter() {
+        for j in 0..segments {
+            let theta = 2.0 * PI * j as f64 / segments as f64;
+            add_point(&mut ob, radius * theta.cos(), *y, radius * theta.sin());
+        }
+    }
+
+    let bottom = |j: u32| -> i32 { (j % segments) as i32 };
+    let top = |j: u32| -> i32 { (segments + (j % segments)) as i32 };
+    for j in 0..segments {
+        ob.surfaces.push(vec![bottom(j), top(j), top(j + 1), bottom(j + 1)]);
+    }
+    ob.surfaces.push((0..segments).map(bottom).collect());
+    ob.surfaces.push((0..segments).rev().map(top).collect());
+    finish(ob)
+}
+
+// A cone standing upright on the Y axis, with its base capped
+pub(crate) fn cone(radius: f64, height: f64, segments: u32) -> Object {
+    let segments = segments.clamp(3, MAX_SEGMENTS);
+    let mut ob = Object::new();
+    for j in 0..segments {
+        let theta = 2.0 * PI * j as f64 / segments as f64;
+        add_point(&mut ob, radius * theta.cos(), -height / 2.0, radius * theta.sin());
+    }
+    let apex = add_point(&mut ob, 0.0, height / 2.0, 0.0);
+
+    let base = |j: u32| -> i32 { (j % segments) as i32 };
+    for j in 0..segments {
+        ob.surfaces.push(vec![base(j), apex, base(j + 1)]);
+    }
+    ob.surfaces.push((0..segments).map(base).collect());
+    finish(ob)
+}
+
+// A torus lying flat in the XZ plane.  The tube of the given minor radius is swept around a circle of the major radius
+pub(crate) fn torus(major_radius: f64, minor_radius: f64, major_segments: u32, minor_segments: u32) -> Object {
+    let major_segments = major_segments.clamp(3, MAX_SEGMENTS);
+    let minor_segments = minor_segments.clamp(3, MAX_SEGMENTS);
+    let mut ob = Object::new();
+    for i in 0..major_segments {
+        let theta = 2.0 * PI * i as f64 / major_segments as f64;
+        for j in 0..minor_segments {
+            let phi = 2.0 * PI * j as f64 / minor_segments as f64;
+            let r = major_radius + minor_radius * phi.cos();
+            add_point(&mut ob, r * theta.cos(), minor_radius * phi.sin(), r * theta.sin());
+        }
+    }
+
+    let idx = |i: u32, j: u32| -> i32 { ((i % major_segments) * minor_segments + (j % minor_segments)) as i32 };
+    for i in 0..major_segments {
+        for j in 0..minor_segments {
+            ob.surfaces.push(vec![idx(i, j), idx(i, j + 1), idx(i + 1, j + 1), idx(i + 1, j)]);
+        }
+    }
+    finish(ob)
+}
+
+// A flat rectangle in the XZ plane, facing up the Y axis, split into a grid of squares
+pub(crate) fn plane(width: f64, depth: f64, x_divisions: u32, z_divisions: u32) -> Object {
+    let nx = x_divisions.clamp(1, MAX_DIVISIONS);
+    let nz = z_divisions.clamp(1, MAX_DIVISIONS);
+    let mut ob = Object::new();
+    for i in 0..=nz {
+        for j in 0..=nx {
+            let x = width * (j as f64 / nx as f64 - 0.5);
+            let z = depth * (i as f64 / nz as f64 - 0.5);
+            add_point(&mut ob, x, 0.0, z);
+        }
+    }
+
+    let idx = |i: u32, j: u32| -> i32 { (i * (nx + 1) + j) as i32 };
+    for i in 0..nz {
+        for j in 0..nx {
+            ob.surfaces.push(vec![idx(i, j), idx(i + 1, j), idx(i + 1, j + 1), idx(i, j + 1)]);
+        }
+    }
+    finish(ob)
+}
+
+// Adds a point to the object, returning its index
+fn add_point(ob: &mut Object, x: f64, y: f64, z: f64) -> i32 {
+    let num = ob.points.len() as i32;
+    ob.points.push(Point { num, x, y, z });
+    num
+}
+
+// Fills in the edges from the surfaces, and gives the object a default colour
+fn finish(mut ob: Object) -> Object {
+    ob.edges = surface_edges(&ob.surfaces);
+    ob.material.diffuse = "lightgray".into();
+    ob
+}
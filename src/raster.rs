@@ -0,0 +1,342 @@
+// A software renderer, drawing into an RGBA pixel buffer
+//
+// This lets images be rendered without a canvas (eg natively, or at a larger size than the window).  Shapes are
+// filled a row at a time, using several sub rows per pixel to smooth their edges.  Text is drawn with a small built in
+// bitmap font, so it only roughly matches the fonts used on the canvas.
+
+use crate::colour::{parse_colour, Rgba};
+use crate::painter::Painter;
+use crate::shading::Shade;
+use crate::vector::cross_2d;
+
+// The number of sub rows sampled per row of pixels, for anti-aliasing
+const SUB_ROWS: usize = 4;
+
+// The largest image width or height which can be rendered, and the most memory its pixels can take (4 bytes each).  An
+// image can't be the largest size in both directions, as that would need 1 GiB
+pub(crate) const MAX_SIZE: u32 = 16384;
+pub(crate) const MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+// A 5 x 7 bitmap font.  Each row of a glyph is a byte, with the leftmost pixel in bit 4
+const FONT: [(char, [u8; 7]); 47] = [
+    ('0', [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e]),
+    ('1', [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    ('2', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f]),
+    ('3', [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e]),
+    ('4', [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02]),
+    ('5', [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e]),
+    ('6', [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e]),
+    ('7', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e]),
+    ('9', [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c]),
+    ('A', [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11]),
+    ('B', [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e]),
+    ('C', [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e]),
+    ('D', [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c]),
+    ('E', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f]),
+    ('F', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10]),
+    ('G', [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f]),
+    ('H', [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11]),
+    ('I', [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f]),
+    ('M', [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    ('P', [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10]),
+    ('Q', [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d]),
+    ('R', [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11]),
+    ('S', [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e]),
+    ('T', [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a]),
+    ('X', [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04]),
+    ('Z', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f]),
+    ('-', [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08]),
+    (':', [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f]),
+    ('=', [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00]),
+    ('?', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+];
+
+// A straight edge of a shape, from one point to another
+type Segment = ((f64, f64), (f64, f64));
+
+// Draws into an RGBA pixel buffer, which starts out white
+pub(crate) struct RasterPainter {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) pixels: Vec<u8>, // 4 bytes per pixel, in rows from the top left
+    coverage: Vec<f32>,         // Scratch space for the amount of each pixel in a row covered by a shape
+}
+
+impl RasterPainter {
+    pub(crate) fn new(width: usize, height: usize) -> RasterPainter {
+        RasterPainter {
+            width,
+            height,
+            pixels: vec![255; width * height * 4],
+            coverage: vec![0.0; width + 1],
+        }
+    }
+
+    // Blends a colour into a pixel, with the given opacity
+    pub(crate) fn blend(&mut self, x: usize, y: usize, c: Rgba, alpha: f64) {
+        let a = (c.a * alpha).clamp(0.0, 1.0);
+        if a <= 0.0 {
+            return;
+        }
+        let i = ((y * self.width) + x) * 4;
+        let mix = |dst: u8, src: u8| -> u8 { ((src as f64 * a) + (dst as f64 * (1.0 - a))).round() as u8 };
+        self.pixels[i] = mix(self.pixels[i], c.r);
+        self.pixels[i + 1] = mix(self.pixels[i + 1], c.g);
+        self.pixels[i + 2] = mix(self.pixels[i + 2], c.b);
+        self.pixels[i + 3] = 255;
+    }
+
+    // Fills a polygon using the non-zero winding rule (the same as the canvas default)
+    pub(crate) fn fill(&mut self, points: &[(f64, f64)], c: Rgba) {
+        if points.len() < 3 {
+            return;
+        }
+        let edges: Vec<Segment> =
+            (0..points.len()).map(|k| (points[k], points[(k + 1) % points.len()])).collect();
+        self.fill_edges(&edges, |_, _| c);
+    }
+
+    // Fills the area covered by any of the given triangles (as positions in the list of points).  Triangles which
+    // have been turned over by the projection are turned back, so they add to the area rather than cancelling out
+    pub(crate) fn fill_triangles(&mut self, points: &[(f64, f64)], triangles: &[[usize; 3]], c: Rgba) {
+        self.fill_edges(&triangle_edges(points, triangles), |_, _| c);
+    }
+
+    // Fills the area covered by the triangles of a surface with the colours of its lighting.  Each pixel takes the
+    // colour at its centre, from whichever triangle it's in (or nearest to, for pixels only partly covered)
+    pub(crate) fn fill_shaded(&mut self, points: &[(f64, f64)], triangles: &[[usize; 3]], shade: &Shade) {
+        self.fill_edges(&triangle_edges(points, triangles), |x, y| {
+            let p = (x as f64 + 0.5, y as f64 + 0.5);
+            let mut best = ([0, 0, 0], [1.0, 0.0, 0.0], f64::NEG_INFINITY);
+            for t in triangles.iter() {
+                let (a, b, c) = (points[t[0]], points[t[1]], points[t[2]]);
+                let area = cross_2d(a, b, c);
+                if area == 0.0 || !area.is_finite() {
+                    continue;
+                }
+                let w = [cross_2d(b, c, p) / area, cross_2d(c, a, p) / area, cross_2d(a, b, p) / area];
+                let worst = w[0].min(w[1]).min(w[2]);
+                if worst > best.2 {
+                    best = (*t, w, worst);
+                }
+            }
+
+            // Pixels outside the triangle are given the colour on its nearest edge
+            let (t, w, _) = best;
+            let w = w.map(|w| w.max(0.0));
+            let sum = w[0] + w[1] + w[2];
+            shade.blend(t, w.map(|w| w / sum))
+        });
+    }
+
+    // Fills the shape outlined by the edges, using the non-zero winding rule.  The colour of each pixel comes from
+    // its column and row
+    fn fill_edges(&mut self, edges: &[Segment], colour: impl Fn(usize, usize) -> Rgba) {
+        if edges.is_empty() {
+            return;
+        }
+        let min_y = edges.iter().map(|e| e.0 .1).fold(f64::INFINITY, f64::min).max(0.0);
+        let max_y = edges.iter().map(|e| e.0 .1).fold(f64::NEG_INFINITY, f64::max).min(self.height as f64);
+        if min_y.is_nan() || max_y.is_nan() || min_y >= max_y {
+            return;
+        }
+
+        let mut crossings: Vec<(f64, i32)> = Vec::new();
+        for y in (min_y.floor() as usize)..(max_y.ceil() as usize).min(self.height) {
+            let mut lo = self.width;
+            let mut hi = 0;
+            for sub in 0..SUB_ROWS {
+                let sy = y as f64 + ((sub as f64 + 0.5) / SUB_ROWS as f64);
+
+                // Find where each edge of the shape crosses this sub row, and in which direction
+                crossings.clear();
+                for ((x1, y1), (x2, y2)) in edges.iter().copied() {
+                    if (y1 <= sy && y2 > sy) || (y2 <= sy && y1 > sy) {
+                        let x = x1 + ((sy - y1) / (y2 - y1) * (x2 - x1));
+                        crossings.push((x, if y2 > y1 { 1 } else { -1 }));
+                    }
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                // Add up the coverage of the spans which are inside the shape
+                let mut winding = 0;
+                for k in 0..crossings.len() {
+                    winding += crossings[k].1;
+                    if winding != 0 && k + 1 < crossings.len() {
+                        let xa = crossings[k].0.max(0.0);
+                        let xb = crossings[k + 1].0.min(self.width as f64);
+                        if xa < xb {
+                            self.add_span(xa, xb, 1.0 / SUB_ROWS as f32);
+                            lo = lo.min(xa.floor() as usize);
+                            hi = hi.max((xb.ceil() as usize).min(self.width));
+                        }
+                    }
+                }
+            }
+            for x in lo..hi {
+                let cov = std::mem::replace(&mut self.coverage[x], 0.0);
+                if cov > 0.0 {
+                    self.blend(x, y, colour(x, y), cov.min(1.0) as f64);
+                }
+            }
+        }
+    }
+
+    // Adds coverage for the span between two x positions, including partly covered pixels at either end
+    fn add_span(&mut self, xa: f64, xb: f64, amount: f32) {
+        let first = xa.floor() as usize;
+        let last = (xb.ceil() as usize).saturating_sub(1);
+        if first == last {
+            self.coverage[first] += amount * (xb - xa) as f32;
+            return;
+        }
+        self.coverage[first] += amount * ((first + 1) as f64 - xa) as f32;
+        for x in (first + 1)..last {
+            self.coverage[x] += amount;
+        }
+        self.coverage[last] += amount * (xb - last as f64) as f32;
+    }
+}
+
+impl Painter for RasterPainter {
+    fn polygon(&mut self, points: &[(f64, f64)], fill: &str) {
+        if let Some(c) = parse_colour(fill) {
+            self.fill(points, c);
+        }
+    }
+
+    // Surfaces are filled by their triangles, so ones which don't lie flat or which cross over themselves still cover
+    // the right area
+    fn surface(&mut self, outline: &[(f64, f64)], triangles: &[[usize; 3]], fill: &str) {
+        if let Some(c) = parse_colour(fill) {
+            self.fill_triangles(outline, triangles, c);
+        }
+    }
+
+    fn shaded_surface(&mut self, outline: &[(f64, f64)], triangles: &[[usize; 3]], shade: &Shade) {
+        if shade.is_even() {
+            self.fill_triangles(outline, triangles, shade.corner(0));
+        } else {
+            self.fill_shaded(outline, triangles, shade);
+        }
+    }
+
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), colour: &str, width: f64) {
+        let c = match parse_colour(colour) {
+            Some(c) => c,
+            None => return,
+        };
+
+        // Lines are drawn as long thin rectangles
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len == 0.0 {
+            return;
+        }
+        let (nx, ny) = (-dy / len * width / 2.0, dx / len * width / 2.0);
+        self.fill(
+            &[
+                (from.0 + nx, from.1 + ny),
+                (to.0 + nx, to.1 + ny),
+                (to.0 - nx, to.1 - ny),
+                (from.0 - nx, from.1 - ny),
+            ],
+            c,
+        );
+    }
+
+    fn circle(&mut self, centre: (f64, f64), radius: f64, fill: &str) {
+        let c = match parse_colour(fill) {
+            Some(c) => c,
+            None => return,
+        };
+        let sides = ((radius * 4.0).ceil() as usize).clamp(8, 64);
+        let points: Vec<(f64, f64)> = (0..sides)
+            .map(|i| {
+                let a = 2.0 * std::f64::consts::PI * i as f64 / sides as f64;
+                (centre.0 + (radius * a.cos()), centre.1 + (radius * a.sin()))
+            })
+            .collect();
+        self.fill(&points, c);
+    }
+
+    fn text(&mut self, text: &str, pos: (f64, f64), font: &str, colour: &str) {
+        let c = match parse_colour(colour) {
+            Some(c) => c,
+            None => return,
+        };
+
+        // The font size is the part of the CSS font ending in "px".  The glyphs are scaled to about the height of
+        // capital letters at that size
+        let size = font
+            .split_whitespace()
+            .find_map(|p| p.strip_suffix("px").and_then(|n| n.parse::<f64>().ok()))
+            .unwrap_or(10.0);
+        let scale = size / 10.0;
+        let bold = font.split_whitespace().any(|p| p == "bold");
+        let mut x = pos.0;
+        let top = pos.1 - (7.0 * scale);
+        for ch in text.chars() {
+            let ch = ch.to_ascii_uppercase();
+            if ch != ' ' {
+                let glyph = FONT.iter().find(|(g, _)| *g == ch).or_else(|| FONT.iter().find(|(g, _)| *g == '?'));
+                if let Some((_, rows)) = glyph {
+                    for (row, bits) in rows.iter().enumerate() {
+                        for col in 0..5 {
+                            if bits & (0x10 >> col) != 0 {
+                                let px = x + (col as f64 * scale);
+                                let py = top + (row as f64 * scale);
+                                let w = if bold { scale * 1.5 } else { scale };
+                                self.fill(&[(px, py), (px + w, py), (px + w, py + scale), (px, py + scale)], c);
+                            }
+                        }
+                    }
+                }
+            }
+            x += 6.0 * scale;
+        }
+    }
+}
+
+// Returns the edges of the triangles, with each triangle turned to wind the same way
+fn triangle_edges(points: &[(f64, f64)], triangles: &[[usize; 3]]) -> Vec<Segment> {
+    let mut edges = Vec::with_capacity(triangles.len() * 3);
+    for t in triangles.iter() {
+        let (p0, mut p1, mut p2) = (points[t[0]], points[t[1]], points[t[2]]);
+        if cross_2d(p0, p1, p2) < 0.0 {
+            std::mem::swap(&mut p1, &mut p2);
+        }
+        edges.extend([(p0, p1), (p1, p2), (p2, p0)]);
+    }
+    edges
+}
+
+// Encodes RGBA pixels as a PNG image
+pub(crate) fn encode_png(width: usize, height: usize, pixels: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(pixels).map_err(|e| e.to_string())?;
+    }
+    Ok(out)
+}
@@ -0,0 +1,262 @@
+// 3D scatter plots from CSV data
+//
+// Each row of the CSV gives the x, y, and z of a data point, plus an optional fourth value used for its colour.  The
+// fourth value can be a number (coloured along a continuous colour map), a colour (eg "#ff0000" or "rgb(...)"), or
+// any other text, which is treated as a category name.  A first line whose x, y, and z are all text is taken to be a
+// header, but one with only some of them numbers is reported like any other bad line.
+//
+// As with function plots, the data X and Y are laid out across the world space X and Z axes, with the data Z pointing
+// up the world space Y axis.  The plot is a single object made only of points, each drawn as a round marker in the
+// colour of its data point.
+
+use std::collections::HashMap;
+
+use crate::colour::{category_colour, colour_map, parse_colour};
+use crate::{Object, Point};
+
+// The radius of the marker drawn for each data point, in pixels
+const MARKER_SIZE: f64 = 4.0;
+
+// The colour of data points without a colour value
+const DEFAULT_COLOUR: &str = "steelblue";
+
+// A data point read from the CSV
+struct Row {
+    x: f64,
+    y: f64,
+    z: f64,
+    colour: Option<String>, // The raw colour value, if any
+}
+
+// The data points read from a CSV file, along with messages about any rows which couldn't be used
+pub(crate) struct ScatterData {
+    rows: Vec<Row>,
+    pub(crate) errors: Vec<String>,
+}
+
+// Reads the data points from CSV text
+pub(crate) fn parse_csv(text: &str) -> ScatterData {
+    let mut data = ScatterData {
+        rows: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut first = true;
+    for (i, line) in text.lines().enumerate() {
+        let line_num = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = split_line(line);
+        let is_first = first;
+        first = false;
+        if fields.len() < 3 {
+            data.errors.push(format!("line {}: expected at least 3 values, found {}", line_num, fields.len()));
+            continue;
+        }
+        let parsed: Vec<Option<f64>> =
+            fields.iter().take(3).map(|f| f.parse::<f64>().ok().filter(|v| v.is_finite())).collect();
+
+        // A first line without any numbers is a header rather than a mistake
+        if is_first && parsed.iter().all(|v| v.is_none()) {
+            continue;
+        }
+        if let Some(k) = parsed.iter().position(|v| v.is_none()) {
+            data.errors.push(format!("line {}: '{}' is not a number", line_num, fields[k]));
+            continue;
+        }
+        let colour = fields.get(3).filter(|c| !c.is_empty()).cloned();
+        data.rows.push(Row {
+            x: parsed[0].unwrap_or_default(),
+            y: parsed[1].unwrap_or_default(),
+            z: parsed[2].unwrap_or_default(),
+            colour,
+        });
+    }
+    data
+}
+
+impl ScatterData {
+    // Returns true if no usable data points were found
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    // Creates an object with a point for each data point, fitted into a cube around the origin with the given half
+    // width.  The points are coloured individually, and there are no edges or surfaces
+    pub(crate) fn plot(&self, half_size: f64) -> Object {
+        let fit = |get: fn(&Row) -> f64| -> (f64, f64) {
+            let low = self.rows.iter().map(get).fold(f64::INFINITY, f64::min);
+            let high = self.rows.iter().map(get).fold(f64::NEG_INFINITY, f64::max);
+            let mid = (low + high) / 2.0;
+            let scale = if high > low { 2.0 * half_size / (high - low) } else { 1.0 };
+            (mid, scale)
+        };
+        let (mid_x, scale_x) = fit(|r| r.x);
+        let (mid_y, scale_y) = fit(|r| r.y);
+        let (mid_z, scale_z) = fit(|r| r.z);
+
+        let mut ob = Object::new();
+        ob.material.point_size = MARKER_SIZE;
+        for (n, r) in self.rows.iter().enumerate() {
+            ob.points.push(Point {
+                num: n as i32,
+                x: (r.x - mid_x) * scale_x,
+                y: (r.z - mid_z) * scale_z,
+                z: (r.y - mid_y) * scale_y,
+            });
+        }
+        ob.point_colours = self.colours();
+        ob
+    }
+
+    // Works out the colour of each data point
+    fn colours(&self) -> Vec<String> {
+        let values: Vec<Option<&String>> = self.rows.iter().map(|r| r.colour.as_ref()).collect();
+
+        // If every colour value is a number, they're spread along a continuous colour map
+        let numbers: Vec<Option<f64>> = values.iter().map(|v| v.and_then(|s| s.parse::<f64>().ok())).collect();
+        if values.iter().any(|v| v.is_some()) && values.iter().zip(numbers.iter()).all(|(v, n)| v.is_none() || n.is_some()) {
+            let low = numbers.iter().flatten().cloned().fold(f64::INFINITY, f64::min);
+            let high = numbers.iter().flatten().cloned().fold(f64::NEG_INFINITY, f64::max);
+            return numbers
+                .iter()
+                .map(|n| match n {
+                    Some(n) if high > low => colour_map((n - low) / (high - low)),
+                    Some(_) => colour_map(0.5),
+                    None => DEFAULT_COLOUR.to_string(),
+                })
+                .collect();
+        }
+
+        // Otherwise they're colours given directly, or category names
+        let mut categories: HashMap<&str, usize> = HashMap::new();
+        values
+            .iter()
+            .map(|v| match v {
+                None => DEFAULT_COLOUR.to_string(),
+                Some(s) if is_colour(s) => s.to_string(),
+                Some(s) => {
+                    let next = categories.len();
+                    category_colour(*categories.entry(s.as_str()).or_insert(next))
+                }
+            })
+            .collect()
+    }
+}
+
+// Returns true if the value is a CSS colour written out (rather than a category name), which can be worked out
+fn is_colour(s: &str) -> bool {
+    let written_out = s.starts_with('#') || s.starts_with("rgb(") || s.starts_with("rgba(") || s.starts_with("hsl(");
+    written_out && parse_colour(s).is_some()
+}
+
+// Splits a CSV line into its trimmed fields.  Fields can be wrapped in double quotes to include commas, with a doubled
+// quote inside them standing for a single one
+fn split_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                fields.push(field.trim().to_string());
+                field.clear();
+            }
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colours(text: &str) -> Vec<String> {
+        parse_csv(text).colours()
+    }
+
+    #[test]
+    fn a_first_line_of_text_is_a_header() {
+        let data = parse_csv("x, y, z, kind\n1, 2, 3, a\n4, 5, 6, b\n");
+        assert!(data.errors.is_empty());
+        assert_eq!(data.rows.len(), 2);
+        assert_eq!((data.rows[0].x, data.rows[0].y, data.rows[0].z), (1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn a_malformed_first_line_is_an_error() {
+        let data = parse_csv("1, two, 3\n4, 5, 6\n");
+        assert_eq!(data.errors, vec!["line 1: 'two' is not a number"]);
+        assert_eq!(data.rows.len(), 1);
+
+        // Only the first line can be a header
+        let data = parse_csv("1, 2, 3\nx, y, z\n");
+        assert_eq!(data.errors, vec!["line 2: 'x' is not a number"]);
+    }
+
+    #[test]
+    fn errors_give_their_line_numbers() {
+        // Blank lines and comments still count towards the line numbers
+        let data = parse_csv("# points\n1, 2, 3\n\n4, 5\n7, 8, inf\n10, 11, 12\n");
+        assert_eq!(data.errors, vec!["line 4: expected at least 3 values, found 2", "line 5: 'inf' is not a number"]);
+        assert_eq!(data.rows.len(), 2);
+        assert!(parse_csv("x, y, z\n").is_empty());
+    }
+
+    #[test]
+    fn quoted_fields() {
+        assert_eq!(split_line(r#"1, "a, b", 3"#), vec!["1", "a, b", "3"]);
+        assert_eq!(split_line(r#""say ""hi""", 2"#), vec![r#"say "hi""#, "2"]);
+        assert_eq!(split_line("1,,3"), vec!["1", "", "3"]);
+        let data = parse_csv("1, 2, 3, \"rgb(255, 0, 0)\"\n");
+        assert_eq!(data.rows[0].colour.as_deref(), Some("rgb(255, 0, 0)"));
+    }
+
+    #[test]
+    fn numbers_are_spread_along_the_colour_map() {
+        assert_eq!(
+            colours("0, 0, 0, 10\n0, 0, 0, 20\n0, 0, 0, 15\n0, 0, 0\n"),
+            vec![colour_map(0.0), colour_map(1.0), colour_map(0.5), DEFAULT_COLOUR.to_string()]
+        );
+
+        // All the same value is the middle of the map
+        assert_eq!(colours("0, 0, 0, 3\n0, 0, 0, 3\n"), vec![colour_map(0.5), colour_map(0.5)]);
+    }
+
+    #[test]
+    fn text_is_categories_unless_it_s_a_colour() {
+        assert_eq!(
+            colours("0, 0, 0, cat\n0, 0, 0, dog\n0, 0, 0, #ff0000\n0, 0, 0, cat\n0, 0, 0, 5\n"),
+            vec![category_colour(0), category_colour(1), "#ff0000".to_string(), category_colour(0), category_colour(2)]
+        );
+
+        // Colour names are categories too, and so are colours which don't parse
+        assert_eq!(
+            colours("0, 0, 0, red\n0, 0, 0, #12\n0, 0, 0, \"rgb(0, 0, 255)\"\n"),
+            vec![category_colour(0), category_colour(1), "rgb(0, 0, 255)".to_string()]
+        );
+        assert_eq!(colours("0, 0, 0\n"), vec![DEFAULT_COLOUR.to_string()]);
+    }
+
+    #[test]
+    fn the_plot_fits_the_given_size() {
+        let data = parse_csv("0, 0, 0, a\n10, 5, 2, b\n5, 5, 5, c\n");
+        let ob = data.plot(2.0);
+        let points: Vec<(f64, f64, f64)> = ob.points.iter().map(|p| (p.x, p.y, p.z)).collect();
+
+        // The data's Z is up the world space Y axis
+        assert_eq!(points, vec![(-2.0, -2.0, -2.0), (2.0, -0.4, 2.0), (0.0, 2.0, 2.0)]);
+        assert_eq!(ob.point_colours, data.colours());
+        assert!(ob.edges.is_empty() && ob.surfaces.is_empty());
+        assert_eq!(ob.material.point_size, MARKER_SIZE);
+    }
+}
@@ -0,0 +1,593 @@
+// Loading scenes and models from files, and saving them as JSON scenes or STL models
+//
+// Three formats are understood:
+//
+//   json  A scene of one or more objects, each either a generated shape or a list of points and surfaces
+//   obj   A Wavefront OBJ model.  Only the vertices, faces, and lines are used
+//   stl   An STL model, in either the ASCII or binary form
+//
+// OBJ and STL models are loaded as a single object, which is centred on the origin and scaled to a fixed size so it's
+// always in view.  Their edges are the feature edges of their surfaces (see mesh::feature_edges()), as drawing every
+// edge of a detailed model hides its shape.
+//
+// JSON scenes written by write_json() keep everything about each object, including its materials, so they load back
+// the same.  STL models only keep the shape of the surfaces.
+//
+// The world space has X to the right, Y up, and Z pointing away from the viewer, whereas OBJ files have Z pointing
+// towards the viewer and STL files have Z pointing up.  Models are flipped around to match, with their surfaces
+// reversed so they still wind the same way when seen from outside.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::material::{check_colour, Material};
+use crate::mesh::{feature_edges, surface_edges, DEFAULT_CREASE_ANGLE};
+use crate::primitives;
+use crate::triangulate::{bridge_holes, triangulate};
+use crate::{import_object, Object, Point, Surface};
+
+// Half the width of the cube OBJ and STL models are fitted into, in world space units
+const FIT_SIZE: f64 = 5.0;
+
+// The colour of objects which don't give one
+const DEFAULT_COLOUR: &str = "lightgray";
+
+// Reads the objects from a scene file in the given format.  Objects without their own name (all of them for OBJ and
+// STL) are named after the file.  The objects are returned already placed in the world space
+pub(crate) fn parse(name: &str, format: &str, data: &[u8]) -> Result<Vec<(String, Object)>, String> {
+    match format.to_lowercase().as_str() {
+        "json" => parse_json(name, text(data)?),
+        "obj" => Ok(vec![(name.to_string(), fit(parse_obj(text(data)?)?))]),
+        "stl" => Ok(vec![(name.to_string(), fit(parse_stl(data)?))]),
+        _ => Err(format!("Unknown scene format '{}'.  It should be json, obj, or stl", format)),
+    }
+}
+
+fn text(data: &[u8]) -> Result<&str, String> {
+    std::str::from_utf8(data).map_err(|_| "The file isn't valid UTF-8 text".to_string())
+}
+
+// Reads a JSON scene.  This is either an array of objects, or a JSON object with an "objects" array:
+//
+//   { "objects": [
+//       { "name": "box", "primitive": "cube", "params": [2, 1], "position": [0, 1, 0], "colour": "red" },
+//       { "name": "tri", "points": [[0, 0, 0], [1, 0, 0], [0, 1, 0]], "surfaces": [[0, 1, 2]] }
+//   ] }
+//
+// The primitive kinds and params are the same as for add_primitive().  Objects given as points can also have "edges"
+// (which otherwise come from the surfaces).  Objects without their own edges can have a "crease_angle" in degrees, to
+// only keep the edges where their surfaces meet at more than that angle.  Surfaces with holes are given as
+// {"points": [0, 1, 2, 3], "holes": [[4, 5, 6]]}.
+//
+// Any object can have a "material" (see the material module), of which "colour" is a shorter way to give just the
+// diffuse colour.  Their surfaces can have their own materials too, either as a "surface_materials" array of changes
+// to the object's material, or a "surface_colours" array of diffuse colours, with one entry for each surface.  Points
+// can likewise have a "point_colours" array, with one entry for each point.  The highlights are part of the material,
+// so a "shininess" outside of it is an error rather than being ignored
+fn parse_json(name: &str, text: &str) -> Result<Vec<(String, Object)>, String> {
+    let doc: Value = serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let list = match doc.get("objects").unwrap_or(&doc) {
+        Value::Array(l) => l,
+        _ => return Err("The scene should be an array of objects, or have an \"objects\" array".to_string()),
+    };
+
+    let mut objects = Vec::new();
+    for (i, entry) in list.iter().enumerate() {
+        let ob_name = match entry.get("name").and_then(Value::as_str) {
+            Some(n) => n.to_string(),
+            None => format!("{} {}", name, i + 1),
+        };
+        let err = |msg: &str| format!("object '{}': {}", ob_name, msg);
+
+        let mut ob = if let Some(kind) = entry.get("primitive") {
+            let kind = kind.as_str().ok_or_else(|| err("primitive should be a string"))?;
+            let params = match entry.get("params") {
+                Some(p) => numbers(p).ok_or_else(|| err("params should be an array of numbers"))?,
+                None => Vec::new(),
+            };
+            match primitives::by_name(kind, &params) {
+                Some((ob, _)) => ob,
+                None => return Err(err(&format!("unknown primitive '{}'", kind))),
+            }
+        } else {
+            object_from_json(entry).map_err(|e| err(&e))?
+        };
+
+        if let Some(a) = entry.get("crease_angle") {
+            let a = a.as_f64().ok_or_else(|| err("crease_angle should be a number"))?;
+            if entry.get("edges").is_some() {
+                return Err(err("crease_angle can't be used along with edges"));
+            }
+            ob.edges = feature_edges(&ob.points, &ob.surfaces, a);
+        }
+        if let Some(c) = entry.get("colour") {
+            let c = c.as_str().ok_or_else(|| err("colour should be a string"))?;
+            check_colour("colour", c).map_err(|e| err(&e))?;
+            ob.material.diffuse = c.to_string();
+        }
+        if entry.get("shininess").is_some() {
+            return Err(err("shininess should be given in the material, eg \"material\": {\"shininess\": 20}"));
+        }
+        if let Some(m) = entry.get("material") {
+            ob.material = ob.material.with_json(m).map_err(|e| err(&e))?;
+        }
+        ob.surface_materials = surface_materials(entry, &ob).map_err(|e| err(&e))?;
+        ob.point_colours = point_colours(entry, &ob).map_err(|e| err(&e))?;
+        let pos = match entry.get("position") {
+            Some(p) => match numbers(p) {
+                Some(ref v) if v.len() == 3 => [v[0], v[1], v[2]],
+                _ => return Err(err("position should be an array of 3 numbers")),
+            },
+            None => [0.0; 3],
+        };
+        objects.push((ob_name, import_object(&ob, pos[0], pos[1], pos[2])));
+    }
+    Ok(objects)
+}
+
+// Builds an object from its JSON list of points, surfaces, and (optionally) edges
+fn object_from_json(entry: &Value) -> Result<Object, String> {
+    let mut ob = Object::new();
+    let points = entry.get("points").and_then(Value::as_array).ok_or("needs either a primitive or points")?;
+    for p in points.iter() {
+        match numbers(p) {
+            Some(ref v) if v.len() == 3 => ob.points.push(Point { num: ob.points.len() as i32, x: v[0], y: v[1], z: v[2] }),
+            _ => return Err("each point should be an array of 3 numbers".to_string()),
+        }
+    }
+    if ob.points.is_empty() {
+        return Err("has no points".to_string());
+    }
+    ob.surfaces = surface_lists(entry.get("surfaces"), &ob.points)?;
+    ob.edges = match entry.get("edges") {
+        Some(_) => index_lists(entry.get("edges"), ob.points.len(), 2, "edges")?,
+        None => surface_edges(&ob.surfaces),
+    };
+    ob.material.diffuse = DEFAULT_COLOUR.to_string();
+    Ok(ob)
+}
+
+// Reads the materials of an object's surfaces, which start out the same as the object's own.  Returns the ones the
+// object already has if the JSON doesn't give any
+fn surface_materials(entry: &Value, ob: &Object) -> Result<Vec<Material>, String> {
+    let list = match (entry.get("surface_colours"), entry.get("surface_materials")) {
+        (Some(_), Some(_)) => return Err("surface_colours can't be used along with surface_materials".to_string()),
+        (Some(c), None) => {
+            let bad = || "surface_colours should be an array of strings".to_string();
+            let colours = c.as_array().ok_or_else(bad)?;
+            let colours = colours.iter().map(|c| c.as_str().ok_or_else(bad)).collect::<Result<Vec<&str>, String>>()?;
+            for c in colours.iter() {
+                check_colour("surface colour", c)?;
+            }
+            colours.iter().map(|c| ob.material.with_diffuse(c)).collect::<Vec<Material>>()
+        }
+        (None, Some(m)) => {
+            let list = m.as_array().ok_or("surface_materials should be an array of materials")?;
+            list.iter().map(|m| ob.material.with_json(m)).collect::<Result<Vec<Material>, String>>()?
+        }
+        (None, None) => return Ok(ob.surface_materials.clone()),
+    };
+    if list.len() != ob.surfaces.len() {
+        return Err("there should be one surface colour or material for each surface".to_string());
+    }
+    Ok(list)
+}
+
+// Reads the colours of an object's points.  Returns the ones the object already has if the JSON doesn't give any
+fn point_colours(entry: &Value, ob: &Object) -> Result<Vec<String>, String> {
+    let c = match entry.get("point_colours") {
+        Some(c) => c,
+        None => return Ok(ob.point_colours.clone()),
+    };
+    let bad = || "point_colours should be an array of strings".to_string();
+    let colours = c.as_array().ok_or_else(bad)?;
+    let colours = colours.iter().map(|c| c.as_str().ok_or_else(bad)).collect::<Result<Vec<&str>, String>>()?;
+    for c in colours.iter() {
+        check_colour("point colour", c)?;
+    }
+    if colours.len() != ob.points.len() {
+        return Err("there should be one point colour for each point".to_string());
+    }
+    Ok(colours.iter().map(|c| c.to_string()).collect())
+}
+
+// Reads a JSON array of numbers
+fn numbers(v: &Value) -> Option<Vec<f64>> {
+    v.as_array()?.iter().map(Value::as_f64).collect()
+}
+
+// Reads a JSON array of point index lists (eg surfaces or edges), checking each index refers to an existing point
+fn index_lists(v: Option<&Value>, num_points: usize, min_len: usize, what: &str) -> Result<Vec<Vec<i32>>, String> {
+    match v {
+        Some(v) => array(v, min_len, what)?.iter().map(|l| index_list(l, num_points, min_len, what)).collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Reads a JSON array of surfaces.  A surface with holes is an object giving the "points" around its outside and a
+// list of "holes", each a list of points too.  The holes are joined to the outside, making a single surface
+fn surface_lists(v: Option<&Value>, points: &[Point]) -> Result<Vec<Surface>, String> {
+    let v = match v {
+        Some(v) => v,
+        None => return Ok(Vec::new()),
+    };
+    let mut surfaces = Vec::new();
+    for s in array(v, 3, "surfaces")?.iter() {
+        match s.get("points") {
+            Some(outer) => {
+                let outer = index_list(outer, points.len(), 3, "surfaces")?;
+                let holes = index_lists(s.get("holes"), points.len(), 3, "holes")?;
+                surfaces.push(bridge_holes(points, &outer, &holes));
+            }
+            None => surfaces.push(index_list(s, points.len(), 3, "surfaces")?),
+        }
+    }
+    Ok(surfaces)
+}
+
+fn array<'a>(v: &'a Value, min_len: usize, what: &str) -> Result<&'a Vec<Value>, String> {
+    v.as_array().ok_or_else(|| format!("{} should be arrays of at least {} point numbers", what, min_len))
+}
+
+fn index_list(v: &Value, num_points: usize, min_len: usize, what: &str) -> Result<Vec<i32>, String> {
+    let bad = || format!("{} should be arrays of at least {} point numbers", what, min_len);
+    let mut list = Vec::new();
+    for n in array(v, min_len, what)?.iter() {
+        match n.as_u64() {
+            Some(n) if (n as usize) < num_points => list.push(n as i32),
+            Some(n) => return Err(format!("{} refer to point {}, but there are only {} points", what, n, num_points)),
+            None => return Err(bad()),
+        }
+    }
+    if list.len() < min_len {
+        return Err(bad());
+    }
+    Ok(list)
+}
+
+// Reads a Wavefront OBJ model
+fn parse_obj(text: &str) -> Result<Object, String> {
+    let mut ob = Object::new();
+    let mut lines = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let err = |msg: &str| format!("line {}: {}", i + 1, msg);
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("v") => {
+                let v: Vec<f64> = parts.take(3).filter_map(|p| p.parse().ok()).collect();
+                if v.len() != 3 {
+                    return Err(err("a vertex needs 3 numbers"));
+                }
+                ob.points.push(Point { num: ob.points.len() as i32, x: v[0], y: v[1], z: -v[2] });
+            }
+            Some(kind @ "f") | Some(kind @ "l") => {
+                // Each corner is "v", "v/vt", "v//vn", or "v/vt/vn".  Only the vertex number is needed.  Negative
+                // numbers count back from the most recent vertex
+                let mut list = Vec::new();
+                for p in parts {
+                    let n: i64 = p.split('/').next().unwrap_or("").parse().map_err(|_| err("invalid vertex number"))?;
+                    let idx = if n < 0 { ob.points.len() as i64 + n } else { n - 1 };
+                    if idx < 0 || idx >= ob.points.len() as i64 {
+                        return Err(err(&format!("vertex {} doesn't exist", n)));
+                    }
+                    list.push(idx as i32);
+                }
+                if kind == "f" {
+                    if list.len() < 3 {
+                        return Err(err("a face needs at least 3 vertices"));
+                    }
+                    list.reverse();
+                    ob.surfaces.push(list);
+                } else {
+                    for pair in list.windows(2) {
+                        lines.push(pair.to_vec());
+                    }
+                }
+            }
+            _ => {} // Comments, normals, texture co-ordinates, groups, materials, etc
+        }
+    }
+    if ob.points.is_empty() {
+        return Err("The OBJ file has no vertices".to_string());
+    }
+    ob.edges = feature_edges(&ob.points, &ob.surfaces, DEFAULT_CREASE_ANGLE);
+    ob.edges.extend(lines);
+    Ok(ob)
+}
+
+// Reads an STL model.  Binary files are recognised by their size matching the triangle count in their header, as
+// ASCII files can't be told apart by their "solid" header alone
+fn parse_stl(data: &[u8]) -> Result<Object, String> {
+    let mut triangles: Vec<[[f64; 3]; 3]> = Vec::new();
+    let binary_count = if data.len() >= 84 {
+        Some(u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize)
+    } else {
+        None
+    };
+    // The count comes from the file, so the size it gives could be too big to work out on 32 bit targets
+    let binary_size = binary_count.and_then(|n| n.checked_mul(50)).and_then(|b| b.checked_add(84));
+    if binary_size == Some(data.len()) {
+        // An 80 byte header and the triangle count, then for each triangle its normal, three corners, and two bytes of
+        // attributes
+        for t in data[84..].chunks(50) {
+            let f = |k: usize| -> f64 {
+                let at = 12 + (k * 4);
+                f32::from_le_bytes([t[at], t[at + 1], t[at + 2], t[at + 3]]) as f64
+            };
+            triangles.push([[f(0), f(1), f(2)], [f(3), f(4), f(5)], [f(6), f(7), f(8)]]);
+        }
+    } else {
+        let text = text(data)?;
+        let mut corners: Vec<[f64; 3]> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("vertex") => {
+                    let v: Vec<f64> = parts.take(3).filter_map(|p| p.parse().ok()).collect();
+                    if v.len() != 3 {
+                        return Err(format!("line {}: a vertex needs 3 numbers", i + 1));
+                    }
+                    corners.push([v[0], v[1], v[2]]);
+                }
+                Some("endloop") => {
+                    if corners.len() != 3 {
+                        return Err(format!("line {}: a facet needs 3 vertices", i + 1));
+                    }
+                    triangles.push([corners[0], corners[1], corners[2]]);
+                    corners.clear();
+                }
+                _ => {}
+            }
+        }
+    }
+    if triangles.is_empty() {
+        return Err("The STL file has no triangles".to_string());
+    }
+
+    // STL files list the corners of each triangle separately, so join up the ones at the same place
+    let mut ob = Object::new();
+    let mut seen: HashMap<[u64; 3], i32> = HashMap::new();
+    for tri in triangles.iter() {
+        let mut surf = Vec::with_capacity(3);
+        for c in tri.iter().rev() {
+            let key = [c[0].to_bits(), c[1].to_bits(), c[2].to_bits()];
+            let num = *seen.entry(key).or_insert_with(|| {
+                ob.points.push(Point { num: ob.points.len() as i32, x: c[0], y: c[2], z: c[1] });
+                ob.points.len() as i32 - 1
+            });
+            surf.push(num);
+        }
+        ob.surfaces.push(surf);
+    }
+    ob.edges = feature_edges(&ob.points, &ob.surfaces, DEFAULT_CREASE_ANGLE);
+    Ok(ob)
+}
+
+// Writes objects as a JSON scene, which parse_json() reads back in the same way.  Each object is on its own line
+pub(crate) fn write_json<'a>(objects: impl Iterator<Item = (&'a String, &'a Object)>) -> String {
+    let list: Vec<String> = objects
+        .map(|(name, ob)| {
+            let mut entry = json!({
+                "name": name,
+                "points": ob.points.iter().map(|p| [p.x, p.y, p.z]).collect::<Vec<_>>(),
+                "edges": ob.edges,
+                "surfaces": ob.surfaces,
+                "material": ob.material.json(),
+            });
+            if !ob.surface_materials.is_empty() {
+                entry["surface_materials"] = ob.surface_materials.iter().map(Material::json).collect();
+            }
+            if !ob.point_colours.is_empty() {
+                entry["point_colours"] = json!(ob.point_colours);
+            }
+            format!("  {}", entry)
+        })
+        .collect();
+    format!("{{\"objects\": [\n{}\n]}}\n", list.join(",\n"))
+}
+
+// Writes objects as a binary STL model, with their surfaces split into triangles.  The objects are flipped around
+// from the world space the opposite way to parse_stl(), so a model saved and loaded again faces the same way
+pub(crate) fn write_stl<'a>(objects: impl Iterator<Item = &'a Object>) -> Vec<u8> {
+    let mut triangles: Vec<[[f32; 3]; 3]> = Vec::new();
+    for ob in objects {
+        let num_points = ob.points.len() as i32;
+        for surf in ob.surfaces.iter().filter(|s| s.iter().all(|n| *n >= 0 && *n < num_points)) {
+            for t in triangulate(&ob.points, surf).iter() {
+                let corner = |k: usize| {
+                    let p = ob.points[surf[t[k]] as usize];
+                    [p.x as f32, p.z as f32, p.y as f32]
+                };
+                triangles.push([corner(2), corner(1), corner(0)]);
+            }
+        }
+    }
+
+    // An 80 byte header and the triangle count, then for each triangle its normal, three corners, and two bytes of
+    // attributes
+    let mut data = Vec::with_capacity(84 + (triangles.len() * 50));
+    data.extend_from_slice(b"Binary STL written by canvas_2d_test1");
+    data.resize(80, b' ');
+    data.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for [a, b, c] in triangles.iter() {
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+        let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        let n = if len > 0.0 { [n[0] / len, n[1] / len, n[2] / len] } else { [0.0; 3] };
+        for v in [n, *a, *b, *c].iter().flatten() {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&[0, 0]);
+    }
+    data
+}
+
+// Centres a model on the origin and scales it to fit inside a fixed size cube
+fn fit(mut ob: Object) -> Object {
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for p in ob.points.iter() {
+        for (k, v) in [p.x, p.y, p.z].iter().enumerate() {
+            min[k] = min[k].min(*v);
+            max[k] = max[k].max(*v);
+        }
+    }
+    let centre: Vec<f64> = (0..3).map(|k| (min[k] + max[k]) / 2.0).collect();
+    let half = (0..3).map(|k| (max[k] - min[k]) / 2.0).fold(0.0, f64::max);
+    let k = if half > 0.0 { FIT_SIZE / half } else { 1.0 };
+    for p in ob.points.iter_mut() {
+        p.x = (p.x - centre[0]) * k;
+        p.y = (p.y - centre[1]) * k;
+        p.z = (p.z - centre[2]) * k;
+    }
+    ob.material.diffuse = DEFAULT_COLOUR.to_string();
+    import_object(&ob, 0.0, 0.0, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh;
+
+    fn json_error(text: &str) -> String {
+        parse_json("scene", text).err().unwrap()
+    }
+
+    fn obj_error(text: &str) -> String {
+        parse_obj(text).err().unwrap()
+    }
+
+    #[test]
+    fn json_objects() {
+        let text = r#"{"objects": [
+            {"name": "tri", "points": [[0, 0, 0], [1, 0, 0], [0, 1, 0]], "surfaces": [[0, 1, 2]],
+             "position": [1, 2, 3]},
+            {"primitive": "cube", "params": [2, 1], "colour": "red"}
+        ]}"#;
+        let objects = parse_json("scene", text).unwrap();
+        assert_eq!(objects.len(), 2);
+        let (name, tri) = &objects[0];
+        assert_eq!(name, "tri");
+        assert_eq!(tri.surfaces, vec![vec![0, 1, 2]]);
+        assert_eq!(tri.edges.len(), 3);
+        assert_eq!((tri.points[1].x, tri.points[1].y, tri.points[1].z), (2.0, 2.0, 3.0));
+        assert_eq!(objects[1].0, "scene 2");
+        assert_eq!(objects[1].1.material.diffuse, "red");
+    }
+
+    #[test]
+    fn json_point_numbers_are_checked() {
+        let points = r#""points": [[0, 0, 0], [1, 0, 0], [0, 1, 0]]"#;
+        let error = |rest: &str| json_error(&format!("[{{\"name\": \"a\", {}, {}}}]", points, rest));
+        let not_numbers = "object 'a': surfaces should be arrays of at least 3 point numbers";
+        let too_big = "object 'a': surfaces refer to point 3, but there are only 3 points";
+        assert_eq!(error(r#""surfaces": [[0, 1, 3]]"#), too_big);
+        assert_eq!(error(r#""surfaces": [[0, -1, 2]]"#), not_numbers);
+        assert_eq!(error(r#""surfaces": [[0, 1]]"#), not_numbers);
+        assert_eq!(error(r#""surfaces": [[0, 1.5, 2]]"#), not_numbers);
+        assert_eq!(error(r#""edges": [[0, 5]]"#), "object 'a': edges refer to point 5, but there are only 3 points");
+        assert_eq!(
+            error(r#""surfaces": [{"points": [0, 1, 2], "holes": [[0, 1, 9]]}]"#),
+            "object 'a': holes refer to point 9, but there are only 3 points"
+        );
+    }
+
+    #[test]
+    fn json_mistakes_are_errors() {
+        assert!(json_error("{").starts_with("Invalid JSON"));
+        assert_eq!(
+            json_error(r#"{"name": "a"}"#),
+            "The scene should be an array of objects, or have an \"objects\" array"
+        );
+        assert_eq!(json_error(r#"[{"name": "a"}]"#), "object 'a': needs either a primitive or points");
+        assert_eq!(json_error(r#"[{"name": "a", "primitive": "blob"}]"#), "object 'a': unknown primitive 'blob'");
+        assert_eq!(
+            json_error(r#"[{"name": "a", "primitive": "cube", "shininess": 20}]"#),
+            "object 'a': shininess should be given in the material, eg \"material\": {\"shininess\": 20}"
+        );
+        assert_eq!(
+            json_error(r#"[{"name": "a", "points": [[0, 0, 0], [1, 0, 0]], "point_colours": ["red"]}]"#),
+            "object 'a': there should be one point colour for each point"
+        );
+    }
+
+    #[test]
+    fn json_written_out_reads_back_the_same() {
+        let text = r##"[{"name": "a", "points": [[0, 0, 0], [1, 0, 0], [0, 1, 0]], "surfaces": [[0, 1, 2]],
+            "surface_colours": ["blue"], "point_colours": ["red", "green", "#123456"],
+            "material": {"shininess": 5}}]"##;
+        let objects = parse_json("scene", text).unwrap();
+        let written = write_json(objects.iter().map(|(n, o)| (n, o)));
+        let (name, ob) = &parse_json("scene", &written).unwrap()[0];
+        assert_eq!(name, "a");
+        assert_eq!(ob.points.len(), 3);
+        assert_eq!(ob.surfaces, vec![vec![0, 1, 2]]);
+        assert_eq!(ob.surface_materials[0].diffuse, "blue");
+        assert_eq!(ob.surface_materials[0].shininess, 5.0);
+        assert_eq!(ob.point_colours, vec!["red", "green", "#123456"]);
+    }
+
+    #[test]
+    fn obj_models() {
+        let text = "# a square\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\nf -4 -2 -1\nl 1 3\n";
+        let ob = parse_obj(text).unwrap();
+        assert_eq!(ob.points.len(), 4);
+
+        // The faces are reversed, as Z is flipped
+        assert_eq!(ob.surfaces, vec![vec![2, 1, 0], vec![3, 2, 0]]);
+        assert_eq!(ob.points[2].z, 0.0);
+        assert!(ob.edges.contains(&vec![0, 2]));
+    }
+
+    #[test]
+    fn obj_vertex_numbers_are_checked() {
+        let square = "v 0 0 0\nv 1 0 0\nv 1 1 0\n";
+        assert_eq!(obj_error(&format!("{}f 1 2 4\n", square)), "line 4: vertex 4 doesn't exist");
+        assert_eq!(obj_error(&format!("{}f 0 1 2\n", square)), "line 4: vertex 0 doesn't exist");
+        assert_eq!(obj_error(&format!("{}f -1 -2 -4\n", square)), "line 4: vertex -4 doesn't exist");
+        assert_eq!(obj_error(&format!("{}f 1 2\n", square)), "line 4: a face needs at least 3 vertices");
+        assert_eq!(obj_error(&format!("{}f 1 a 2\n", square)), "line 4: invalid vertex number");
+        assert_eq!(obj_error("v 1 2\n"), "line 1: a vertex needs 3 numbers");
+        assert_eq!(obj_error("# nothing\n"), "The OBJ file has no vertices");
+    }
+
+    #[test]
+    fn ascii_stl_models() {
+        let facet = |a: &str, b: &str, c: &str| {
+            format!("facet normal 0 0 1\nouter loop\nvertex {}\nvertex {}\nvertex {}\nendloop\nendfacet\n", a, b, c)
+        };
+        let (first, second) = (facet("0 0 0", "1 0 0", "1 1 0"), facet("0 0 0", "1 1 0", "0 1 0"));
+        let text = format!("solid square\n{}{}endsolid\n", first, second);
+        let ob = parse_stl(text.as_bytes()).unwrap();
+
+        // The corners the triangles share are joined up
+        assert_eq!(ob.points.len(), 4);
+        assert_eq!(ob.surfaces, vec![vec![0, 1, 2], vec![3, 0, 2]]);
+        assert_eq!(mesh::validate(&ob), vec![]);
+
+        let short = "solid a\nfacet\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\n";
+        assert_eq!(parse_stl(short.as_bytes()).err().unwrap(), "line 6: a facet needs 3 vertices");
+        assert_eq!(parse_stl(b"solid a\nendsolid\n").err().unwrap(), "The STL file has no triangles");
+    }
+
+    #[test]
+    fn binary_stl_models() {
+        let text = r#"[{"points": [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]], "surfaces": [[0, 1, 2, 3]]}]"#;
+        let objects = parse_json("scene", text).unwrap();
+        let data = write_stl(objects.iter().map(|(_, o)| o));
+        assert_eq!(data.len(), 84 + (2 * 50));
+        let ob = parse_stl(&data).unwrap();
+        assert_eq!(ob.points.len(), 4);
+        assert_eq!(ob.surfaces.len(), 2);
+        assert_eq!(mesh::validate(&ob), vec![]);
+    }
+
+    #[test]
+    fn binary_stl_counts_which_don_t_match_are_read_as_ascii() {
+        let mut data = vec![0xff; 80];
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&[0; 50]);
+        assert_eq!(parse_stl(&data).err().unwrap(), "The file isn't valid UTF-8 text");
+    }
+}
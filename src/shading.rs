@@ -0,0 +1,215 @@
+// Lighting for the surfaces of objects
+//
+// Without lighting each surface is filled with its plain colour.  With it, surfaces are lit by a single light above,
+// to the left of, and in front of the scene, plus some ambient light so the sides facing away from it aren't black.
+// There are three ways of shading the surfaces:
+//
+//   flat     Each surface is lit using its own normal, so it stays a single colour
+//   gouraud  The light is worked out at each corner of a surface, then blended across it
+//   phong    The normals at the corners are blended across the surface, and the light worked out for each pixel
+//
+// The normals at the corners average the surfaces around each point, except those meeting at more than the crease
+// angle (see mesh::corner_normals()), so curved shapes made of many small surfaces look smooth while their sharp edges
+// stay sharp.  Surfaces are lit on whichever side faces the viewer.  Their materials give the size and brightness of
+// the highlights where the light reflects towards the viewer, and any light the surfaces give off themselves.
+//
+// The world space is transformed along with the view, so the light and the viewer stay in the same place while the
+// scene turns.
+
+use lazy_static::lazy_static;
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+
+use crate::vector::{add, dot, scaled, unit, Vec3};
+use crate::colour::{parse_colour, Rgba};
+use crate::mesh::{corner_normals, normal, DEFAULT_CREASE_ANGLE};
+use crate::{Matrix, Object, Point, Surface};
+
+// The directions towards the light, and towards the viewer
+const LIGHT: Vec3 = [-0.4, 0.6, -0.7];
+const VIEWER: Vec3 = [0.0, 0.0, -1.0];
+
+// How much of a surface's colour comes from the ambient light, and how much from the light shining straight onto it
+const AMBIENT: f64 = 0.3;
+const DIFFUSE: f64 = 0.7;
+
+// How the surfaces are lit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ShadingMode {
+    None,    // Surfaces are their plain colour
+    Flat,    // Each surface is lit evenly
+    Gouraud, // The light at the corners is blended across each surface
+    Phong,   // The light is worked out for each pixel
+}
+
+impl ShadingMode {
+    pub(crate) fn from_name(name: &str) -> Option<ShadingMode> {
+        match name.to_lowercase().as_str() {
+            "none" => Some(ShadingMode::None),
+            "flat" => Some(ShadingMode::Flat),
+            "gouraud" => Some(ShadingMode::Gouraud),
+            "phong" => Some(ShadingMode::Phong),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ShadingMode::None => "none",
+            ShadingMode::Flat => "flat",
+            ShadingMode::Gouraud => "gouraud",
+            ShadingMode::Phong => "phong",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Shading {
+    pub(crate) mode: ShadingMode,
+    pub(crate) crease_angle: f64, // Surfaces meeting at more than this many degrees aren't smoothed into each other
+}
+
+lazy_static! {
+    pub(crate) static ref SHADING: Arc<Mutex<Shading>> =
+        Arc::new(Mutex::new(Shading { mode: ShadingMode::None, crease_angle: DEFAULT_CREASE_ANGLE }));
+}
+
+impl Shading {
+    // Works out how each surface of an object is lit.  Returns nothing when lighting is turned off, and leaves out
+    // surfaces whose colour can't be worked out, which are drawn with their plain colour instead
+    pub(crate) fn shade(&self, ob: &Object) -> Vec<Option<Shade>> {
+        if self.mode == ShadingMode::None {
+            return Vec::new();
+        }
+        let corners = match self.mode {
+            ShadingMode::Gouraud | ShadingMode::Phong => ob.corner_normals(self.crease_angle),
+            _ => Cow::Borrowed(&[][..]),
+        };
+        ob.surfaces
+            .iter()
+            .enumerate()
+            .map(|(k, surf)| {
+                let m = ob.surface_material(k);
+                let colour = m.fill()?;
+
+                // Surfaces facing away from the viewer are lit from behind, by turning their normals around
+                let face = unit(normal(&ob.points, surf));
+                let side = if face[2] > 0.0 { -1.0 } else { 1.0 };
+                let normals: Vec<Vec3> = match corners.get(k) {
+                    Some(c) => c.iter().map(|n| if *n == [0.0; 3] { face } else { *n }).collect(),
+                    None => vec![face; surf.len()],
+                };
+                let mut shade = Shade {
+                    per_pixel: self.mode == ShadingMode::Phong,
+                    normals: normals.iter().map(|n| scaled(*n, side)).collect(),
+                    colours: Vec::new(),
+                    colour,
+                    emissive: parse_colour(&m.emissive).unwrap_or(Rgba { r: 0, g: 0, b: 0, a: 1.0 }),
+                    specular: m.specular,
+                    shininess: m.shininess,
+                };
+                shade.colours = shade.normals.iter().map(|n| shade.light(*n)).collect();
+                Some(shade)
+            })
+            .collect()
+    }
+}
+
+// The normals at the corners of an object's surfaces, which are kept with the object so they're only worked out again
+// when its surfaces or the crease angle change
+#[derive(Clone, Default)]
+pub(crate) struct CornerNormals {
+    pub(crate) crease_angle: f64,       // The crease angle they were worked out with
+    pub(crate) normals: Vec<Vec<Vec3>>, // The normal at each corner of each surface
+}
+
+impl CornerNormals {
+    pub(crate) fn new(points: &[Point], surfaces: &[Surface], crease_angle: f64) -> CornerNormals {
+        CornerNormals { crease_angle, normals: corner_normals(points, surfaces, crease_angle) }
+    }
+
+    // Returns the normals after the object's points are transformed by the given matrix.  Normals are transformed by
+    // the cofactors of the matrix (its inverse transpose, scaled by its determinant), which keeps them at right angles
+    // to the surfaces and on the same side of them, even when the transformation mirrors or flattens the object
+    pub(crate) fn transformed(&self, m: &Matrix) -> CornerNormals {
+        let a = |i: usize, j: usize| m[(4 * (i % 3)) + (j % 3)];
+        let cofactor = |i: usize, j: usize| (a(i + 1, j + 1) * a(i + 2, j + 2)) - (a(i + 1, j + 2) * a(i + 2, j + 1));
+        let c = [0, 1, 2].map(|i| [0, 1, 2].map(|j| cofactor(i, j)));
+        let normals = self
+            .normals
+            .iter()
+            .map(|corners| corners.iter().map(|n| unit([0, 1, 2].map(|i| dot(c[i], *n)))).collect())
+            .collect();
+        CornerNormals { crease_angle: self.crease_angle, normals }
+    }
+}
+
+// The lighting of a surface, given by its normal at each of its corners
+pub(crate) struct Shade {
+    per_pixel: bool,    // Whether the normals are blended across the surface rather than the colours
+    normals: Vec<Vec3>, // The normal at each corner, facing the viewer
+    colours: Vec<Rgba>, // The lit colour at each corner
+    colour: Rgba,       // The surface's own colour
+    emissive: Rgba,     // The light the surface gives off itself
+    specular: f64,      // The brightness of the highlights
+    shininess: f64,     // How small and sharp the highlights are
+}
+
+impl Shade {
+    // The lit colour at a corner of the surface
+    pub(crate) fn corner(&self, i: usize) -> Rgba {
+        self.colours[i]
+    }
+
+    // Returns true if the whole surface is the same colour
+    pub(crate) fn is_even(&self) -> bool {
+        self.colours.windows(2).all(|w| w[0] == w[1])
+    }
+
+    // The lit colour of the surface overall, for drawing it as a single colour
+    pub(crate) fn average(&self) -> Rgba {
+        let n = self.normals.iter().fold([0.0; 3], |sum, n| add(sum, *n));
+        self.light(n)
+    }
+
+    // The lit colour at a point in a triangle of the surface (given as positions in the surface), with the weights of
+    // its corners at that point
+    pub(crate) fn blend(&self, t: [usize; 3], w: [f64; 3]) -> Rgba {
+        if self.per_pixel {
+            let n = (0..3).fold([0.0; 3], |sum, k| add(sum, scaled(self.normals[t[k]], w[k])));
+            return self.light(n);
+        }
+        let c = t.map(|i| self.colours[i]);
+        let mix = |f: fn(&Rgba) -> u8| -> u8 {
+            (0..3).map(|k| f(&c[k]) as f64 * w[k]).sum::<f64>().round().clamp(0.0, 255.0) as u8
+        };
+        Rgba { r: mix(|c| c.r), g: mix(|c| c.g), b: mix(|c| c.b), a: self.colour.a }
+    }
+
+    // Lights the surface's colour for the given normal, which needn't be unit length
+    fn light(&self, n: Vec3) -> Rgba {
+        let n = unit(n);
+        if n == [0.0; 3] {
+            return self.colour;
+        }
+        let l = unit(LIGHT);
+        let diffuse = dot(n, l).max(0.0);
+        let specular = if self.specular > 0.0 && diffuse > 0.0 {
+            let half_way = unit([l[0] + VIEWER[0], l[1] + VIEWER[1], l[2] + VIEWER[2]]);
+            self.specular * dot(n, half_way).max(0.0).powf(self.shininess)
+        } else {
+            0.0
+        };
+        let lit = AMBIENT + (DIFFUSE * diffuse);
+        let channel = |c: u8, glow: u8| -> u8 {
+            ((c as f64 * lit) + glow as f64 + (255.0 * specular)).round().clamp(0.0, 255.0) as u8
+        };
+        Rgba {
+            r: channel(self.colour.r, self.emissive.r),
+            g: channel(self.colour.g, self.emissive.g),
+            b: channel(self.colour.b, self.emissive.b),
+            a: self.colour.a,
+        }
+    }
+}
+
@@ -0,0 +1,124 @@
+// Exports the graph area as an SVG image
+
+use std::fmt::Write;
+
+use crate::painter::{triangle_points, Painter};
+
+// Builds up an SVG document from the drawing operations
+pub(crate) struct SvgPainter {
+    width: f64,
+    height: f64,
+    body: String,
+}
+
+impl SvgPainter {
+    // Starts a new SVG image of the given size, with a white background
+    pub(crate) fn new(width: f64, height: f64) -> SvgPainter {
+        SvgPainter {
+            width,
+            height,
+            body: String::new(),
+        }
+    }
+
+    // Returns the finished SVG document.  Everything drawn is clipped to the image area
+    pub(crate) fn finish(self) -> String {
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = num(self.width),
+            h = num(self.height)
+        );
+        let _ = writeln!(
+            svg,
+            r#"<defs><clipPath id="graph"><rect x="0" y="0" width="{}" height="{}"/></clipPath></defs>"#,
+            num(self.width),
+            num(self.height)
+        );
+        let _ = writeln!(svg, r#"<g clip-path="url(#graph)">"#);
+        let _ = writeln!(svg, r#"<rect x="0" y="0" width="{}" height="{}" fill="white"/>"#, num(self.width), num(self.height));
+        svg.push_str(&self.body);
+        svg.push_str("</g>\n</svg>\n");
+        svg
+    }
+}
+
+impl Painter for SvgPainter {
+    fn polygon(&mut self, points: &[(f64, f64)], fill: &str) {
+        if points.is_empty() {
+            return;
+        }
+        let pts: Vec<String> = points.iter().map(|(x, y)| format!("{},{}", num(*x), num(*y))).collect();
+        let _ = writeln!(self.body, r#"<polygon points="{}" fill="{}"/>"#, pts.join(" "), escape(fill));
+    }
+
+    // The triangles are filled together as a single path, which keeps the edges between them from showing
+    fn surface(&mut self, outline: &[(f64, f64)], triangles: &[[usize; 3]], fill: &str) {
+        let d: Vec<String> = triangle_points(outline, triangles)
+            .iter()
+            .map(|t| {
+                let (a, b, c) = (t[0], t[1], t[2]);
+                format!("M{},{} L{},{} L{},{} Z", num(a.0), num(a.1), num(b.0), num(b.1), num(c.0), num(c.1))
+            })
+            .collect();
+        if !d.is_empty() {
+            let _ = writeln!(self.body, r#"<path d="{}" fill="{}"/>"#, d.join(" "), escape(fill));
+        }
+    }
+
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), colour: &str, width: f64) {
+        let _ = writeln!(
+            self.body,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}"/>"#,
+            num(from.0),
+            num(from.1),
+            num(to.0),
+            num(to.1),
+            escape(colour),
+            num(width)
+        );
+    }
+
+    fn circle(&mut self, centre: (f64, f64), radius: f64, fill: &str) {
+        let _ = writeln!(
+            self.body,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+            num(centre.0),
+            num(centre.1),
+            num(radius),
+            escape(fill)
+        );
+    }
+
+    fn text(&mut self, text: &str, pos: (f64, f64), font: &str, colour: &str) {
+        let _ = writeln!(
+            self.body,
+            r#"<text x="{}" y="{}" style="font: {}" fill="{}">{}</text>"#,
+            num(pos.0),
+            num(pos.1),
+            escape(font),
+            escape(colour),
+            escape(text)
+        );
+    }
+}
+
+// Formats a co-ordinate to two decimal places, dropping any trailing zeros to keep the output small
+fn num(v: f64) -> String {
+    let s = format!("{:.2}", v);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+// Escapes the characters with special meaning in XML
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
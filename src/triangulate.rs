@@ -0,0 +1,307 @@
+// Splits surfaces into triangles, by ear clipping
+//
+// Surfaces can have any number of points, which needn't lie flat, and can be concave.  Each surface is flattened onto
+// the plane it's closest to lying in, then triangles ("ears") are cut off its corners one at a time.  Holes are joined
+// to the outside of the surface by a pair of seam edges first, so the surface becomes a single loop which doubles back
+// on itself along the seam.
+//
+// The triangles are given as positions in the surface's list of points, and wind the same way as the surface.  The
+// surfaces themselves are left as they are, so their edges are still drawn around the original outline.
+
+use crate::mesh::normal;
+use crate::vector::cross_2d;
+use crate::{Point, Surface};
+
+// Corners which turn by less than this (relative to the square of the surface's size) are treated as straight
+const STRAIGHT_EPSILON: f64 = 1e-12;
+
+// Returns the triangles a surface is split into
+pub(crate) fn triangulate(points: &[Point], surf: &[i32]) -> Vec<[usize; 3]> {
+    match surf.len() {
+        0..=2 => Vec::new(),
+        3 => vec![[0, 1, 2]],
+        _ => ear_clip(&flatten(points, surf)),
+    }
+}
+
+// Joins holes onto the outside loop of a surface, returning a single surface.  The holes can wind either way
+pub(crate) fn bridge_holes(points: &[Point], outer: &[i32], holes: &[Surface]) -> Surface {
+    let mut surf = outer.to_vec();
+    if outer.len() < 3 {
+        return surf;
+    }
+
+    // Everything is flattened onto the plane of the outside loop.  The holes are joined on from the rightmost one
+    // inwards, so the seams of earlier holes don't block the later ones
+    let plane = Plane::new(points, outer);
+    let flat = |n: &i32| plane.flatten(&points[*n as usize]);
+    let outer_area = signed_area(&outer.iter().map(flat).collect::<Vec<_>>());
+    let mut holes: Vec<Surface> = holes.iter().filter(|h| h.len() >= 3).cloned().collect();
+    let right = |h: &Surface| h.iter().map(|n| flat(n).0).fold(f64::NEG_INFINITY, f64::max);
+    holes.sort_by(|a, b| right(b).total_cmp(&right(a)));
+    for k in 0..holes.len() {
+        let mut hole = holes[k].clone();
+        if signed_area(&hole.iter().map(flat).collect::<Vec<_>>()) * outer_area > 0.0 {
+            hole.reverse();
+        }
+
+        // The seam runs from the hole's rightmost point to the nearest point it can see without crossing anything
+        let start = (0..hole.len())
+            .max_by(|a, b| flat(&hole[*a]).0.total_cmp(&flat(&hole[*b]).0))
+            .unwrap_or(0);
+        let m = flat(&hole[start]);
+        let mut candidates: Vec<usize> = (0..surf.len()).collect();
+        let near = |n: &usize| distance2(m, flat(&surf[*n]));
+        candidates.sort_by(|a, b| near(a).total_cmp(&near(b)));
+        let loops: Vec<&[i32]> = std::iter::once(surf.as_slice()).chain(holes[k..].iter().map(Vec::as_slice)).collect();
+        let blocked = |v: (f64, f64)| {
+            loops.iter().any(|l| (0..l.len()).any(|i| crosses(m, v, flat(&l[i]), flat(&l[(i + 1) % l.len()]))))
+        };
+        let join = candidates.iter().copied().find(|c| !blocked(flat(&surf[*c]))).unwrap_or(candidates[0]);
+
+        let mut joined = surf[..=join].to_vec();
+        joined.extend(hole[start..].iter().chain(hole[..=start].iter()));
+        joined.extend(surf[join..].iter());
+        surf = joined;
+    }
+    surf
+}
+
+// The plane a surface is flattened onto: one of the XY, YZ, or ZX planes, whichever the surface faces most
+struct Plane {
+    axes: (usize, usize),
+}
+
+impl Plane {
+    fn new(points: &[Point], surf: &[i32]) -> Plane {
+        let n = normal(points, surf);
+        let (x, y, z) = (n[0].abs(), n[1].abs(), n[2].abs());
+        let axes = if z >= x && z >= y {
+            (0, 1)
+        } else if x >= y {
+            (1, 2)
+        } else {
+            (2, 0)
+        };
+        Plane { axes }
+    }
+
+    fn flatten(&self, p: &Point) -> (f64, f64) {
+        let v = [p.x, p.y, p.z];
+        (v[self.axes.0], v[self.axes.1])
+    }
+}
+
+fn flatten(points: &[Point], surf: &[i32]) -> Vec<(f64, f64)> {
+    let plane = Plane::new(points, surf);
+    surf.iter().map(|n| plane.flatten(&points[*n as usize])).collect()
+}
+
+// Cuts triangles off a flat loop until there's nothing left.  If the loop crosses over itself there may be no ear to
+// cut off, in which case the corners are cut off anyway so the whole loop is still covered
+fn ear_clip(flat: &[(f64, f64)]) -> Vec<[usize; 3]> {
+    let mut triangles = Vec::with_capacity(flat.len() - 2);
+    let mut ring: Vec<usize> = (0..flat.len()).collect();
+    let sign = if signed_area(flat) < 0.0 { -1.0 } else { 1.0 };
+    let (lo, hi) = flat.iter().fold(((f64::INFINITY, f64::INFINITY), (f64::NEG_INFINITY, f64::NEG_INFINITY)), |b, p| {
+        ((b.0 .0.min(p.0), b.0 .1.min(p.1)), (b.1 .0.max(p.0), b.1 .1.max(p.1)))
+    });
+    let straight = STRAIGHT_EPSILON * (distance2(lo, hi)).max(f64::MIN_POSITIVE);
+
+    let turn = |ring: &[usize], i: usize| {
+        let len = ring.len();
+        let (a, b, c) = (flat[ring[(i + len - 1) % len]], flat[ring[i]], flat[ring[(i + 1) % len]]);
+        sign * cross_2d(a, b, c)
+    };
+    let mut i = 0;
+    let mut misses = 0;
+    while ring.len() > 3 {
+        let len = ring.len();
+        let (prev, next) = ((i + len - 1) % len, (i + 1) % len);
+        let t = turn(&ring, i);
+        let is_ear = t > straight && {
+            let (a, b, c) = (flat[ring[prev]], flat[ring[i]], flat[ring[next]]);
+            !ring.iter().enumerate().any(|(j, v)| {
+                let p = flat[*v];
+                j != prev && j != i && j != next && p != a && p != b && p != c && inside(a, b, c, p, sign)
+            })
+        };
+
+        // Straight corners are dropped without adding a triangle, as it would have no area.  After going all the way
+        // around without finding an ear, the corner is cut off regardless
+        if is_ear || t.abs() <= straight || misses >= len {
+            if t.abs() > straight || misses >= len {
+                triangles.push([ring[prev], ring[i], ring[next]]);
+            }
+            ring.remove(i);
+            i = i.saturating_sub(1).min(ring.len() - 1);
+            misses = 0;
+        } else {
+            i = next;
+            misses += 1;
+        }
+    }
+    if turn(&ring, 1).abs() > straight {
+        triangles.push([ring[0], ring[1], ring[2]]);
+    }
+    triangles
+}
+
+// Twice the area of a flat loop, positive when it winds anticlockwise
+fn signed_area(flat: &[(f64, f64)]) -> f64 {
+    (0..flat.len())
+        .map(|i| {
+            let (a, b) = (flat[i], flat[(i + 1) % flat.len()]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum()
+}
+
+// Returns true if p is inside (or on an edge of) the triangle a, b, c, which winds the way given by the sign
+fn inside(a: (f64, f64), b: (f64, f64), c: (f64, f64), p: (f64, f64), sign: f64) -> bool {
+    sign * cross_2d(a, b, p) >= 0.0 && sign * cross_2d(b, c, p) >= 0.0 && sign * cross_2d(c, a, p) >= 0.0
+}
+
+// Returns true if the segments a-b and c-d cross, other than by sharing an end
+fn crosses(a: (f64, f64), b: (f64, f64), c: (f64, f64), d: (f64, f64)) -> bool {
+    if a == c || a == d || b == c || b == d {
+        return false;
+    }
+    let (d1, d2) = (cross_2d(a, b, c), cross_2d(a, b, d));
+    let (d3, d4) = (cross_2d(c, d, a), cross_2d(c, d, b));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+fn distance2(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(xy: &[(f64, f64)]) -> Vec<Point> {
+        xy.iter().enumerate().map(|(n, (x, y))| Point { num: n as i32, x: *x, y: *y, z: 0.0 }).collect()
+    }
+
+    fn area(flat: &[(f64, f64)], triangles: &[[usize; 3]]) -> f64 {
+        triangles.iter().map(|t| cross_2d(flat[t[0]], flat[t[1]], flat[t[2]]).abs() / 2.0).sum()
+    }
+
+    // Checks the triangles cover the loop exactly, each winding the same way as it
+    fn check_covers(flat: &[(f64, f64)], triangles: &[[usize; 3]]) {
+        assert!(triangles.iter().flatten().all(|i| *i < flat.len()));
+        assert!((area(flat, triangles) - signed_area(flat).abs() / 2.0).abs() < 1e-9);
+        let sign = signed_area(flat).signum();
+        assert!(triangles.iter().all(|t| cross_2d(flat[t[0]], flat[t[1]], flat[t[2]]) * sign > 0.0));
+    }
+
+    #[test]
+    fn convex() {
+        let flat = [(0.0, 0.0), (2.0, 0.0), (3.0, 1.0), (2.0, 2.0), (0.0, 2.0)];
+        let triangles = ear_clip(&flat);
+        assert_eq!(triangles.len(), 3);
+        check_covers(&flat, &triangles);
+    }
+
+    #[test]
+    fn concave() {
+        // An L shape, wound both ways
+        let mut flat = vec![(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)];
+        let triangles = ear_clip(&flat);
+        assert_eq!(triangles.len(), 4);
+        check_covers(&flat, &triangles);
+        flat.reverse();
+        check_covers(&flat, &ear_clip(&flat));
+
+        // An arrow head, whose reflex corner is inside the triangle of its neighbours
+        let flat = [(0.0, 0.0), (2.0, 1.0), (0.0, 2.0), (0.5, 1.0)];
+        let triangles = ear_clip(&flat);
+        assert_eq!(triangles.len(), 2);
+        check_covers(&flat, &triangles);
+    }
+
+    #[test]
+    fn collinear() {
+        // Points in the middle of straight sides don't make triangles with no area
+        let flat = [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 2.0), (1.0, 2.0), (0.0, 2.0)];
+        let triangles = ear_clip(&flat);
+        check_covers(&flat, &triangles);
+        assert!(triangles.iter().all(|t| cross_2d(flat[t[0]], flat[t[1]], flat[t[2]]).abs() > 0.0));
+
+        // A surface with every point in a line has nothing to fill
+        let flat = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)];
+        assert!(ear_clip(&flat).is_empty());
+    }
+
+    #[test]
+    fn self_crossing() {
+        // A bow tie, which crosses over itself in the middle.  Both halves are still covered
+        let flat = [(0.0, 0.0), (2.0, 2.0), (2.0, 0.0), (0.0, 2.0)];
+        let triangles = ear_clip(&flat);
+        assert_eq!(triangles.len(), 2);
+        assert!(triangles.iter().flatten().all(|i| *i < flat.len()));
+        assert!(area(&flat, &triangles) > 0.0);
+    }
+
+    #[test]
+    fn surfaces_off_the_xy_plane() {
+        // A square standing upright in the YZ plane is flattened onto that plane, rather than to a line
+        let pts: Vec<Point> = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+            .iter()
+            .enumerate()
+            .map(|(n, (y, z))| Point { num: n as i32, x: 3.0, y: *y, z: *z })
+            .collect();
+        assert_eq!(triangulate(&pts, &[0, 1, 2, 3]).len(), 2);
+    }
+
+    #[test]
+    fn hole() {
+        // A square with a square hole, wound the same way as the outside (so it needs turning around)
+        let pts = points(&[
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 4.0),
+            (0.0, 4.0),
+            (1.0, 1.0),
+            (3.0, 1.0),
+            (3.0, 3.0),
+            (1.0, 3.0),
+        ]);
+        let surf = bridge_holes(&pts, &[0, 1, 2, 3], &[vec![4, 5, 6, 7]]);
+        assert_eq!(surf.len(), 4 + 4 + 2);
+        assert!((0..8).all(|n| surf.contains(&n)));
+        let flat: Vec<(f64, f64)> = surf.iter().map(|n| (pts[*n as usize].x, pts[*n as usize].y)).collect();
+        let triangles = triangulate(&pts, &surf);
+        assert!((area(&flat, &triangles) - 12.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn two_holes() {
+        let pts = points(&[
+            (0.0, 0.0),
+            (6.0, 0.0),
+            (6.0, 3.0),
+            (0.0, 3.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (2.0, 2.0),
+            (2.0, 1.0),
+            (4.0, 1.0),
+            (4.0, 2.0),
+            (5.0, 2.0),
+            (5.0, 1.0),
+        ]);
+        let surf = bridge_holes(&pts, &[0, 1, 2, 3], &[vec![4, 5, 6, 7], vec![8, 9, 10, 11]]);
+        assert_eq!(surf.len(), 4 + 2 * (4 + 2));
+        let flat: Vec<(f64, f64)> = surf.iter().map(|n| (pts[*n as usize].x, pts[*n as usize].y)).collect();
+        let triangles = triangulate(&pts, &surf);
+        assert!((area(&flat, &triangles) - 16.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn small_holes_are_ignored() {
+        let pts = points(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0), (1.0, 1.0), (2.0, 2.0)]);
+        assert_eq!(bridge_holes(&pts, &[0, 1, 2, 3], &[vec![4, 5]]), vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,38 @@
+// Vector arithmetic on 3D vectors, shared by the modules working with points and normals
+
+pub(crate) type Vec3 = [f64; 3];
+
+// Twice the signed area of the flat triangle a, b, c, which is positive when they turn anticlockwise
+pub(crate) fn cross_2d(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+pub(crate) fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub(crate) fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn scaled(a: Vec3, k: f64) -> Vec3 {
+    [a[0] * k, a[1] * k, a[2] * k]
+}
+
+pub(crate) fn dot(a: Vec3, b: Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn length(a: Vec3) -> f64 {
+    dot(a, a).sqrt()
+}
+
+// Returns the vector scaled to unit length, or all zeros if it has no length (or an infinite one)
+pub(crate) fn unit(a: Vec3) -> Vec3 {
+    let len = length(a);
+    if len > 0.0 && len.is_finite() {
+        scaled(a, 1.0 / len)
+    } else {
+        [0.0; 3]
+    }
+}
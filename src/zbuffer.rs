@@ -0,0 +1,203 @@
+// A software renderer which keeps the depth of every pixel, so objects hide each other correctly
+//
+// The usual drawing sorts the surfaces by the depth of their middles and draws the furthest first, which goes wrong
+// when surfaces overlap or pass through each other.  This renderer splits the surfaces into triangles and only
+// draws each pixel of them if it's nearer than whatever was drawn there before.  Edges and points are depth tested
+// too, but with some leeway so the ones lying on a surface aren't hidden by it.  See-through pixels are blended over
+// what's there without keeping their depth, so the scene draws them last, furthest away first, after everything solid.
+// Everything which isn't part of the scene (eg the background grid and the axes) is drawn straight into the image,
+// without depth testing.
+//
+// Pixels are only sampled at their centres, so unlike the other renderers the edges of shapes aren't smoothed.
+
+use lazy_static::lazy_static;
+use std::sync::{Arc, Mutex};
+
+use crate::colour::{parse_colour, Rgba};
+use crate::raster::RasterPainter;
+use crate::shading::Shade;
+use crate::vector::cross_2d;
+use crate::{Point, View};
+
+// How far behind a surface (in pixels) edges and points can be while still being drawn over it
+const DEPTH_LEEWAY_PIXELS: f64 = 2.0;
+
+// How the scene is drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RenderMode {
+    Painter, // Surfaces are drawn in order of depth, furthest away first
+    ZBuffer, // Each pixel is depth tested
+}
+
+impl RenderMode {
+    pub(crate) fn from_name(name: &str) -> Option<RenderMode> {
+        match name.to_lowercase().as_str() {
+            "painter" => Some(RenderMode::Painter),
+            "zbuffer" => Some(RenderMode::ZBuffer),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            RenderMode::Painter => "painter",
+            RenderMode::ZBuffer => "zbuffer",
+        }
+    }
+}
+
+lazy_static! {
+    pub(crate) static ref RENDER_MODE: Arc<Mutex<RenderMode>> = Arc::new(Mutex::new(RenderMode::Painter));
+}
+
+// A point projected onto the image.  The depth is larger further away.  The view is orthographic, so depths and
+// colours can be blended straight across triangles on the image, without correcting for perspective
+#[derive(Clone, Copy)]
+pub(crate) struct Vertex {
+    x: f64,
+    y: f64,
+    depth: f64,
+}
+
+impl Vertex {
+    pub(crate) fn project(view: &View, p: &Point) -> Vertex {
+        let (x, y) = view.project(p);
+        Vertex { x, y, depth: p.z }
+    }
+}
+
+pub(crate) struct ZBufferPainter {
+    pub(crate) image: RasterPainter, // The image, which anything not depth tested is drawn straight into
+    depth: Vec<f64>,                 // The depth of the nearest thing drawn at each pixel so far
+    leeway: f64,                     // How far behind surfaces edges and points can be, in world space units
+}
+
+impl ZBufferPainter {
+    pub(crate) fn new(width: usize, height: usize, view: &View) -> ZBufferPainter {
+        ZBufferPainter {
+            image: RasterPainter::new(width, height),
+            depth: vec![f64::INFINITY; width * height],
+            leeway: DEPTH_LEEWAY_PIXELS / view.step,
+        }
+    }
+
+    // Fills a triangle
+    pub(crate) fn triangle(&mut self, v: [Vertex; 3], fill: &str) {
+        if let Some(c) = parse_colour(fill) {
+            self.fill(v, 0.0, |_| c);
+        }
+    }
+
+    // Fills a triangle of a surface (given as positions in the surface) with the colours of its lighting
+    pub(crate) fn shaded_triangle(&mut self, v: [Vertex; 3], t: [usize; 3], shade: &Shade) {
+        self.fill(v, 0.0, |w| shade.blend(t, w));
+    }
+
+    // Draws a straight line, as a long thin rectangle
+    pub(crate) fn line(&mut self, from: Vertex, to: Vertex, colour: &str, width: f64) {
+        let c = match parse_colour(colour) {
+            Some(c) => c,
+            None => return,
+        };
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len == 0.0 {
+            return;
+        }
+        let half = width.max(1.0) / 2.0;
+        let (nx, ny) = (-dy / len * half, dx / len * half);
+        let offset = |v: Vertex, k: f64| Vertex { x: v.x + (nx * k), y: v.y + (ny * k), ..v };
+        self.fill([offset(from, 1.0), offset(to, 1.0), offset(to, -1.0)], self.leeway, |_| c);
+        self.fill([offset(from, 1.0), offset(to, -1.0), offset(from, -1.0)], self.leeway, |_| c);
+    }
+
+    // Fills a circle facing the viewer
+    pub(crate) fn circle(&mut self, centre: Vertex, radius: f64, fill: &str) {
+        let c = match parse_colour(fill) {
+            Some(c) => c,
+            None => return,
+        };
+        let r = radius.max(0.5);
+        let (x0, x1) = self.columns(centre.x - r, centre.x + r);
+        let (y0, y1) = self.rows(centre.y - r, centre.y + r);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (px, py) = (x as f64 + 0.5 - centre.x, y as f64 + 0.5 - centre.y);
+                if (px * px) + (py * py) <= r * r && self.visible(x, y, centre.depth, self.leeway) {
+                    self.plot(x, y, centre.depth, c);
+                }
+            }
+        }
+    }
+
+    // Fills the pixels whose centres are inside a triangle, with either winding.  Pixels whose centres are exactly on
+    // an edge are only filled for the triangle's top and left edges, so pixels along an edge shared by two triangles
+    // are filled once rather than being blended twice.  The depth of each pixel is blended from the depths of the
+    // corners by their weights there, and its colour comes from the same weights
+    fn fill(&mut self, v: [Vertex; 3], leeway: f64, colour: impl Fn([f64; 3]) -> Rgba) {
+        let xy = v.map(|p| (p.x, p.y));
+        let area = cross_2d(xy[0], xy[1], xy[2]);
+        if area == 0.0 || !area.is_finite() {
+            return;
+        }
+        let (x0, x1) = self.columns(v[0].x.min(v[1].x).min(v[2].x), v[0].x.max(v[1].x).max(v[2].x));
+        let (y0, y1) = self.rows(v[0].y.min(v[1].y).min(v[2].y), v[0].y.max(v[1].y).max(v[2].y));
+
+        // An edge is a left edge if the weight of the corner opposite it grows to the right, and a top edge if it's
+        // level and the weight grows downwards
+        let owns_edge = [(1, 2), (2, 0), (0, 1)].map(|(a, b): (usize, usize)| {
+            let (right, down) = ((v[a].y - v[b].y) / area, (v[b].x - v[a].x) / area);
+            right > 0.0 || (right == 0.0 && down > 0.0)
+        });
+        for y in y0..y1 {
+            let py = y as f64 + 0.5;
+            for x in x0..x1 {
+                let px = x as f64 + 0.5;
+                // The weight of each corner is the area of the triangle the pixel makes with the other two corners,
+                // as a part of the whole triangle's area
+                let p = (px, py);
+                let b = [cross_2d(xy[1], xy[2], p), cross_2d(xy[2], xy[0], p), cross_2d(xy[0], xy[1], p)];
+                let b = b.map(|e| e / area);
+                if (0..3).any(|k| b[k] < 0.0 || (b[k] == 0.0 && !owns_edge[k])) {
+                    continue;
+                }
+                let depth = (0..3).map(|k| b[k] * v[k].depth).sum::<f64>();
+                if self.visible(x, y, depth, leeway) {
+                    self.plot(x, y, depth, colour(b));
+                }
+            }
+        }
+    }
+
+    // Returns true if a pixel is no further away than the leeway behind what's already there
+    fn visible(&self, x: usize, y: usize, depth: f64, leeway: f64) -> bool {
+        depth - leeway <= self.depth[(y * self.image.width) + x]
+    }
+
+    // Draws a pixel.  Only solid pixels keep their depth, so things behind see-through ones can still be drawn
+    fn plot(&mut self, x: usize, y: usize, depth: f64, c: Rgba) {
+        if c.a >= 1.0 {
+            let i = (y * self.image.width) + x;
+            self.depth[i] = self.depth[i].min(depth);
+        }
+        self.image.blend(x, y, c, 1.0);
+    }
+
+    // The range of pixel columns whose centres could be between two x positions
+    fn columns(&self, lo: f64, hi: f64) -> (usize, usize) {
+        pixel_range(lo, hi, self.image.width)
+    }
+
+    fn rows(&self, lo: f64, hi: f64) -> (usize, usize) {
+        pixel_range(lo, hi, self.image.height)
+    }
+}
+
+fn pixel_range(lo: f64, hi: f64, size: usize) -> (usize, usize) {
+    if !(lo.is_finite() && hi.is_finite()) {
+        return (0, 0);
+    }
+    let first = (lo - 0.5).ceil().clamp(0.0, size as f64) as usize;
+    let last = ((hi - 0.5).floor() + 1.0).clamp(0.0, size as f64) as usize;
+    (first, last.max(first))
+}